@@ -0,0 +1,217 @@
+// Frame time benchmark, run with `cargo bench`. Models passed as arguments
+// (`cargo bench -- path/to/model.gltf`) are rendered in addition to the defaults.
+// The coverage group compares testing the pixels of the same triangles one at a time
+// against testing them in 2x2 quads, which is what the rasterizer does.
+use glam::Vec2;
+use rusterizer::camera::Camera;
+use rusterizer::edge::TriangleEdges;
+use rusterizer::light::Light;
+use rusterizer::raster::{RenderSettings, Screen};
+use rusterizer::shader::{LambertShader, SurfaceVertexShader};
+use rusterizer::transform::Transform;
+use rusterizer::utils::geometry::Mesh;
+use rusterizer::utils::{load_gltf, map_to_range};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+const WIDTH: usize = 512;
+const HEIGHT: usize = 512;
+const FRAMES: u32 = 30;
+const COVERAGE_PASSES: u32 = 20;
+
+const MODELS: [&str; 1] = ["assets/gltf/teapot.gltf"];
+
+// these are not checked in, they are picked up when present
+const OPTIONAL_MODELS: [&str; 2] = [
+    "assets/gltf/DamagedHelmet.gltf",
+    "assets/gltf/DamagedHelmet.glb",
+];
+
+fn camera() -> Camera {
+    Camera {
+        aspect_ratio: WIDTH as f32 / HEIGHT as f32,
+        transform: Transform::from_translation(glam::vec3(0.0, 0.0, 4.0)),
+        ..Default::default()
+    }
+}
+
+fn frame_time(mesh: &Mesh, threads: usize) -> Duration {
+    let mut screen = Screen::create(WIDTH, HEIGHT);
+    screen.settings = RenderSettings {
+        threads,
+        ..Default::default()
+    };
+    let camera = camera();
+
+    let lights = [Light::default()];
+
+    let start = Instant::now();
+    for frame in 0..FRAMES {
+        screen.clear();
+        let model =
+            Transform::from_rotation(glam::Quat::from_rotation_y(frame as f32 * 0.1)).get_local();
+        let view_projection = camera.projection() * camera.view();
+        screen.raster_mesh(
+            mesh,
+            &SurfaceVertexShader::new(&view_projection, &model),
+            &LambertShader::new(None, &lights),
+        );
+        screen.present();
+    }
+    start.elapsed() / FRAMES
+}
+
+// edge functions of a triangle and its pixel bounds: left, right, top, bottom
+type Projected = (TriangleEdges, [usize; 4]);
+
+// edge functions and pixel bounds of the triangles in front of the camera, as in the
+// first frame of frame_time
+fn project(mesh: &Mesh) -> Vec<Projected> {
+    let camera = camera();
+    let transform = camera.projection() * camera.view();
+    let size = Vec2::new(WIDTH as f32, HEIGHT as f32);
+    let to_screen = |index: u32| {
+        let clip = transform * mesh.vertices[index as usize].position;
+        let ndc = clip.truncate().truncate() / clip.w;
+        (clip.w > 0.0).then(|| {
+            Vec2::new(
+                map_to_range(ndc.x, -1.0, 1.0, 0.0, size.x),
+                map_to_range(-ndc.y, -1.0, 1.0, 0.0, size.y),
+            )
+        })
+    };
+    let mut triangles = Vec::new();
+    for triangle in mesh.get_triangles() {
+        let [a, b, c] = triangle.to_array().map(to_screen);
+        let (Some(a), Some(b), Some(c)) = (a, b, c) else {
+            continue;
+        };
+        let screen = [a, b, c];
+        let min = screen[0].min(screen[1]).min(screen[2]).max(Vec2::ZERO);
+        let max = screen[0].max(screen[1]).max(screen[2]).min(size - 1.0);
+        if min.cmpgt(max).any() {
+            continue;
+        }
+        if let Some(edges) = TriangleEdges::new(&screen) {
+            let bounds = [
+                min.x as usize,
+                max.x as usize,
+                min.y as usize,
+                max.y as usize,
+            ];
+            triangles.push((edges, bounds));
+        }
+    }
+    triangles
+}
+
+// covered pixels, testing every pixel of the bounds on its own
+fn pixel_coverage(triangles: &[Projected]) -> u64 {
+    let mut covered = 0;
+    for (edges, [left, right, top, bottom]) in triangles {
+        for y in *top..=*bottom {
+            let mut weights = edges.evaluate(*left, y);
+            for _ in *left..=*right {
+                covered += edges.covers(weights) as u64;
+                weights += edges.step_x;
+            }
+        }
+    }
+    covered
+}
+
+// covered pixels, testing the bounds in 2x2 quads like the rasterizer, lanes as in QUAD_LANES
+fn quad_coverage(triangles: &[Projected]) -> u64 {
+    let mut covered = 0;
+    for (edges, [left, right, top, bottom]) in triangles {
+        let (quad_left, quad_top) = (left & !1, top & !1);
+        let mut row_weights = edges.evaluate_quad(quad_left, quad_top);
+        for y in (quad_top..=*bottom).step_by(2) {
+            let mut weights = row_weights;
+            row_weights += edges.quad_step_y;
+            for x in (quad_left..=*right).step_by(2) {
+                let mut mask = edges.quad_coverage(&weights);
+                weights += edges.quad_step_x;
+                if x < *left {
+                    mask &= !0b0101;
+                }
+                if x + 1 > *right {
+                    mask &= !0b1010;
+                }
+                if y < *top {
+                    mask &= !0b0011;
+                }
+                if y + 1 > *bottom {
+                    mask &= !0b1100;
+                }
+                covered += mask.count_ones() as u64;
+            }
+        }
+    }
+    covered
+}
+
+fn coverage_time(triangles: &[Projected], coverage: fn(&[Projected]) -> u64) -> (Duration, u64) {
+    let covered = coverage(triangles);
+    let start = Instant::now();
+    for _ in 0..COVERAGE_PASSES {
+        std::hint::black_box(coverage(std::hint::black_box(triangles)));
+    }
+    (start.elapsed() / COVERAGE_PASSES, covered)
+}
+
+fn main() {
+    let mut scenes = Vec::new();
+
+    let arguments = std::env::args()
+        .skip(1)
+        .filter(|arg| !arg.starts_with("--"));
+    let models = MODELS
+        .iter()
+        .map(PathBuf::from)
+        .chain(
+            OPTIONAL_MODELS
+                .iter()
+                .map(PathBuf::from)
+                .filter(|path| path.exists()),
+        )
+        .chain(arguments.map(PathBuf::from));
+    for path in models {
+        match load_gltf(&path) {
+            Ok(mesh) => scenes.push((path.display().to_string(), mesh)),
+            Err(e) => eprintln!("skipping {}", e),
+        }
+    }
+
+    let threads = RenderSettings::default().threads;
+    println!("{}x{}, average of {} frames", WIDTH, HEIGHT, FRAMES);
+    for (name, mesh) in &scenes {
+        print!(
+            "{:<40} {:>8} triangles  1 thread {:>9.2?}",
+            name,
+            mesh.get_triangles().len(),
+            frame_time(mesh, 1)
+        );
+        if threads > 1 {
+            print!("  {} threads {:>9.2?}", threads, frame_time(mesh, threads));
+        }
+        println!();
+    }
+
+    println!();
+    println!("coverage, average of {} passes", COVERAGE_PASSES);
+    for (name, mesh) in &scenes {
+        let triangles = project(mesh);
+        let (pixel_time, pixel_covered) = coverage_time(&triangles, pixel_coverage);
+        let (quad_time, quad_covered) = coverage_time(&triangles, quad_coverage);
+        assert_eq!(pixel_covered, quad_covered, "{}", name);
+        println!(
+            "{:<40} {:>8} pixels  per pixel {:>9.2?}  quads {:>9.2?}  {:.2}x",
+            name,
+            pixel_covered,
+            pixel_time,
+            quad_time,
+            pixel_time.as_secs_f64() / quad_time.as_secs_f64()
+        );
+    }
+}
@@ -0,0 +1,84 @@
+use glam::{Vec3, Vec4, Vec4Swizzles};
+
+// How the output of a fragment shader is combined with the color already in the hdr
+// buffer. Colors are linear, alpha is the fourth component.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum BlendMode {
+    // overwrites the pixel, alpha is ignored
+    #[default]
+    Opaque,
+    // source over destination with straight alpha
+    Alpha,
+    // source over destination with the color already multiplied by alpha
+    Premultiplied,
+    // adds the color scaled by alpha, for glows and particles. Keeps the alpha of the
+    // destination, like Multiply.
+    Additive,
+    // darkens the destination by the color, alpha fades towards no change. For tinted
+    // glass and decals.
+    Multiply,
+    // accumulated into the order independent transparency buffer of the screen and
+    // composited by `Screen::resolve_transparency`, so the draw order doesn't matter
+    WeightedBlended,
+}
+
+impl BlendMode {
+    pub fn blend(self, source: Vec4, destination: Vec4) -> Vec4 {
+        let (color, alpha) = (source.xyz(), source.w.clamp(0.0, 1.0));
+        let (background, coverage) = (destination.xyz(), destination.w);
+        // the source covers its alpha of the destination
+        let over = alpha + coverage * (1.0 - alpha);
+        match self {
+            Self::Opaque => source,
+            Self::Alpha => (color * alpha + background * (1.0 - alpha)).extend(over),
+            Self::Premultiplied => (color + background * (1.0 - alpha)).extend(over),
+            Self::Additive => (color * alpha + background).extend(coverage),
+            Self::Multiply => (background * Vec3::ONE.lerp(color, alpha)).extend(coverage),
+            // the color buffer isn't touched until the resolve
+            Self::WeightedBlended => destination,
+        }
+    }
+}
+
+// Weighted blended order independent transparency, McGuire and Bavoil 2013. Every
+// transparent fragment adds its weighted color, the revealage is the fraction of the
+// background still visible through all of them.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct OitTexel {
+    // premultiplied color and alpha, scaled by the weight
+    pub accumulation: Vec4,
+    pub revealage: f32,
+}
+
+impl Default for OitTexel {
+    fn default() -> Self {
+        Self {
+            accumulation: Vec4::ZERO,
+            revealage: 1.0,
+        }
+    }
+}
+
+impl OitTexel {
+    // view_depth is the linear distance along the view direction, the w of the clip
+    // position, so the weight doesn't depend on the projection or the depth direction.
+    // Closer fragments get a higher weight, equation 9 of McGuire and Bavoil.
+    pub fn add(&mut self, source: Vec4, view_depth: f32) {
+        let alpha = source.w.clamp(0.0, 1.0);
+        let z = view_depth.abs();
+        let falloff = 10.0 / (1e-5 + (z / 5.0).powi(2) + (z / 200.0).powi(6));
+        let weight = (alpha * falloff).clamp(1e-2, 3e3);
+        self.accumulation += (source.xyz() * alpha).extend(alpha) * weight;
+        self.revealage *= 1.0 - alpha;
+    }
+
+    // composites the average transparent color over the destination
+    pub fn resolve(&self, destination: Vec4) -> Vec4 {
+        if self.revealage >= 1.0 {
+            return destination;
+        }
+        let average = self.accumulation.xyz() / self.accumulation.w.max(1e-5);
+        let color = average * (1.0 - self.revealage) + destination.xyz() * self.revealage;
+        color.extend(1.0 - (1.0 - destination.w) * self.revealage)
+    }
+}
@@ -8,6 +8,9 @@ pub struct Camera {
     pub aspect_ratio: f32,
     pub transform: Transform,
     pub speed: f32,
+    // maps the near plane to depth 1 and the far plane to 0, which spreads the float
+    // precision more evenly. Needs `DrawState::reversed_z` and a depth clear value of 0.
+    pub reversed_z: bool,
 }
 
 impl Default for Camera {
@@ -19,13 +22,18 @@ impl Default for Camera {
             aspect_ratio: 1.0,
             transform: Transform::IDENTITY,
             speed: 1.0,
+            reversed_z: false,
         }
     }
 }
 
 impl Camera {
     pub fn projection(&self) -> Mat4 {
-        Mat4::perspective_rh(self.fov, self.aspect_ratio, self.near_plane, self.far_plane)
+        if self.reversed_z {
+            Mat4::perspective_rh(self.fov, self.aspect_ratio, self.far_plane, self.near_plane)
+        } else {
+            Mat4::perspective_rh(self.fov, self.aspect_ratio, self.near_plane, self.far_plane)
+        }
     }
 
     pub fn view(&self) -> Mat4 {
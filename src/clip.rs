@@ -0,0 +1,113 @@
+use crate::utils::{geometry::*, *};
+use glam::Vec4;
+
+// a triangle clipped against n planes gains at most one vertex per plane
+pub const MAX_CLIPPED_VERTICES: usize = 3 + CLIP_PLANES.len();
+
+// frustum planes in clip space, a position p is inside when dot(plane, p) >= 0
+// depth follows the glam/wgpu convention of 0 <= z <= w
+pub const CLIP_PLANES: [Vec4; 6] = [
+    Vec4::new(1.0, 0.0, 0.0, 1.0),  // left: -w <= x
+    Vec4::new(-1.0, 0.0, 0.0, 1.0), // right: x <= w
+    Vec4::new(0.0, 1.0, 0.0, 1.0),  // bottom: -w <= y
+    Vec4::new(0.0, -1.0, 0.0, 1.0), // top: y <= w
+    Vec4::new(0.0, 0.0, 1.0, 0.0),  // near: 0 <= z
+    Vec4::new(0.0, 0.0, -1.0, 1.0), // far: z <= w
+];
+
+// bit i is set when the position is outside of CLIP_PLANES[i]
+pub fn outcode(position: Vec4) -> u8 {
+    CLIP_PLANES.iter().enumerate().fold(0, |code, (i, plane)| {
+        if plane.dot(position) < 0.0 {
+            code | (1 << i)
+        } else {
+            code
+        }
+    })
+}
+
+// convex polygon produced by clipping a triangle, stored inline to avoid allocations
+#[derive(Debug, Copy, Clone)]
+pub struct ClippedPolygon<V = Vertex> {
+    vertices: [V; MAX_CLIPPED_VERTICES],
+    len: usize,
+    clipped: bool,
+}
+
+impl<V: Varyings> ClippedPolygon<V> {
+    pub fn from_triangle(triangle: &Triangle<V>) -> Self {
+        let mut vertices = [triangle.v0; MAX_CLIPPED_VERTICES];
+        vertices[1] = triangle.v1;
+        vertices[2] = triangle.v2;
+        Self {
+            vertices,
+            len: 3,
+            clipped: false,
+        }
+    }
+
+    pub fn vertices(&self) -> &[V] {
+        &self.vertices[..self.len]
+    }
+
+    // true when at least one plane cut away part of the original triangle
+    pub fn was_clipped(&self) -> bool {
+        self.clipped
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len < 3
+    }
+
+    fn push(&mut self, vertex: V) {
+        self.vertices[self.len] = vertex;
+        self.len += 1;
+    }
+
+    // Sutherland-Hodgman step: keeps the part of the polygon in front of the plane
+    pub fn clip_against_plane(&self, plane: Vec4) -> Self {
+        let mut result = *self;
+        result.len = 0;
+
+        let vertices = self.vertices();
+        for (i, current) in vertices.iter().enumerate() {
+            let next = vertices[(i + 1) % vertices.len()];
+            let current_distance = plane.dot(current.position());
+            let next_distance = plane.dot(next.position());
+
+            if current_distance >= 0.0 {
+                result.push(*current);
+            } else {
+                result.clipped = true;
+            }
+            if (current_distance >= 0.0) != (next_distance >= 0.0) {
+                // interpolate all attributes to the intersection with the plane
+                let alpha = current_distance / (current_distance - next_distance);
+                result.push(lerp(*current, next, alpha));
+            }
+        }
+
+        result
+    }
+
+    // clips against every plane in the outcode, planes the polygon doesn't cross are skipped
+    pub fn clip(&self, outcode: u8) -> Self {
+        let mut result = *self;
+        for (i, plane) in CLIP_PLANES.iter().enumerate() {
+            if outcode & (1 << i) != 0 {
+                result = result.clip_against_plane(*plane);
+                if result.is_empty() {
+                    break;
+                }
+            }
+        }
+        result
+    }
+
+    // fan triangulation around the first vertex, keeps the winding of the input
+    pub fn triangles(&self) -> impl Iterator<Item = Triangle<V>> + '_ {
+        let vertices = self.vertices();
+        (1..self.len.saturating_sub(1))
+            .map(move |i| Triangle::create(vertices[0], vertices[i], vertices[i + 1]))
+    }
+}
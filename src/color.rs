@@ -0,0 +1,83 @@
+use crate::utils::from_rgb_u32;
+use glam::Vec3;
+
+// Maps the unbounded linear colors of the hdr buffer into [0, 1]
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum ToneMapper {
+    // everything above 1 is cut off
+    #[default]
+    Clamp,
+    // x / (1 + x), never quite reaches white
+    Reinhard,
+    // Narkowicz's fit of the ACES filmic curve, with a slight toe and shoulder
+    Aces,
+    // 1 - e^-x, approaches white faster than Reinhard
+    Exposure,
+}
+
+impl ToneMapper {
+    pub fn apply(self, color: Vec3) -> Vec3 {
+        let color = color.max(Vec3::ZERO);
+        let mapped = match self {
+            Self::Clamp => color,
+            Self::Reinhard => color / (color + 1.0),
+            Self::Aces => (color * (color * 2.51 + 0.03)) / (color * (color * 2.43 + 0.59) + 0.14),
+            Self::Exposure => Vec3::ONE - (-color).exp(),
+        };
+        mapped.clamp(Vec3::ZERO, Vec3::ONE)
+    }
+}
+
+// How the hdr buffer of the screen is turned into the 8-bit presentation buffer
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct OutputSettings {
+    // scales the color before tone mapping
+    pub exposure: f32,
+    pub tone_mapper: ToneMapper,
+    // encodes the tone mapped color with the sRGB transfer function, which is what
+    // displays and image files expect, on by default. Off writes the linear values.
+    pub srgb: bool,
+}
+
+impl Default for OutputSettings {
+    fn default() -> Self {
+        Self {
+            exposure: 1.0,
+            tone_mapper: ToneMapper::Clamp,
+            srgb: true,
+        }
+    }
+}
+
+impl OutputSettings {
+    pub fn present(&self, color: Vec3) -> u32 {
+        let mapped = self.tone_mapper.apply(color * self.exposure);
+        if self.srgb {
+            from_rgb_u32(linear_to_srgb(mapped))
+        } else {
+            from_rgb_u32(mapped)
+        }
+    }
+}
+
+pub fn srgb_to_linear(color: Vec3) -> Vec3 {
+    let decode = |c: f32| {
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    Vec3::new(decode(color.x), decode(color.y), decode(color.z))
+}
+
+pub fn linear_to_srgb(color: Vec3) -> Vec3 {
+    let encode = |c: f32| {
+        if c <= 0.0031308 {
+            c * 12.92
+        } else {
+            1.055 * c.powf(1.0 / 2.4) - 0.055
+        }
+    };
+    Vec3::new(encode(color.x), encode(color.y), encode(color.z))
+}
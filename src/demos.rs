@@ -0,0 +1,983 @@
+use crate::blend::BlendMode;
+use crate::camera::Camera;
+use crate::color::ToneMapper;
+use crate::light::Light;
+use crate::material::{Material, TextureBinding};
+use crate::raster::{CompareFunction, DrawState};
+use crate::scene::Scene;
+use crate::shader::*;
+use crate::stencil::StencilState;
+use crate::texture::*;
+use crate::transform::Transform;
+use crate::utils::geometry::*;
+use crate::Screen;
+use glam::Vec4Swizzles;
+use std::path::Path;
+
+// the photo textured on most of the scenes, it is sRGB encoded
+pub fn bojan_texture() -> Texture {
+    let mut texture = Texture::load(Path::new("assets/bojan.jpg")).unwrap();
+    texture.set_srgb(true);
+    texture
+}
+
+pub fn _test_indices(screen: &mut Screen) {
+    screen._output_index();
+}
+
+pub fn _test_coords(screen: &mut Screen) {
+    screen._output_coords();
+}
+
+pub fn _test_edge_function(screen: &mut Screen) {
+    let v0 = Vertex {
+        position: glam::vec4(100.0, 100.0, 1.0, 1.0),
+        color: glam::vec3(0.0, 1.0, 0.0),
+        normal: glam::vec3(0.0, 0.0, 1.0),
+        uv: glam::vec2(0.0, 0.0),
+        tangent: glam::Vec4::ZERO,
+    };
+
+    let v1 = Vertex {
+        position: glam::vec4(250.0, 400.0, 1.0, 1.0),
+        color: glam::vec3(1.0, 0.0, 0.0),
+        normal: glam::vec3(0.0, 0.0, 1.0),
+        uv: glam::vec2(0.0, 0.0),
+        tangent: glam::Vec4::ZERO,
+    };
+
+    screen._output_edge_function(v0.position.xy(), v1.position.xy());
+}
+
+pub fn _test_triangle1(screen: &mut Screen) {
+    // Nice triangle
+    let v0 = Vertex {
+        position: glam::vec4(100.0, 100.0, 1.0, 1.0),
+        color: glam::vec3(0.0, 1.0, 0.0),
+        normal: glam::vec3(0.0, 0.0, 1.0),
+        uv: glam::vec2(0.0, 0.0),
+        tangent: glam::Vec4::ZERO,
+    };
+
+    let v1 = Vertex {
+        position: glam::vec4(250.0, 400.0, 1.0, 1.0),
+        color: glam::vec3(1.0, 0.0, 0.0),
+        normal: glam::vec3(0.0, 0.0, 1.0),
+        uv: glam::vec2(0.0, 0.0),
+        tangent: glam::Vec4::ZERO,
+    };
+
+    let v2 = Vertex {
+        position: glam::vec4(400.0, 100.0, 1.0, 1.0),
+        color: glam::vec3(0.0, 0.0, 1.0),
+        normal: glam::vec3(0.0, 0.0, 1.0),
+        uv: glam::vec2(0.0, 0.0),
+        tangent: glam::Vec4::ZERO,
+    };
+
+    screen._output_triangle1(v0.position.xy(), v1.position.xy(), v2.position.xy());
+}
+
+pub fn _test_triangle2(screen: &mut Screen) {
+    // Nice triangle
+    let v0 = Vertex {
+        position: glam::vec4(100.0, 100.0, 1.0, 1.0),
+        color: glam::vec3(0.0, 1.0, 0.0),
+        normal: glam::vec3(0.0, 0.0, 1.0),
+        uv: glam::vec2(0.0, 0.0),
+        tangent: glam::Vec4::ZERO,
+    };
+
+    let v1 = Vertex {
+        position: glam::vec4(250.0, 400.0, 1.0, 1.0),
+        color: glam::vec3(1.0, 0.0, 0.0),
+        normal: glam::vec3(0.0, 0.0, 1.0),
+        uv: glam::vec2(0.0, 0.0),
+        tangent: glam::Vec4::ZERO,
+    };
+
+    let v2 = Vertex {
+        position: glam::vec4(400.0, 100.0, 1.0, 1.0),
+        color: glam::vec3(0.0, 0.0, 1.0),
+        normal: glam::vec3(0.0, 0.0, 1.0),
+        uv: glam::vec2(0.0, 0.0),
+        tangent: glam::Vec4::ZERO,
+    };
+
+    screen._output_triangle2(v0.position.xy(), v1.position.xy(), v2.position.xy());
+}
+
+pub fn _test_barycentric(screen: &mut Screen) {
+    // Nice triangle
+    let v0 = Vertex {
+        position: glam::vec4(100.0, 100.0, 1.0, 1.0),
+        color: glam::vec3(1.0, 0.0, 0.0),
+        normal: glam::vec3(0.0, 0.0, 1.0),
+        uv: glam::vec2(0.0, 0.0),
+        tangent: glam::Vec4::ZERO,
+    };
+
+    let v1 = Vertex {
+        position: glam::vec4(250.0, 400.0, 1.0, 1.0),
+        color: glam::vec3(0.0, 1.0, 0.0),
+        normal: glam::vec3(0.0, 0.0, 1.0),
+        uv: glam::vec2(0.0, 0.0),
+        tangent: glam::Vec4::ZERO,
+    };
+
+    let v2 = Vertex {
+        position: glam::vec4(400.0, 100.0, 1.0, 1.0),
+        color: glam::vec3(0.0, 0.0, 1.0),
+        normal: glam::vec3(0.0, 0.0, 1.0),
+        uv: glam::vec2(0.0, 0.0),
+        tangent: glam::Vec4::ZERO,
+    };
+
+    screen._output_barycentric(&v0, &v1, &v2);
+}
+
+pub fn _test_textured_triangle(screen: &mut Screen) {
+    let v0 = Vertex {
+        position: glam::vec4(-1.0, 1.0, 1.0, 1.0),
+        color: glam::vec3(0.0, 1.0, 0.0),
+        normal: glam::vec3(0.0, 0.0, 1.0),
+        uv: glam::vec2(0.0, 0.0),
+        tangent: glam::Vec4::ZERO,
+    };
+
+    let v1 = Vertex {
+        position: glam::vec4(0.0, -1.0, 1.0, 1.0),
+        color: glam::vec3(1.0, 0.0, 0.0),
+        normal: glam::vec3(0.0, 0.0, 1.0),
+        uv: glam::vec2(0.5, 1.0),
+        tangent: glam::Vec4::ZERO,
+    };
+
+    let v2 = Vertex {
+        position: glam::vec4(1.0, 1.0, 1.0, 1.0),
+        color: glam::vec3(0.0, -0.0, 1.0),
+        normal: glam::vec3(0.0, 0.0, 1.0),
+        uv: glam::vec2(1.0, 0.0),
+        tangent: glam::Vec4::ZERO,
+    };
+
+    // Texture
+    let texture = bojan_texture();
+
+    let aspect_ratio: f32 = 1.0;
+
+    let camera = Camera {
+        far_plane: 100.0,
+        aspect_ratio,
+        transform: Transform::from_translation(glam::vec3(0.0, 0.0, 5.0)),
+        ..Default::default()
+    };
+
+    let lights = [Light::default()];
+
+    // view projection
+    let proj = camera.projection();
+    let view = camera.view();
+
+    let triangle = [&v0, &v1, &v2];
+
+    screen.raster_triangle(
+        &triangle,
+        &SurfaceVertexShader::new(&(proj * view), &Transform::IDENTITY.get_local()),
+        &LambertShader::new(Some(&texture), &lights),
+    );
+}
+
+pub fn _test_textured_quad(screen: &mut Screen) {
+    // Quad
+    let v0 = Vertex {
+        position: glam::vec4(-1.0, -1.0, 0.0, 1.0),
+        color: glam::vec3(0.0, 1.0, 1.0),
+        normal: glam::vec3(0.0, 0.0, 1.0),
+        uv: glam::vec2(0.0, 1.0),
+        tangent: glam::Vec4::ZERO,
+    };
+    let v1 = Vertex {
+        position: glam::vec4(-1.0, 1.0, 0.0, 1.0),
+        color: glam::vec3(1.0, 0.0, 0.0),
+        normal: glam::vec3(0.0, 0.0, 1.0),
+        uv: glam::vec2(0.0, 0.0),
+        tangent: glam::Vec4::ZERO,
+    };
+    let v2 = Vertex {
+        position: glam::vec4(1.0, 1.0, 0.0, 1.0),
+        color: glam::vec3(0.0, 1.0, 0.0),
+        normal: glam::vec3(0.0, 0.0, 1.0),
+        uv: glam::vec2(1.0, 0.0),
+        tangent: glam::Vec4::ZERO,
+    };
+    let v3 = Vertex {
+        position: glam::vec4(1.0, -1.0, 0.0, 1.0),
+        color: glam::vec3(0.0, 1.0, 1.0),
+        normal: glam::vec3(0.0, 0.0, 1.0),
+        uv: glam::vec2(1.0, 1.0),
+        tangent: glam::Vec4::ZERO,
+    };
+
+    // Texture
+    let texture = bojan_texture();
+
+    let aspect_ratio: f32 = 1.0;
+
+    let camera = Camera {
+        far_plane: 100.0,
+        aspect_ratio,
+        transform: Transform::from_translation(glam::vec3(0.0, 0.0, 5.0)),
+        ..Default::default()
+    };
+
+    let lights = [Light::default()];
+
+    // view projection
+    let proj = camera.projection();
+    let view = camera.view();
+
+    let triangle0 = [&v0, &v2, &v1];
+    let triangle1 = [&v0, &v3, &v2];
+
+    let vertex_shader = SurfaceVertexShader::new(&(proj * view), &Transform::IDENTITY.get_local());
+    let fragment_shader = LambertShader::new(Some(&texture), &lights);
+
+    screen.raster_triangle(&triangle0, &vertex_shader, &fragment_shader);
+    screen.raster_triangle(&triangle1, &vertex_shader, &fragment_shader);
+}
+
+pub fn _test_camera(screen: &mut Screen, rot: &mut f32) {
+    // Quad
+    let v0 = Vertex {
+        position: glam::vec4(-1.0, -1.0, 0.0, 1.0),
+        color: glam::vec3(0.0, 1.0, 1.0),
+        normal: glam::vec3(0.0, 0.0, 1.0),
+        uv: glam::vec2(0.0, 1.0),
+        tangent: glam::Vec4::ZERO,
+    };
+    let v1 = Vertex {
+        position: glam::vec4(-1.0, 1.0, 0.0, 1.0),
+        color: glam::vec3(1.0, 0.0, 0.0),
+        normal: glam::vec3(0.0, 0.0, 1.0),
+        uv: glam::vec2(0.0, 0.0),
+        tangent: glam::Vec4::ZERO,
+    };
+    let v2 = Vertex {
+        position: glam::vec4(1.0, 1.0, 0.0, 1.0),
+        color: glam::vec3(0.0, 1.0, 0.0),
+        normal: glam::vec3(0.0, 0.0, 1.0),
+        uv: glam::vec2(1.0, 0.0),
+        tangent: glam::Vec4::ZERO,
+    };
+    let v3 = Vertex {
+        position: glam::vec4(1.0, -1.0, 0.0, 1.0),
+        color: glam::vec3(0.0, 1.0, 1.0),
+        normal: glam::vec3(0.0, 0.0, 1.0),
+        uv: glam::vec2(1.0, 1.0),
+        tangent: glam::Vec4::ZERO,
+    };
+
+    // Texture
+    let texture = bojan_texture();
+
+    let aspect_ratio: f32 = 1.0;
+
+    let camera = Camera {
+        far_plane: 100.0,
+        aspect_ratio,
+        transform: Transform::from_translation(glam::vec3(0.0, 0.0, 5.0)),
+        ..Default::default()
+    };
+
+    *rot += 0.05;
+
+    let lights = [Light::default()];
+
+    // view projection
+    let transform =
+        Transform::from_rotation(glam::Quat::from_euler(glam::EulerRot::XYZ, *rot, 0.0, 0.0));
+    let proj = camera.projection();
+    let view = camera.view();
+
+    let triangle0 = [&v0, &v2, &v1];
+    let triangle1 = [&v0, &v3, &v2];
+
+    let vertex_shader = SurfaceVertexShader::new(&(proj * view), &transform.get_local());
+    let fragment_shader = LambertShader::new(Some(&texture), &lights);
+
+    screen.raster_triangle(&triangle0, &vertex_shader, &fragment_shader);
+    screen.raster_triangle(&triangle1, &vertex_shader, &fragment_shader);
+}
+
+pub fn _test_raster_mesh(screen: &mut Screen) {
+    // Quad
+    let v0 = Vertex {
+        position: glam::vec4(-2.0, -2.0, 0.0, 1.0),
+        color: glam::vec3(0.0, 1.0, 1.0),
+        normal: glam::vec3(0.0, 0.0, 1.0),
+        uv: glam::vec2(0.0, 1.0),
+        tangent: glam::Vec4::ZERO,
+    };
+    let v1 = Vertex {
+        position: glam::vec4(-2.0, 2.0, 0.0, 1.0),
+        color: glam::vec3(1.0, 0.0, 0.0),
+        normal: glam::vec3(0.0, 0.0, 1.0),
+        uv: glam::vec2(0.0, 0.0),
+        tangent: glam::Vec4::ZERO,
+    };
+    let v2 = Vertex {
+        position: glam::vec4(2.0, 2.0, 0.0, 1.0),
+        color: glam::vec3(0.0, 1.0, 0.0),
+        normal: glam::vec3(0.0, 0.0, 1.0),
+        uv: glam::vec2(1.0, 0.0),
+        tangent: glam::Vec4::ZERO,
+    };
+    let v3 = Vertex {
+        position: glam::vec4(2.0, -2.0, 0.0, 1.0),
+        color: glam::vec3(0.0, 1.0, 1.0),
+        normal: glam::vec3(0.0, 0.0, 1.0),
+        uv: glam::vec2(1.0, 1.0),
+        tangent: glam::Vec4::ZERO,
+    };
+
+    // Texture
+    let texture = bojan_texture();
+
+    // Camera
+    let aspect_ratio: f32 = 1.0;
+    let camera = Camera {
+        far_plane: 100.0,
+        aspect_ratio,
+        transform: Transform::from_translation(glam::vec3(0.0, 0.0, 5.0)),
+        ..Default::default()
+    };
+
+    let transform =
+        Transform::from_rotation(glam::Quat::from_euler(glam::EulerRot::XYZ, 0.0, 0.0, 0.0));
+
+    // Mesh
+    let triangles = vec![glam::uvec3(2, 1, 0), glam::uvec3(3, 2, 0)];
+    let vertices = vec![v0, v1, v2, v3];
+    let mesh = Mesh::from_vertices(&triangles, &vertices);
+
+    let lights = [Light::default()];
+
+    // view projection
+    let proj = camera.projection();
+    let view = camera.view();
+    let fragment_shader = LambertShader::new(Some(&texture), &lights);
+
+    screen.raster_mesh(
+        &mesh,
+        &SurfaceVertexShader::new(&(proj * view), &transform.get_local()),
+        &fragment_shader,
+    );
+}
+
+pub fn _test_textured_cube(screen: &mut Screen, rot: &mut f32) {
+    // Quad
+    let v0 = Vertex {
+        position: glam::vec4(-1.0, -1.0, 1.0, 1.0),
+        color: glam::vec3(0.0, 1.0, 1.0),
+        normal: glam::vec3(0.0, 0.0, 1.0),
+        uv: glam::vec2(0.0, 1.0),
+        tangent: glam::Vec4::ZERO,
+    };
+    let v1 = Vertex {
+        position: glam::vec4(-1.0, 1.0, 1.0, 1.0),
+        color: glam::vec3(1.0, 0.0, 0.0),
+        normal: glam::vec3(0.0, 0.0, 1.0),
+        uv: glam::vec2(0.0, 0.0),
+        tangent: glam::Vec4::ZERO,
+    };
+    let v2 = Vertex {
+        position: glam::vec4(1.0, 1.0, 1.0, 1.0),
+        color: glam::vec3(0.0, 1.0, 0.0),
+        normal: glam::vec3(0.0, 0.0, 1.0),
+        uv: glam::vec2(1.0, 0.0),
+        tangent: glam::Vec4::ZERO,
+    };
+    let v3 = Vertex {
+        position: glam::vec4(1.0, -1.0, 1.0, 1.0),
+        color: glam::vec3(0.0, 1.0, 1.0),
+        normal: glam::vec3(0.0, 0.0, 1.0),
+        uv: glam::vec2(1.0, 1.0),
+        tangent: glam::Vec4::ZERO,
+    };
+
+    // Camera
+    let aspect_ratio: f32 = 1.0;
+    let camera = Camera {
+        far_plane: 100.0,
+        aspect_ratio,
+        transform: Transform::from_translation(glam::vec3(0.0, 0.0, 5.0)),
+        ..Default::default()
+    };
+
+    // Texture
+    let texture = bojan_texture();
+
+    // Mesh
+    let triangles = vec![glam::uvec3(2, 1, 0), glam::uvec3(3, 2, 0)];
+    let vertices = vec![v0, v1, v2, v3];
+    let mesh = Mesh::from_vertices(&triangles, &vertices);
+
+    // Transforms
+    let transform0 = Transform::IDENTITY;
+
+    // Rotate
+    *rot += 0.05;
+
+    //-z
+    let transform1 = Transform::from_rotation(glam::Quat::from_euler(
+        glam::EulerRot::XYZ,
+        -std::f32::consts::PI,
+        0.0,
+        0.0,
+    ));
+    //+y
+    let transform2 = Transform::from_rotation(glam::Quat::from_euler(
+        glam::EulerRot::XYZ,
+        std::f32::consts::FRAC_PI_2,
+        0.0,
+        0.0,
+    ));
+    //-y
+    let transform3 = Transform::from_rotation(glam::Quat::from_euler(
+        glam::EulerRot::XYZ,
+        -std::f32::consts::FRAC_PI_2,
+        0.0,
+        0.0,
+    ));
+    //+x
+    let transform4 = Transform::from_rotation(glam::Quat::from_euler(
+        glam::EulerRot::XYZ,
+        0.0,
+        -std::f32::consts::FRAC_PI_2,
+        0.0,
+    ));
+    //-x
+    let transform5 = Transform::from_rotation(glam::Quat::from_euler(
+        glam::EulerRot::XYZ,
+        0.0,
+        std::f32::consts::FRAC_PI_2,
+        0.0,
+    ));
+
+    let parent_local =
+        Transform::from_rotation(glam::Quat::from_euler(glam::EulerRot::XYZ, *rot, *rot, 0.0))
+            .get_local();
+
+    let lights = [Light::default()];
+
+    // view projection
+    let proj = camera.projection();
+    let view = camera.view();
+    let fragment_shader = LambertShader::new(Some(&texture), &lights);
+
+    screen.raster_mesh(
+        &mesh,
+        &SurfaceVertexShader::new(&(proj * view), &(parent_local * transform0.get_local())),
+        &fragment_shader,
+    );
+    screen.raster_mesh(
+        &mesh,
+        &SurfaceVertexShader::new(&(proj * view), &(parent_local * transform1.get_local())),
+        &fragment_shader,
+    );
+    screen.raster_mesh(
+        &mesh,
+        &SurfaceVertexShader::new(&(proj * view), &(parent_local * transform2.get_local())),
+        &fragment_shader,
+    );
+    screen.raster_mesh(
+        &mesh,
+        &SurfaceVertexShader::new(&(proj * view), &(parent_local * transform3.get_local())),
+        &fragment_shader,
+    );
+
+    screen.raster_mesh(
+        &mesh,
+        &SurfaceVertexShader::new(&(proj * view), &(parent_local * transform4.get_local())),
+        &fragment_shader,
+    );
+    screen.raster_mesh(
+        &mesh,
+        &SurfaceVertexShader::new(&(proj * view), &(parent_local * transform5.get_local())),
+        &fragment_shader,
+    );
+}
+
+pub fn _test_camera_inputs(screen: &mut Screen, rot: &mut f32, camera: &Camera) {
+    // Quad
+    let v0 = Vertex {
+        position: glam::vec4(-2.0, -2.0, 0.0, 1.0),
+        color: glam::vec3(0.0, 1.0, 1.0),
+        normal: glam::vec3(0.0, 0.0, 1.0),
+        uv: glam::vec2(0.0, 1.0),
+        tangent: glam::Vec4::ZERO,
+    };
+    let v1 = Vertex {
+        position: glam::vec4(-2.0, 2.0, 0.0, 1.0),
+        color: glam::vec3(1.0, 0.0, 0.0),
+        normal: glam::vec3(0.0, 0.0, 1.0),
+        uv: glam::vec2(0.0, 0.0),
+        tangent: glam::Vec4::ZERO,
+    };
+    let v2 = Vertex {
+        position: glam::vec4(2.0, 2.0, 0.0, 1.0),
+        color: glam::vec3(0.0, 1.0, 0.0),
+        normal: glam::vec3(0.0, 0.0, 1.0),
+        uv: glam::vec2(1.0, 0.0),
+        tangent: glam::Vec4::ZERO,
+    };
+    let v3 = Vertex {
+        position: glam::vec4(2.0, -2.0, 0.0, 1.0),
+        color: glam::vec3(0.0, 1.0, 1.0),
+        normal: glam::vec3(0.0, 0.0, 1.0),
+        uv: glam::vec2(1.0, 1.0),
+        tangent: glam::Vec4::ZERO,
+    };
+
+    // Texture
+    let texture = bojan_texture();
+
+    *rot += 0.05;
+
+    let transform =
+        Transform::from_rotation(glam::Quat::from_euler(glam::EulerRot::XYZ, *rot, 0.0, 0.0));
+
+    // Mesh
+    let triangles = vec![glam::uvec3(2, 1, 0), glam::uvec3(3, 2, 0)];
+    let vertices = vec![v0, v1, v2, v3];
+    let mesh = Mesh::from_vertices(&triangles, &vertices);
+
+    let lights = [Light::default()];
+
+    // view projection
+    let projection = camera.projection();
+    let view = camera.view();
+
+    screen.raster_mesh(
+        &mesh,
+        &SurfaceVertexShader::new(&(projection * view), &transform.get_local()),
+        &LambertShader::new(Some(&texture), &lights),
+    );
+}
+
+pub fn _test_gltf(screen: &mut Screen, rot: &mut f32, camera: &Camera, mesh: &Mesh) {
+    *rot += 0.05;
+    let transform =
+        Transform::from_rotation(glam::Quat::from_euler(glam::EulerRot::XYZ, 0.0, *rot, 0.0));
+    let lights = [Light::default()];
+    let projection = camera.projection();
+    let view = camera.view();
+
+    screen.raster_mesh(
+        mesh,
+        &SurfaceVertexShader::new(&(projection * view), &transform.get_local()),
+        &LambertShader::new(None, &lights),
+    );
+}
+
+pub fn _test_gltf_textured(screen: &mut Screen, rot: &mut f32, camera: &Camera, scene: &Scene) {
+    *rot += 0.05;
+    let transform =
+        Transform::from_rotation(glam::Quat::from_euler(glam::EulerRot::XYZ, *rot, *rot, 0.0));
+    let lights = [Light::default()];
+    let projection = camera.projection();
+    let view = camera.view();
+
+    for (mesh, world) in scene.instances() {
+        let model = transform.get_local() * world;
+        let vertex_shader = SurfaceVertexShader::new(&(projection * view), &model);
+        screen.raster_submeshes(mesh, &vertex_shader, |submesh| {
+            let material = scene.material(submesh.material);
+            let mut fragment_shader = LambertShader::new(None, &lights);
+            if let Some((texture, sampler)) = material.and_then(|m| scene.base_color_texture(m)) {
+                fragment_shader.texture = Some(texture);
+                fragment_shader.sampler = sampler;
+            }
+            fragment_shader
+        });
+    }
+}
+
+pub fn _test_gltf_pbr(screen: &mut Screen, rot: &mut f32, camera: &Camera, scene: &Scene) {
+    *rot += 0.05;
+    let transform =
+        Transform::from_rotation(glam::Quat::from_euler(glam::EulerRot::XYZ, *rot, *rot, 0.0));
+    let view_projection = camera.projection() * camera.view();
+    let default_material = Material::default();
+    let mut lights = scene.world_lights();
+    if lights.is_empty() {
+        lights.push(Light::default());
+    }
+
+    for (mesh, world) in scene.instances() {
+        let model = transform.get_local() * world;
+        let vertex_shader = SurfaceVertexShader::new(&view_projection, &model);
+        screen.raster_submeshes(mesh, &vertex_shader, |submesh| {
+            let material = scene
+                .material(submesh.material)
+                .unwrap_or(&default_material);
+            PbrShader::new(
+                material,
+                &scene.textures,
+                &lights,
+                camera.transform.translation,
+            )
+        });
+    }
+}
+
+// sphere on a floor, lit by a red point light on the left and a blue spot light
+// shining down on the right
+pub fn _test_lights(screen: &mut Screen, camera: &Camera) {
+    let floor = Mesh::quad(-glam::Vec3::Y, glam::Vec3::Z * 4.0, glam::Vec3::X * 4.0);
+    let sphere = Mesh::uv_sphere(1.0, 48, 24);
+    let material = Material {
+        metallic_factor: 0.0,
+        roughness_factor: 0.6,
+        ..Default::default()
+    };
+    let lights = [
+        Light::Point {
+            position: glam::vec3(-2.0, 0.5, 1.0),
+            color: glam::vec3(1.0, 0.2, 0.1),
+            intensity: 12.0,
+            range: Some(6.0),
+        },
+        Light::Spot {
+            position: glam::vec3(2.0, 3.0, 0.0),
+            direction: -glam::Vec3::Y,
+            color: glam::vec3(0.2, 0.4, 1.0),
+            intensity: 30.0,
+            range: None,
+            inner_cone_angle: 0.2,
+            outer_cone_angle: 0.4,
+        },
+    ];
+
+    let view_projection = camera.projection() * camera.view();
+    let identity = glam::Mat4::IDENTITY;
+    let mut shader = PbrShader::new(&material, &[], &lights, camera.transform.translation);
+    shader.ambient = glam::Vec3::splat(0.02);
+    for mesh in [&floor, &sphere] {
+        screen.raster_mesh(
+            mesh,
+            &SurfaceVertexShader::new(&view_projection, &identity),
+            &shader,
+        );
+    }
+}
+
+// sphere floating over a floor, shadowed by a directional and a spot light
+pub fn _test_shadows(screen: &mut Screen, camera: &Camera) {
+    let floor = Mesh::quad(-glam::Vec3::Y, glam::Vec3::Z * 4.0, glam::Vec3::X * 4.0);
+    let sphere = Mesh::uv_sphere(0.8, 48, 24);
+    let material = Material {
+        metallic_factor: 0.0,
+        roughness_factor: 0.8,
+        ..Default::default()
+    };
+    let lights = [
+        Light::Directional {
+            direction: glam::vec3(1.0, -2.0, -0.5).normalize(),
+            color: glam::Vec3::ONE,
+            intensity: 2.0,
+        },
+        Light::Spot {
+            position: glam::vec3(-2.5, 3.0, 0.5),
+            direction: glam::vec3(0.8, -1.0, -0.2).normalize(),
+            color: glam::vec3(1.0, 0.6, 0.2),
+            intensity: 20.0,
+            range: None,
+            inner_cone_angle: 0.3,
+            outer_cone_angle: 0.6,
+        },
+    ];
+
+    let instances = [
+        (&floor, glam::Mat4::IDENTITY),
+        (
+            &sphere,
+            glam::Mat4::from_translation(glam::vec3(0.0, 0.3, 0.0)),
+        ),
+    ];
+    let settings = crate::shadow::ShadowSettings {
+        size: 256,
+        ..Default::default()
+    };
+    let mut target = screen.depth_target(settings.size, settings.size);
+    let shadow_maps = lights
+        .iter()
+        .enumerate()
+        .filter_map(|(index, light)| {
+            let target = &mut target;
+            crate::shadow::ShadowMap::render(target, index, light, &instances, &[], &[], settings)
+        })
+        .collect::<Vec<_>>();
+
+    let view_projection = camera.projection() * camera.view();
+    let mut shader = PbrShader::new(&material, &[], &lights, camera.transform.translation);
+    shader.shadow_maps = &shadow_maps;
+    shader.ambient = glam::Vec3::splat(0.05);
+    for (mesh, model) in instances {
+        screen.raster_mesh(
+            mesh,
+            &SurfaceVertexShader::new(&view_projection, &model),
+            &shader,
+        );
+    }
+}
+
+// tinted panes in front of a sphere, blended with alpha, additive, multiply and
+// weighted blended transparency from the top left to the bottom right
+pub fn _test_blend_modes(screen: &mut Screen, camera: &Camera) {
+    let pane = Mesh::quad(glam::Vec3::ZERO, glam::Vec3::X * 0.7, glam::Vec3::Y * 0.7);
+    let sphere = Mesh::uv_sphere(1.3, 48, 24);
+    let lights = [Light::default()];
+    let view_projection = camera.projection() * camera.view();
+    let draw = |screen: &mut Screen, mesh: &Mesh, model: glam::Mat4, material: &Material| {
+        let shader = PbrShader::new(material, &[], &lights, camera.transform.translation);
+        screen.raster_mesh(
+            mesh,
+            &SurfaceVertexShader::new(&view_projection, &model),
+            &shader,
+        );
+    };
+
+    let material = Material {
+        metallic_factor: 0.0,
+        roughness_factor: 0.5,
+        ..Default::default()
+    };
+    draw(screen, &sphere, glam::Mat4::IDENTITY, &material);
+
+    let panes = [
+        (
+            BlendMode::Alpha,
+            glam::vec4(1.0, 0.1, 0.1, 0.5),
+            (-1.0, 1.0),
+        ),
+        (
+            BlendMode::Additive,
+            glam::vec4(0.2, 0.6, 1.0, 0.8),
+            (1.0, 1.0),
+        ),
+        (
+            BlendMode::Multiply,
+            glam::vec4(1.0, 0.8, 0.1, 1.0),
+            (-1.0, -1.0),
+        ),
+        (
+            BlendMode::WeightedBlended,
+            glam::vec4(0.1, 1.0, 0.2, 0.5),
+            (1.0, -1.0),
+        ),
+    ];
+    for (blend, color, (x, y)) in panes {
+        screen.state = DrawState::transparent(blend);
+        let material = Material {
+            base_color_factor: color,
+            metallic_factor: 0.0,
+            emissive_factor: color.xyz() * 0.5,
+            ..Default::default()
+        };
+        let model = glam::Mat4::from_translation(glam::vec3(x * 0.8, y * 0.8, 1.5));
+        draw(screen, &pane, model, &material);
+    }
+    screen.resolve_transparency();
+    screen.state = DrawState::default();
+}
+
+// fence of alpha tested bars in front of a sphere. The fence is drawn first, the
+// sphere is only visible between the bars if they didn't write the z buffer.
+pub fn _test_cutout(screen: &mut Screen, camera: &Camera) {
+    let (width, height) = (32, 32);
+    let mut texels = Vec::new();
+    for y in 0..height {
+        for x in 0..width {
+            let bar = x % 8 < 3 || y % 16 < 2;
+            let alpha = if bar { 1.0 } else { 0.0 };
+            texels.push(glam::vec4(0.6, 0.4, 0.2, alpha));
+        }
+    }
+    let textures = [Texture::new(width, height, Texels::Rgba32F(texels))];
+    let fence = Mesh::quad(
+        glam::Vec3::Z * 1.2,
+        glam::Vec3::X * 1.6,
+        glam::Vec3::Y * 1.6,
+    );
+    let sphere = Mesh::uv_sphere(1.0, 48, 24);
+    let fence_material = Material {
+        base_color_texture: Some(TextureBinding {
+            texture: 0,
+            sampler: Sampler::NEAREST,
+            tex_coord: 0,
+        }),
+        metallic_factor: 0.0,
+        alpha_mode: crate::material::AlphaMode::Mask,
+        ..Default::default()
+    };
+    let sphere_material = Material {
+        base_color_factor: glam::vec4(0.2, 0.5, 1.0, 1.0),
+        metallic_factor: 0.0,
+        roughness_factor: 0.4,
+        ..Default::default()
+    };
+
+    let lights = [Light::default()];
+    let view_projection = camera.projection() * camera.view();
+    let vertex_shader = SurfaceVertexShader::new(&view_projection, &glam::Mat4::IDENTITY);
+    let camera_position = camera.transform.translation;
+    screen.state = DrawState::cutout(fence_material.alpha_cutoff);
+    let shader = PbrShader::new(&fence_material, &textures, &lights, camera_position);
+    screen.raster_mesh(&fence, &vertex_shader, &shader);
+    screen.state = DrawState::default();
+    let shader = PbrShader::new(&sphere_material, &textures, &lights, camera_position);
+    screen.raster_mesh(&sphere, &vertex_shader, &shader);
+}
+
+// two spheres, the front one selected with an outline. The selected sphere marks its
+// pixels in the stencil buffer, a larger copy is then drawn everywhere else on top.
+pub fn _test_stencil_outline(screen: &mut Screen, camera: &Camera) {
+    let sphere = Mesh::uv_sphere(1.0, 48, 24);
+    let material = Material {
+        metallic_factor: 0.0,
+        roughness_factor: 0.5,
+        ..Default::default()
+    };
+    let outline = Material {
+        base_color_factor: glam::Vec4::W,
+        emissive_factor: glam::vec3(1.0, 0.6, 0.1),
+        ..Default::default()
+    };
+    let lights = [Light::default()];
+    let view_projection = camera.projection() * camera.view();
+    let camera_position = camera.transform.translation;
+    let shader = PbrShader::new(&material, &[], &lights, camera_position);
+    let outline_shader = PbrShader {
+        ambient: glam::Vec3::ZERO,
+        ..PbrShader::new(&outline, &[], &[], camera_position)
+    };
+    let draw = |screen: &mut Screen, model: glam::Mat4, shader: &PbrShader| {
+        let vertex_shader = SurfaceVertexShader::new(&view_projection, &model);
+        screen.raster_mesh(&sphere, &vertex_shader, shader);
+    };
+
+    let selected = glam::Mat4::from_translation(glam::vec3(-0.6, 0.0, 0.5));
+    draw(
+        screen,
+        glam::Mat4::from_translation(glam::vec3(0.8, 0.3, -0.5)),
+        &shader,
+    );
+    screen.state = DrawState {
+        stencil: Some(StencilState::write(1)),
+        ..Default::default()
+    };
+    draw(screen, selected, &shader);
+    screen.state = DrawState {
+        depth_compare: CompareFunction::Always,
+        depth_write: false,
+        stencil: Some(StencilState::test(CompareFunction::NotEqual, 1)),
+        ..Default::default()
+    };
+    draw(
+        screen,
+        selected * glam::Mat4::from_scale(glam::Vec3::splat(1.08)),
+        &outline_shader,
+    );
+    screen.state = DrawState::default();
+}
+
+// overexposed spheres, presented with the tone mapper
+pub fn _test_tone_mapping(screen: &mut Screen, camera: &Camera, tone_mapper: ToneMapper) {
+    screen.output.tone_mapper = tone_mapper;
+    let sphere = Mesh::uv_sphere(0.8, 48, 24);
+    let lights = [Light::Directional {
+        direction: glam::vec3(-1.0, -1.0, -1.0).normalize(),
+        color: glam::Vec3::ONE,
+        intensity: 6.0,
+    }];
+    let view_projection = camera.projection() * camera.view();
+    for (x, y) in [(-1.0, 1.0), (1.0, 1.0), (-1.0, -1.0), (1.0, -1.0)] {
+        let material = Material {
+            base_color_factor: glam::vec4(0.9, 0.4, 0.2, 1.0),
+            metallic_factor: 0.0,
+            roughness_factor: 0.3,
+            ..Default::default()
+        };
+        let shader = PbrShader::new(&material, &[], &lights, camera.transform.translation);
+        let model = glam::Mat4::from_translation(glam::vec3(x, y, 0.0));
+        screen.raster_mesh(
+            &sphere,
+            &SurfaceVertexShader::new(&view_projection, &model),
+            &shader,
+        );
+    }
+}
+
+// sphere with ridges running from pole to pole, only present in the normal map
+pub fn _test_normal_map(screen: &mut Screen, camera: &Camera) {
+    let (width, height) = (64, 32);
+    let mut texels = Vec::new();
+    for _ in 0..height {
+        for x in 0..width {
+            let slope = (x as f32 / width as f32 * 16.0 * std::f32::consts::PI).sin() * 0.6;
+            let normal = glam::vec3(slope, 0.0, 1.0).normalize() * 0.5 + 0.5;
+            texels.push(normal.extend(1.0));
+        }
+    }
+    let textures = [Texture::new(width, height, Texels::Rgba32F(texels))];
+    let material = Material {
+        base_color_factor: glam::vec4(0.8, 0.8, 0.8, 1.0),
+        metallic_factor: 0.0,
+        roughness_factor: 0.4,
+        normal_texture: Some(TextureBinding {
+            texture: 0,
+            sampler: Sampler::BILINEAR,
+            tex_coord: 0,
+        }),
+        ..Default::default()
+    };
+    let sphere = Mesh::uv_sphere(1.5, 64, 32);
+    let view_projection = camera.projection() * camera.view();
+    let model = Transform::from_rotation(glam::Quat::from_rotation_y(0.3)).get_local();
+    screen.raster_mesh(
+        &sphere,
+        &SurfaceVertexShader::new(&view_projection, &model),
+        &PbrShader::new(
+            &material,
+            &textures,
+            &[Light::default()],
+            camera.transform.translation,
+        ),
+    );
+}
+
+// rows of dielectric and metallic spheres, getting rougher from left to right
+pub fn _test_pbr_spheres(screen: &mut Screen, camera: &Camera) {
+    let sphere = Mesh::uv_sphere(0.8, 48, 24);
+    let lights = [Light::default()];
+    let view_projection = camera.projection() * camera.view();
+    for (row, metallic) in [0.0, 1.0].into_iter().enumerate() {
+        for (column, roughness) in [0.2, 0.5, 0.9].into_iter().enumerate() {
+            let material = Material {
+                base_color_factor: glam::vec4(0.9, 0.3, 0.2, 1.0),
+                metallic_factor: metallic,
+                roughness_factor: roughness,
+                ..Default::default()
+            };
+            let position = glam::vec3(column as f32 * 2.0 - 2.0, 1.0 - row as f32 * 2.0, 0.0);
+            let model = Transform::from_translation(position).get_local();
+            screen.raster_mesh(
+                &sphere,
+                &SurfaceVertexShader::new(&view_projection, &model),
+                &PbrShader::new(&material, &[], &lights, camera.transform.translation),
+            );
+        }
+    }
+}
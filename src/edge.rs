@@ -0,0 +1,286 @@
+use glam::{I64Vec2, I64Vec3, Vec2, Vec4};
+use std::ops::{Add, AddAssign, BitOr};
+
+// window coordinates are snapped to 1/256th of a pixel before rasterization, so
+// triangles sharing an edge see exactly the same edge function
+pub const SUBPIXEL_BITS: u32 = 8;
+pub const SUBPIXEL_STEPS: i64 = 1 << SUBPIXEL_BITS;
+
+pub fn to_fixed(position: Vec2) -> I64Vec2 {
+    (position * SUBPIXEL_STEPS as f32).round().as_i64vec2()
+}
+
+// center of the pixel in fixed point window coordinates
+pub fn pixel_center(x: usize, y: usize) -> I64Vec2 {
+    I64Vec2::new(x as i64, y as i64) * SUBPIXEL_STEPS + SUBPIXEL_STEPS / 2
+}
+
+// offsets of the pixels of a 2x2 quad, in the order of the lanes:
+// top left, top right, bottom left, bottom right
+pub const QUAD_LANES: [(usize, usize); 4] = [(0, 0), (1, 0), (0, 1), (1, 1)];
+
+// Four i64 lanes, one per pixel of a quad. On x86_64 they live in two SSE2 registers,
+// so stepping and testing a whole quad takes a handful of vector instructions. i64 is
+// needed because edge functions are products of fixed point coordinates.
+#[cfg(target_arch = "x86_64")]
+mod lanes {
+    use std::arch::x86_64::*;
+
+    // the unsafe blocks only call sse2 intrinsics, which every x86_64 cpu has
+    // lanes 0 and 1 in the first register, 2 and 3 in the second
+    #[derive(Debug, Copy, Clone)]
+    pub struct I64x4(__m128i, __m128i);
+
+    impl I64x4 {
+        pub fn new([a, b, c, d]: [i64; 4]) -> Self {
+            unsafe { Self(_mm_set_epi64x(b, a), _mm_set_epi64x(d, c)) }
+        }
+
+        pub fn splat(value: i64) -> Self {
+            unsafe { Self(_mm_set1_epi64x(value), _mm_set1_epi64x(value)) }
+        }
+
+        pub fn to_array(self) -> [i64; 4] {
+            let split = |r| unsafe {
+                (
+                    _mm_cvtsi128_si64(r),
+                    _mm_cvtsi128_si64(_mm_unpackhi_epi64(r, r)),
+                )
+            };
+            let ((a, b), (c, d)) = (split(self.0), split(self.1));
+            [a, b, c, d]
+        }
+
+        // bit i is set when lane i is negative, read straight from the sign bits
+        pub fn negative_mask(self) -> u32 {
+            unsafe {
+                let low = _mm_movemask_pd(_mm_castsi128_pd(self.0));
+                let high = _mm_movemask_pd(_mm_castsi128_pd(self.1));
+                (low | high << 2) as u32
+            }
+        }
+
+        pub(super) fn add(self, rhs: Self) -> Self {
+            unsafe { Self(_mm_add_epi64(self.0, rhs.0), _mm_add_epi64(self.1, rhs.1)) }
+        }
+
+        pub(super) fn or(self, rhs: Self) -> Self {
+            unsafe { Self(_mm_or_si128(self.0, rhs.0), _mm_or_si128(self.1, rhs.1)) }
+        }
+    }
+}
+
+// two NEON registers on aarch64, the same layout as on x86_64
+#[cfg(target_arch = "aarch64")]
+mod lanes {
+    use std::arch::aarch64::*;
+
+    // the unsafe blocks only call neon intrinsics, which every aarch64 cpu has
+    #[derive(Debug, Copy, Clone)]
+    pub struct I64x4(int64x2_t, int64x2_t);
+
+    impl I64x4 {
+        pub fn new(lanes: [i64; 4]) -> Self {
+            unsafe { Self(vld1q_s64(lanes.as_ptr()), vld1q_s64(lanes[2..].as_ptr())) }
+        }
+
+        pub fn splat(value: i64) -> Self {
+            unsafe { Self(vdupq_n_s64(value), vdupq_n_s64(value)) }
+        }
+
+        pub fn to_array(self) -> [i64; 4] {
+            let mut lanes = [0; 4];
+            unsafe {
+                vst1q_s64(lanes.as_mut_ptr(), self.0);
+                vst1q_s64(lanes[2..].as_mut_ptr(), self.1);
+            }
+            lanes
+        }
+
+        // bit i is set when lane i is negative, the sign bits shifted down to bit 0
+        pub fn negative_mask(self) -> u32 {
+            unsafe {
+                let low = vshrq_n_u64::<63>(vreinterpretq_u64_s64(self.0));
+                let high = vshrq_n_u64::<63>(vreinterpretq_u64_s64(self.1));
+                (vgetq_lane_u64::<0>(low)
+                    | vgetq_lane_u64::<1>(low) << 1
+                    | vgetq_lane_u64::<0>(high) << 2
+                    | vgetq_lane_u64::<1>(high) << 3) as u32
+            }
+        }
+
+        pub(super) fn add(self, rhs: Self) -> Self {
+            unsafe { Self(vaddq_s64(self.0, rhs.0), vaddq_s64(self.1, rhs.1)) }
+        }
+
+        pub(super) fn or(self, rhs: Self) -> Self {
+            unsafe { Self(vorrq_s64(self.0, rhs.0), vorrq_s64(self.1, rhs.1)) }
+        }
+    }
+}
+
+// same lanes as plain integers for the other targets
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+mod lanes {
+    #[derive(Debug, Copy, Clone)]
+    pub struct I64x4([i64; 4]);
+
+    impl I64x4 {
+        pub fn new(lanes: [i64; 4]) -> Self {
+            Self(lanes)
+        }
+
+        pub fn splat(value: i64) -> Self {
+            Self([value; 4])
+        }
+
+        pub fn to_array(self) -> [i64; 4] {
+            self.0
+        }
+
+        pub fn negative_mask(self) -> u32 {
+            (0..4).fold(0, |mask, i| mask | ((self.0[i] < 0) as u32) << i)
+        }
+
+        pub(super) fn add(self, rhs: Self) -> Self {
+            Self([0, 1, 2, 3].map(|i| self.0[i].wrapping_add(rhs.0[i])))
+        }
+
+        pub(super) fn or(self, rhs: Self) -> Self {
+            Self([0, 1, 2, 3].map(|i| self.0[i] | rhs.0[i]))
+        }
+    }
+}
+
+pub use lanes::I64x4;
+
+impl Add for I64x4 {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        I64x4::add(self, rhs)
+    }
+}
+
+impl AddAssign for I64x4 {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+impl BitOr for I64x4 {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        self.or(rhs)
+    }
+}
+
+// edge functions for the four pixels of a quad, one vector per edge
+#[derive(Debug, Copy, Clone)]
+pub struct QuadWeights(pub [I64x4; 3]);
+
+impl AddAssign for QuadWeights {
+    fn add_assign(&mut self, rhs: Self) {
+        for (weights, step) in self.0.iter_mut().zip(rhs.0) {
+            *weights += step;
+        }
+    }
+}
+
+// The three edge functions of a triangle in fixed point, component i belongs to the
+// edge opposite of vertex i and is positive inside of the triangle. They are linear
+// in the pixel position, so neighbouring pixels are reached by adding a constant step.
+#[derive(Debug, Copy, Clone)]
+pub struct TriangleEdges {
+    origins: [I64Vec2; 3],
+    directions: [I64Vec2; 3],
+    // 0 for top and left edges, -1 for the others: pixel centers exactly on an edge
+    // belong to the triangle on its top or left side only
+    bias: I64Vec3,
+    quad_bias: [I64x4; 3],
+    pub step_x: I64Vec3,
+    pub step_y: I64Vec3,
+    // steps from one quad to the next
+    pub quad_step_x: QuadWeights,
+    pub quad_step_y: QuadWeights,
+    // twice the area in fixed point, the sum of the three edge functions
+    pub area: i64,
+}
+
+impl TriangleEdges {
+    // None for triangles without area after snapping
+    pub fn new(screen: &[Vec2; 3]) -> Option<Self> {
+        let v = screen.map(to_fixed);
+        let area = (v[1] - v[0]).perp_dot(v[2] - v[0]);
+        if area == 0 {
+            return None;
+        }
+        // edges are oriented so that the inside is positive for both windings
+        let sign = area.signum();
+        let origins = [v[1], v[2], v[0]];
+        let directions = [
+            (v[2] - v[1]) * sign,
+            (v[0] - v[2]) * sign,
+            (v[1] - v[0]) * sign,
+        ];
+
+        // with y pointing down, top edges are horizontal and go right,
+        // left edges go up
+        let is_top_left = |d: I64Vec2| (d.y == 0 && d.x > 0) || d.y < 0;
+        let bias = I64Vec3::from_array(directions.map(|d| if is_top_left(d) { 0 } else { -1 }));
+
+        let step_x = I64Vec3::from_array(directions.map(|d| -d.y * SUBPIXEL_STEPS));
+        let step_y = I64Vec3::from_array(directions.map(|d| d.x * SUBPIXEL_STEPS));
+        Some(Self {
+            origins,
+            directions,
+            bias,
+            quad_bias: bias.to_array().map(I64x4::splat),
+            step_x,
+            step_y,
+            quad_step_x: QuadWeights([0, 1, 2].map(|i| I64x4::splat(step_x[i] * 2))),
+            quad_step_y: QuadWeights([0, 1, 2].map(|i| I64x4::splat(step_y[i] * 2))),
+            area: area.abs(),
+        })
+    }
+
+    // edge functions at the center of a pixel, only needed once per row or tile
+    pub fn evaluate(&self, x: usize, y: usize) -> I64Vec3 {
+        let p = pixel_center(x, y);
+        I64Vec3::from_array([0, 1, 2].map(|i| self.directions[i].perp_dot(p - self.origins[i])))
+    }
+
+    // whether a pixel with these edge functions is inside of the triangle, one pixel at a
+    // time. Applies the top-left fill rule.
+    pub fn covers(&self, weights: I64Vec3) -> bool {
+        (weights + self.bias).cmpge(I64Vec3::ZERO).all()
+    }
+
+    // edge functions for the quad with its top left pixel at x, y
+    pub fn evaluate_quad(&self, x: usize, y: usize) -> QuadWeights {
+        let weights = self.evaluate(x, y);
+        QuadWeights([0, 1, 2].map(|i| {
+            let (dx, dy) = (self.step_x[i], self.step_y[i]);
+            I64x4::new([0, dx, dy, dx + dy].map(|offset| weights[i] + offset))
+        }))
+    }
+
+    // lanes inside of the triangle as bits, see QUAD_LANES. A lane is outside as soon as
+    // one of its biased edge functions is negative, so the sign bits of the three edges
+    // are combined and tested at once. Applies the top-left fill rule.
+    pub fn quad_coverage(&self, weights: &QuadWeights) -> u32 {
+        let [w0, w1, w2] = weights.0;
+        let [b0, b1, b2] = self.quad_bias;
+        let outside = (w0 + b0) | (w1 + b1) | (w2 + b2);
+        !outside.negative_mask() & 0b1111
+    }
+
+    // barycentric coordinates of the lanes, one vector per vertex
+    pub fn quad_barycentric(&self, weights: &QuadWeights) -> [Vec4; 3] {
+        let rec_area = 1.0 / self.area as f32;
+        weights
+            .0
+            .map(|w| Vec4::from_array(w.to_array().map(|w| w as f32)) * rec_area)
+    }
+}
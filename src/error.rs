@@ -0,0 +1,87 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+// Everything that can go wrong while loading assets
+#[derive(Debug)]
+pub enum Error {
+    // the file doesn't exist
+    NotFound(PathBuf),
+    // the file exists but couldn't be read
+    Io(PathBuf, io::Error),
+    // the data is in a known format but broken, `name` is the file or a description
+    // of where the data came from
+    Decode {
+        name: String,
+        message: String,
+    },
+    // the data is valid, but in a format or uses a feature we can't load
+    UnsupportedFormat {
+        name: String,
+        message: String,
+    },
+    // an accessor of a glTF primitive doesn't fit the rest of the primitive
+    MalformedAccessor {
+        mesh: usize,
+        primitive: usize,
+        message: String,
+    },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    pub fn from_io(path: impl Into<PathBuf>, error: io::Error) -> Self {
+        let path = path.into();
+        if error.kind() == io::ErrorKind::NotFound {
+            Self::NotFound(path)
+        } else {
+            Self::Io(path, error)
+        }
+    }
+
+    pub fn decode(name: impl fmt::Display, message: impl fmt::Display) -> Self {
+        Self::Decode {
+            name: name.to_string(),
+            message: message.to_string(),
+        }
+    }
+
+    pub fn unsupported(name: impl fmt::Display, message: impl fmt::Display) -> Self {
+        Self::UnsupportedFormat {
+            name: name.to_string(),
+            message: message.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NotFound(path) => write!(f, "{} doesn't exist", path.display()),
+            Self::Io(path, error) => write!(f, "can't read {}: {}", path.display(), error),
+            Self::Decode { name, message } => write!(f, "can't decode {}: {}", name, message),
+            Self::UnsupportedFormat { name, message } => {
+                write!(f, "unsupported format in {}: {}", name, message)
+            }
+            Self::MalformedAccessor {
+                mesh,
+                primitive,
+                message,
+            } => write!(
+                f,
+                "malformed accessor in primitive {} of mesh {}: {}",
+                primitive, mesh, message
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(_, error) => Some(error),
+            _ => None,
+        }
+    }
+}
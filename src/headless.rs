@@ -0,0 +1,327 @@
+use crate::blend::BlendMode;
+use crate::camera::Camera;
+use crate::color::{OutputSettings, ToneMapper};
+use crate::light::Light;
+use crate::material::{AlphaMode, Material, TextureBinding};
+use crate::raster::{DrawState, RenderSettings, Screen};
+use crate::scene::Scene;
+use crate::shader::{PbrShader, SurfaceVertexShader};
+use crate::shadow::{ShadowMap, ShadowSettings};
+use crate::texture::{Sampler, Texels, Texture, Wrap};
+use crate::transform::Transform;
+use glam::{EulerRot, Quat, Vec3};
+use std::path::PathBuf;
+
+pub const USAGE: &str = "usage: rusterizer render <model.gltf> <output.png|ppm> [options]
+options:
+    --size <width>x<height>    output resolution (default 512x512)
+    --position <x,y,z>         camera position (default 0,0,5)
+    --rotation <yaw,pitch,roll> camera rotation in degrees (default 0,0,0)
+    --fov <degrees>            vertical field of view (default 45)
+    --texture <image>          base color texture applied instead of the model's own
+    --filter <mode>            nearest, bilinear or trilinear texture filtering (default: the model's sampler, or trilinear)
+    --anisotropy <samples>     maximum anisotropic filtering samples (default 1)
+    --wrap <mode>              repeat, mirror, clamp or border texture wrapping (default: the model's sampler, or repeat)
+    --shadows <size>           shadow maps of the given resolution for directional and spot lights
+    --shadow-bias <constant,slope> depth offset and slope offset in texels of the shadow lookups (default 0.002,1)
+    --pcf <radius>             shadow filter radius in texels, 0 for hard shadows (default 1)
+    --tonemap <mode>           clamp, reinhard, aces or exposure tone mapping (default clamp)
+    --exposure <scale>         multiplies the colors before tone mapping (default 1)
+    --linear                   write linear colors instead of sRGB encoded ones
+    --depth <output.png|ppm>   also write the z buffer
+    --threads <count>          number of rasterizer threads (default: all cores)
+    --oit                      weighted blended order independent transparency instead of sorting
+    --debug-clipping           tint triangles produced by clipping";
+
+// everything needed to render a single frame without a window
+pub struct HeadlessOptions {
+    pub model: PathBuf,
+    pub output: PathBuf,
+    pub depth_output: Option<PathBuf>,
+    pub texture: Option<PathBuf>,
+    // replaces the sampler of the model's texture when set
+    pub sampler: Option<Sampler>,
+    // no shadows when None
+    pub shadows: Option<ShadowSettings>,
+    pub width: usize,
+    pub height: usize,
+    pub camera_position: Vec3,
+    pub camera_rotation: Vec3,
+    pub fov: f32,
+    // blended materials use weighted blended transparency instead of sorted alpha blending
+    pub oit: bool,
+    // tone mapping and encoding of the written image
+    pub output_settings: OutputSettings,
+    pub settings: RenderSettings,
+}
+
+impl HeadlessOptions {
+    // parses the arguments following the `render` command
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let mut positional = Vec::new();
+        let mut options = Self {
+            model: PathBuf::new(),
+            output: PathBuf::new(),
+            depth_output: None,
+            texture: None,
+            sampler: None,
+            shadows: None,
+            width: 512,
+            height: 512,
+            camera_position: glam::vec3(0.0, 0.0, 5.0),
+            camera_rotation: Vec3::ZERO,
+            fov: 45.0,
+            oit: false,
+            output_settings: OutputSettings::default(),
+            settings: RenderSettings::default(),
+        };
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if !arg.starts_with("--") {
+                positional.push(arg);
+                continue;
+            }
+            if arg == "--debug-clipping" {
+                options.settings.debug_clipping = true;
+                continue;
+            }
+            if arg == "--oit" {
+                options.oit = true;
+                continue;
+            }
+            if arg == "--linear" {
+                options.output_settings.srgb = false;
+                continue;
+            }
+            let value = args
+                .next()
+                .ok_or_else(|| format!("missing value for {}", arg))?;
+            match arg.as_str() {
+                "--size" => {
+                    let (width, height) = value
+                        .split_once('x')
+                        .ok_or_else(|| format!("invalid size '{}'", value))?;
+                    options.width = parse_number(width)?;
+                    options.height = parse_number(height)?;
+                    if options.width == 0 || options.height == 0 {
+                        return Err(format!("invalid size '{}'", value));
+                    }
+                }
+                "--position" => options.camera_position = parse_vec3(value)?,
+                "--rotation" => options.camera_rotation = parse_vec3(value)?,
+                "--fov" => options.fov = parse_number(value)?,
+                "--texture" => options.texture = Some(PathBuf::from(value)),
+                "--filter" => {
+                    let preset = match value.as_str() {
+                        "nearest" => Sampler::NEAREST,
+                        "bilinear" => Sampler::BILINEAR,
+                        "trilinear" => Sampler::TRILINEAR,
+                        _ => return Err(format!("unknown filter '{}'", value)),
+                    };
+                    let sampler = options.sampler.get_or_insert_with(Sampler::default);
+                    sampler.mag_filter = preset.mag_filter;
+                    sampler.min_filter = preset.min_filter;
+                    sampler.mipmap_filter = preset.mipmap_filter;
+                }
+                "--anisotropy" => {
+                    let sampler = options.sampler.get_or_insert_with(Sampler::default);
+                    sampler.max_anisotropy = parse_number(value)?;
+                }
+                "--wrap" => {
+                    let wrap = match value.as_str() {
+                        "repeat" => Wrap::Repeat,
+                        "mirror" => Wrap::MirroredRepeat,
+                        "clamp" => Wrap::ClampToEdge,
+                        "border" => Wrap::ClampToBorder,
+                        _ => return Err(format!("unknown wrap mode '{}'", value)),
+                    };
+                    let sampler = options.sampler.unwrap_or_default();
+                    options.sampler = Some(sampler.with_wrap(wrap));
+                }
+                "--shadows" => {
+                    let shadows = options.shadows.get_or_insert_with(ShadowSettings::default);
+                    shadows.size = parse_number(value)?;
+                    if shadows.size == 0 {
+                        return Err(format!("invalid shadow map size '{}'", value));
+                    }
+                }
+                "--shadow-bias" => {
+                    let (constant, slope) = value
+                        .split_once(',')
+                        .ok_or_else(|| format!("invalid shadow bias '{}'", value))?;
+                    let shadows = options.shadows.get_or_insert_with(ShadowSettings::default);
+                    shadows.depth_bias = parse_number(constant)?;
+                    shadows.slope_bias = parse_number(slope)?;
+                }
+                "--pcf" => {
+                    let shadows = options.shadows.get_or_insert_with(ShadowSettings::default);
+                    shadows.pcf_radius = parse_number(value)?;
+                }
+                "--tonemap" => {
+                    options.output_settings.tone_mapper = match value.as_str() {
+                        "clamp" => ToneMapper::Clamp,
+                        "reinhard" => ToneMapper::Reinhard,
+                        "aces" => ToneMapper::Aces,
+                        "exposure" => ToneMapper::Exposure,
+                        _ => return Err(format!("unknown tone mapper '{}'", value)),
+                    };
+                }
+                "--exposure" => options.output_settings.exposure = parse_number(value)?,
+                "--depth" => options.depth_output = Some(PathBuf::from(value)),
+                "--threads" => options.settings.threads = parse_number(value)?,
+                _ => return Err(format!("unknown option {}", arg)),
+            }
+        }
+
+        match positional.as_slice() {
+            [model, output] => {
+                options.model = PathBuf::from(model);
+                options.output = PathBuf::from(output);
+                Ok(options)
+            }
+            _ => Err("expected a model and an output path".to_string()),
+        }
+    }
+
+    pub fn camera(&self) -> Camera {
+        let rotation = Quat::from_euler(
+            EulerRot::YXZ,
+            self.camera_rotation.x.to_radians(),
+            self.camera_rotation.y.to_radians(),
+            self.camera_rotation.z.to_radians(),
+        );
+        Camera {
+            fov: self.fov.to_radians(),
+            aspect_ratio: self.width as f32 / self.height as f32,
+            transform: Transform::from_translation_rotation(self.camera_position, rotation),
+            ..Default::default()
+        }
+    }
+}
+
+// renders one frame into an offscreen screen and writes it to disk
+pub fn render(options: &HeadlessOptions) -> Result<Screen, String> {
+    let mut screen = Screen::create(options.width, options.height);
+    screen.settings = options.settings;
+    screen.output = options.output_settings;
+    let mut scene = Scene::load(&options.model).map_err(|e| e.to_string())?;
+    let mut default_material = Material::default();
+    if let Some(path) = &options.texture {
+        let mut texture = Texture::load(path).map_err(|e| e.to_string())?;
+        // like a glTF base color texture, unless it's an hdr image which is already linear
+        let linear = matches!(texture.levels[0].texels, Texels::Rgba32F(_));
+        texture.set_srgb(!linear);
+        scene.textures.push(texture);
+        let binding = TextureBinding {
+            texture: scene.textures.len() - 1,
+            sampler: Sampler::default(),
+            tex_coord: 0,
+        };
+        for material in scene.materials.iter_mut().chain([&mut default_material]) {
+            material.base_color_texture = Some(binding);
+        }
+    }
+    if let Some(sampler) = options.sampler {
+        for material in scene.materials.iter_mut().chain([&mut default_material]) {
+            material
+                .textures_mut()
+                .for_each(|binding| binding.sampler = sampler);
+        }
+    }
+
+    // scenes without lights of their own are lit by the default light
+    let mut lights = scene.world_lights();
+    if lights.is_empty() {
+        lights.push(Light::default());
+    }
+
+    let shadow_maps = match options.shadows {
+        Some(settings) => {
+            let casters = scene.instances().collect::<Vec<_>>();
+            let mut target = screen.depth_target(settings.size, settings.size);
+            lights
+                .iter()
+                .enumerate()
+                .filter_map(|(index, light)| {
+                    let (materials, textures) = (&scene.materials, &scene.textures);
+                    let target = &mut target;
+                    ShadowMap::render(
+                        target, index, light, &casters, materials, textures, settings,
+                    )
+                })
+                .collect()
+        }
+        None => Vec::new(),
+    };
+
+    let camera = options.camera();
+    let view_projection = camera.projection() * camera.view();
+    let transparent = DrawState::transparent(if options.oit {
+        BlendMode::WeightedBlended
+    } else {
+        BlendMode::Alpha
+    });
+    let clip_vertices: Vec<_> = scene
+        .instances()
+        .map(|(mesh, world)| {
+            let vertex_shader = SurfaceVertexShader::new(&view_projection, &world);
+            Screen::shade_vertices(mesh, &vertex_shader)
+        })
+        .collect();
+    for draw in scene.draws(camera.transform.translation) {
+        let material = scene
+            .material(draw.submesh.material)
+            .unwrap_or(&default_material);
+        let fragment_shader = PbrShader {
+            shadow_maps: &shadow_maps,
+            ..PbrShader::new(
+                material,
+                &scene.textures,
+                &lights,
+                camera.transform.translation,
+            )
+        };
+        screen.state = match material.alpha_mode {
+            AlphaMode::Opaque => DrawState::default(),
+            AlphaMode::Mask => DrawState::cutout(material.alpha_cutoff),
+            AlphaMode::Blend => transparent,
+        };
+        let vertices = &clip_vertices[draw.instance];
+        screen.raster_submesh(draw.mesh, draw.submesh, vertices, &fragment_shader);
+    }
+    screen.resolve_transparency();
+    screen.present();
+
+    screen
+        .save_color(&options.output)
+        .map_err(|e| format!("can't write {}: {}", options.output.display(), e))?;
+    if let Some(depth_output) = &options.depth_output {
+        screen
+            .save_depth(depth_output)
+            .map_err(|e| format!("can't write {}: {}", depth_output.display(), e))?;
+    }
+
+    Ok(screen)
+}
+
+fn parse_number<T: std::str::FromStr>(value: &str) -> Result<T, String> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid number '{}'", value))
+}
+
+fn parse_vec3(value: &str) -> Result<Vec3, String> {
+    let components = value
+        .split(',')
+        .map(parse_number)
+        .collect::<Result<Vec<f32>, String>>()?;
+    match components.as_slice() {
+        [x, y, z] => Ok(glam::vec3(*x, *y, *z)),
+        _ => Err(format!(
+            "expected three comma separated values, got '{}'",
+            value
+        )),
+    }
+}
@@ -0,0 +1,26 @@
+pub mod blend;
+pub mod camera;
+pub mod clip;
+pub mod color;
+pub mod demos;
+pub mod edge;
+pub mod error;
+pub mod headless;
+pub mod light;
+pub mod material;
+pub mod output;
+pub mod raster;
+pub mod scene;
+pub mod shader;
+pub mod shadow;
+pub mod stencil;
+pub mod texture;
+pub mod tile;
+pub mod transform;
+pub mod utils;
+
+pub use raster::Screen;
+pub use utils::*;
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,153 @@
+use glam::{Mat4, Vec3};
+use std::f32::consts::PI;
+
+// Punctual lights as defined by KHR_lights_punctual. The intensity of directional
+// lights is in lux, the one of point and spot lights in candela. Positions and
+// directions are in world space, directions point where the light travels.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Light {
+    Directional {
+        direction: Vec3,
+        color: Vec3,
+        intensity: f32,
+    },
+    Point {
+        position: Vec3,
+        color: Vec3,
+        intensity: f32,
+        // distance at which the light reaches zero, None for the physical falloff only
+        range: Option<f32>,
+    },
+    Spot {
+        position: Vec3,
+        direction: Vec3,
+        color: Vec3,
+        intensity: f32,
+        range: Option<f32>,
+        // angles from the direction in radians, full intensity inside the inner cone
+        // fading to zero at the outer one
+        inner_cone_angle: f32,
+        outer_cone_angle: f32,
+    },
+}
+
+// light from the top right front, a white diffuse surface facing it is fully lit
+impl Default for Light {
+    fn default() -> Self {
+        Self::Directional {
+            direction: -Vec3::ONE.normalize(),
+            color: Vec3::ONE,
+            intensity: PI,
+        }
+    }
+}
+
+impl Light {
+    // light of a node without its transform, at the origin and pointing down -z
+    pub fn from_gltf(light: &gltf::khr_lights_punctual::Light) -> Self {
+        use gltf::khr_lights_punctual::Kind;
+        let color = Vec3::from(light.color());
+        let intensity = light.intensity();
+        match light.kind() {
+            Kind::Directional => Self::Directional {
+                direction: -Vec3::Z,
+                color,
+                intensity,
+            },
+            Kind::Point => Self::Point {
+                position: Vec3::ZERO,
+                color,
+                intensity,
+                range: light.range(),
+            },
+            Kind::Spot {
+                inner_cone_angle,
+                outer_cone_angle,
+            } => Self::Spot {
+                position: Vec3::ZERO,
+                direction: -Vec3::Z,
+                color,
+                intensity,
+                range: light.range(),
+                inner_cone_angle,
+                outer_cone_angle,
+            },
+        }
+    }
+
+    // places the light with a world matrix, e.g. the one of its node
+    pub fn transformed(&self, matrix: &Mat4) -> Self {
+        let mut light = *self;
+        match &mut light {
+            Self::Directional { direction, .. } => {
+                *direction = matrix.transform_vector3(*direction).normalize_or_zero();
+            }
+            Self::Point { position, .. } => *position = matrix.transform_point3(*position),
+            Self::Spot {
+                position,
+                direction,
+                ..
+            } => {
+                *position = matrix.transform_point3(*position);
+                *direction = matrix.transform_vector3(*direction).normalize_or_zero();
+            }
+        }
+        light
+    }
+
+    // direction from the point towards the light and the radiance arriving at the point,
+    // None when the light doesn't reach it
+    pub fn incident(&self, point: Vec3) -> Option<(Vec3, Vec3)> {
+        match *self {
+            Self::Directional {
+                direction,
+                color,
+                intensity,
+            } => Some((-direction, color * intensity)),
+            Self::Point {
+                position,
+                color,
+                intensity,
+                range,
+            } => {
+                let (to_light, attenuation) = distance_attenuation(position - point, range)?;
+                Some((to_light, color * intensity * attenuation))
+            }
+            Self::Spot {
+                position,
+                direction,
+                color,
+                intensity,
+                range,
+                inner_cone_angle,
+                outer_cone_angle,
+            } => {
+                let (to_light, attenuation) = distance_attenuation(position - point, range)?;
+                // smooth falloff between the cosines of the cone angles
+                let (cos_inner, cos_outer) = (inner_cone_angle.cos(), outer_cone_angle.cos());
+                let scale = 1.0 / (cos_inner - cos_outer).max(1e-3);
+                let cone = ((direction.dot(-to_light) - cos_outer) * scale).clamp(0.0, 1.0);
+                if cone == 0.0 {
+                    return None;
+                }
+                Some((to_light, color * intensity * attenuation * cone * cone))
+            }
+        }
+    }
+}
+
+// inverse square falloff, smoothly windowed to zero at the range
+fn distance_attenuation(to_light: Vec3, range: Option<f32>) -> Option<(Vec3, f32)> {
+    let distance_squared = to_light.length_squared().max(1e-8);
+    let window = match range {
+        Some(range) => {
+            let ratio = distance_squared / (range * range);
+            (1.0 - ratio * ratio).clamp(0.0, 1.0).powi(2)
+        }
+        None => 1.0,
+    };
+    if window == 0.0 {
+        return None;
+    }
+    Some((to_light.normalize(), window / distance_squared))
+}
@@ -1,18 +1,14 @@
-mod camera;
-mod raster;
-mod tests;
-mod texture;
-mod transform;
-mod utils;
-
-use crate::camera::Camera;
-use crate::texture::Texture;
-use crate::transform::Transform;
 use minifb::{Key, Window, WindowOptions};
-use raster::Screen;
+use rusterizer::camera::Camera;
+use rusterizer::demos::*;
+use rusterizer::error::Error;
+use rusterizer::headless::{self, HeadlessOptions};
+use rusterizer::raster::Screen;
+use rusterizer::scene::Scene;
+use rusterizer::transform::Transform;
+use rusterizer::utils::*;
 use std::path::Path;
-use tests::tests::*;
-use utils::utils::*;
+use std::process::ExitCode;
 
 const WIDTH: usize = 512;
 const HEIGHT: usize = 512;
@@ -37,7 +33,39 @@ pub fn process_camera_inputs(window: &Window, camera: &mut Camera) {
         + camera.transform.forward() * camera.speed * axis.y;
 }
 
-fn main() {
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("render") => run_headless(&args[1..]),
+        Some(_) => {
+            eprintln!("{}", headless::USAGE);
+            ExitCode::FAILURE
+        }
+        None => match run_window() {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => {
+                eprintln!("{}", e);
+                ExitCode::FAILURE
+            }
+        },
+    }
+}
+
+fn run_headless(args: &[String]) -> ExitCode {
+    let result = HeadlessOptions::parse(args).and_then(|options| headless::render(&options));
+    match result {
+        Ok(_) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}\n{}", e, headless::USAGE);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run_window() -> Result<(), Error> {
+    let _mesh_teapot = load_gltf(Path::new("assets/gltf/teapot.gltf"))?;
+    let _helmet = Scene::load(Path::new("assets/gltf/DamagedHelmet.gltf"))?;
+
     let mut screen = Screen::create(WIDTH, HEIGHT);
 
     let mut window = Window::new("Rusterizer", WIDTH, HEIGHT, WindowOptions::default())
@@ -53,16 +81,12 @@ fn main() {
     let mut camera = Camera {
         far_plane: 100.0,
         near_plane: 0.1,
-        aspect_ratio: aspect_ratio,
+        aspect_ratio,
         transform: Transform::from_translation(glam::vec3(0.0, 0.0, 5.0)),
         speed: 0.5,
         ..Default::default()
     };
 
-    let _texture = Texture::load(Path::new("assets/gltf/Default_albedo.jpg"));
-    let _mesh_teapot = load_gltf(Path::new("assets/gltf/teapot.gltf"));
-    let _mesh_helmet = load_gltf(Path::new("assets/gltf/DamagedHelmet.gltf"));
-
     while window.is_open() && !window.is_key_down(Key::Escape) {
         screen.clear();
         process_camera_inputs(&window, &mut camera);
@@ -82,10 +106,14 @@ fn main() {
         //_test_textured_cube(&mut screen, &mut rot);
         //_test_camera_inputs(&mut screen, &mut rot, &camera);
         //_test_gltf(&mut screen, &mut rot, &camera, &_mesh_teapot);
-        _test_gltf_textured(&mut screen, &mut rot, &camera, &_mesh_helmet, &_texture);
+        //_test_gltf_textured(&mut screen, &mut rot, &camera, &_helmet);
+        _test_gltf_pbr(&mut screen, &mut rot, &camera, &_helmet);
+        screen.present();
 
         window
             .update_with_buffer(&screen.data, WIDTH, HEIGHT)
             .unwrap();
     }
+
+    Ok(())
 }
@@ -0,0 +1,138 @@
+use crate::texture::{Sampler, Texture};
+use glam::{Vec2, Vec3, Vec4};
+
+// a texture of a material and how to sample it
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TextureBinding {
+    // index into the textures of the scene
+    pub texture: usize,
+    pub sampler: Sampler,
+    // the uv set used for the lookup, only set 0 is loaded at the moment
+    pub tex_coord: u32,
+}
+
+impl TextureBinding {
+    fn from_gltf(texture: gltf::Texture, tex_coord: u32) -> Self {
+        Self {
+            texture: texture.source().index(),
+            sampler: Sampler::from_gltf(&texture.sampler()),
+            tex_coord,
+        }
+    }
+
+    pub fn sample(&self, textures: &[Texture], uv: Vec2, ddx: Vec2, ddy: Vec2) -> Vec4 {
+        textures[self.texture].sample(&self.sampler, uv, ddx, ddy)
+    }
+}
+
+// how the alpha of the base color is used
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum AlphaMode {
+    // alpha is ignored
+    #[default]
+    Opaque,
+    // cutouts, fragments with an alpha below the cutoff are discarded
+    Mask,
+    // blended over the surfaces behind, in a separate pass after the opaque ones
+    Blend,
+}
+
+impl AlphaMode {
+    fn from_gltf(mode: gltf::material::AlphaMode) -> Self {
+        match mode {
+            gltf::material::AlphaMode::Opaque => Self::Opaque,
+            gltf::material::AlphaMode::Mask => Self::Mask,
+            gltf::material::AlphaMode::Blend => Self::Blend,
+        }
+    }
+}
+
+// glTF metallic-roughness material, the factors are multiplied with their textures
+#[derive(Debug, Clone, PartialEq)]
+pub struct Material {
+    pub name: Option<String>,
+    pub base_color_factor: Vec4,
+    pub base_color_texture: Option<TextureBinding>,
+    pub metallic_factor: f32,
+    pub roughness_factor: f32,
+    // roughness in the green channel, metalness in the blue one
+    pub metallic_roughness_texture: Option<TextureBinding>,
+    // tangent space normals, the xy components are multiplied with the scale
+    pub normal_texture: Option<TextureBinding>,
+    pub normal_scale: f32,
+    // ambient occlusion in the red channel, blended in by the strength
+    pub occlusion_texture: Option<TextureBinding>,
+    pub occlusion_strength: f32,
+    pub emissive_factor: Vec3,
+    pub emissive_texture: Option<TextureBinding>,
+    pub alpha_mode: AlphaMode,
+    // only used by AlphaMode::Mask
+    pub alpha_cutoff: f32,
+}
+
+// the glTF default material
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            name: None,
+            base_color_factor: Vec4::ONE,
+            base_color_texture: None,
+            metallic_factor: 1.0,
+            roughness_factor: 1.0,
+            metallic_roughness_texture: None,
+            normal_texture: None,
+            normal_scale: 1.0,
+            occlusion_texture: None,
+            occlusion_strength: 1.0,
+            emissive_factor: Vec3::ZERO,
+            emissive_texture: None,
+            alpha_mode: AlphaMode::Opaque,
+            alpha_cutoff: 0.5,
+        }
+    }
+}
+
+impl Material {
+    pub fn from_gltf(material: &gltf::Material) -> Self {
+        let pbr = material.pbr_metallic_roughness();
+        let info =
+            |info: gltf::texture::Info| TextureBinding::from_gltf(info.texture(), info.tex_coord());
+        let normal = material.normal_texture();
+        let occlusion = material.occlusion_texture();
+        Self {
+            name: material.name().map(String::from),
+            base_color_factor: Vec4::from(pbr.base_color_factor()),
+            base_color_texture: pbr.base_color_texture().map(info),
+            metallic_factor: pbr.metallic_factor(),
+            roughness_factor: pbr.roughness_factor(),
+            metallic_roughness_texture: pbr.metallic_roughness_texture().map(info),
+            normal_texture: normal
+                .as_ref()
+                .map(|normal| TextureBinding::from_gltf(normal.texture(), normal.tex_coord())),
+            normal_scale: normal.as_ref().map_or(1.0, |normal| normal.scale()),
+            occlusion_texture: occlusion.as_ref().map(|occlusion| {
+                TextureBinding::from_gltf(occlusion.texture(), occlusion.tex_coord())
+            }),
+            occlusion_strength: occlusion
+                .as_ref()
+                .map_or(1.0, |occlusion| occlusion.strength()),
+            emissive_factor: Vec3::from(material.emissive_factor()),
+            emissive_texture: material.emissive_texture().map(info),
+            alpha_mode: AlphaMode::from_gltf(material.alpha_mode()),
+            alpha_cutoff: material.alpha_cutoff().unwrap_or(0.5),
+        }
+    }
+
+    // every texture the material samples
+    pub fn textures_mut(&mut self) -> impl Iterator<Item = &mut TextureBinding> {
+        [
+            &mut self.base_color_texture,
+            &mut self.metallic_roughness_texture,
+            &mut self.normal_texture,
+            &mut self.occlusion_texture,
+            &mut self.emissive_texture,
+        ]
+        .into_iter()
+        .flatten()
+    }
+}
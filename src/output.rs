@@ -0,0 +1,87 @@
+use crate::utils::*;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+// writes packed 0RGB pixels as an 8-bit RGB png
+pub fn write_png(path: &Path, width: usize, height: usize, pixels: &[u32]) -> io::Result<()> {
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(file, width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&to_rgb_bytes(pixels))?;
+    Ok(())
+}
+
+// writes packed 0RGB pixels as a binary (P6) ppm
+pub fn write_ppm(path: &Path, width: usize, height: usize, pixels: &[u32]) -> io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    write!(file, "P6\n{} {}\n255\n", width, height)?;
+    file.write_all(&to_rgb_bytes(pixels))?;
+    file.flush()
+}
+
+// picks the encoder from the file extension, png is the default
+pub fn write_image(path: &Path, width: usize, height: usize, pixels: &[u32]) -> io::Result<()> {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) if ext.eq_ignore_ascii_case("ppm") => write_ppm(path, width, height, pixels),
+        _ => write_png(path, width, height, pixels),
+    }
+}
+
+// remaps the finite depth values to grayscale, near is black and far/empty is white
+pub fn depth_to_pixels(depth: &[f32]) -> Vec<u32> {
+    let (min, max) = depth
+        .iter()
+        .filter(|z| z.is_finite())
+        .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), z| {
+            (min.min(*z), max.max(*z))
+        });
+    let range = if max > min { max - min } else { 1.0 };
+
+    depth
+        .iter()
+        .map(|z| {
+            if z.is_finite() {
+                let value = ((z - min) / range * 255.0) as u8;
+                from_u8_rgb(value, value, value)
+            } else {
+                from_u8_rgb(255, 255, 255)
+            }
+        })
+        .collect()
+}
+
+fn to_rgb_bytes(pixels: &[u32]) -> Vec<u8> {
+    pixels
+        .iter()
+        .flat_map(|pixel| {
+            let (r, g, b) = from_u32_u8(*pixel);
+            [r, g, b]
+        })
+        .collect()
+}
+
+// reads an 8-bit rgb(a) png back into packed 0RGB pixels
+pub fn read_png(path: &Path) -> io::Result<(usize, usize, Vec<u32>)> {
+    let mut decoder = png::Decoder::new(File::open(path)?);
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let mut reader = decoder.read_info()?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer)?;
+
+    let channels = info.color_type.samples();
+    if channels < 3 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "expected an rgb or rgba png",
+        ));
+    }
+    let pixels = buffer[..info.buffer_size()]
+        .chunks_exact(channels)
+        .map(|texel| from_u8_rgb(texel[0], texel[1], texel[2]))
+        .collect();
+    Ok((info.width as usize, info.height as usize, pixels))
+}
@@ -1,69 +1,279 @@
-use crate::texture::Texture;
-use crate::utils::{geometry::*, utils::*};
-use glam::{Mat4, Vec2, Vec3, Vec4Swizzles};
+use crate::blend::{BlendMode, OitTexel};
+use crate::clip::{outcode, ClippedPolygon};
+use crate::color::OutputSettings;
+use crate::edge::{TriangleEdges, QUAD_LANES};
+use crate::output::{depth_to_pixels, write_image};
+use crate::shader::{Fragment, FragmentShader, VertexShader};
+use crate::stencil::StencilState;
+use crate::tile::{Tile, TileGrid, Workers};
+use crate::utils::{geometry::*, *};
+use glam::{Mat3, UVec3, Vec2, Vec3, Vec4, Vec4Swizzles};
+use std::io;
+use std::path::Path;
 
 pub struct Screen {
     pub width: usize,
     pub height: usize,
+    // presentation buffer of packed 0RGB pixels, filled from the hdr buffer by `present`
     pub data: Vec<u32>,
+    // linear RGBA render target, colors aren't limited to [0, 1]
+    pub hdr_buffer: Vec<Vec4>,
+    // how the hdr buffer is mapped to the presentation buffer
+    pub output: OutputSettings,
     pub z_buffer: Vec<f32>,
+    // value of the z buffer after a clear, infinity unless the depth test is reversed
+    pub depth_clear_value: f32,
+    pub stencil_buffer: Vec<u8>,
+    pub stencil_clear_value: u8,
+    // accumulated weighted blended transparency, allocated by the first draw using it
+    pub oit_buffer: Vec<OitTexel>,
+    pub settings: RenderSettings,
+    // applies to all following draws
+    pub state: DrawState,
+    // kept between draws, see `RenderSettings::threads`
+    workers: Workers,
 }
 
-pub enum ClipResult {
-    None,
-    One(Triangle),
-    Two((Triangle, Triangle)),
+#[derive(Debug, Copy, Clone)]
+pub struct RenderSettings {
+    // tints every triangle produced by clipping, cycling through green, blue and red
+    pub debug_clipping: bool,
+    // number of threads rasterizing tiles, 1 rasterizes on the calling thread
+    pub threads: usize,
+    // width and height of the screen tiles in pixels
+    pub tile_size: usize,
 }
 
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            debug_clipping: false,
+            threads: std::thread::available_parallelism().map_or(1, |threads| threads.get()),
+            tile_size: 64,
+        }
+    }
+}
+
+// Comparison of a new value against the one in a buffer, the test passes when
+// `new <op> stored` is true
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum CompareFunction {
+    Never,
+    #[default]
+    Less,
+    LessEqual,
+    Equal,
+    GreaterEqual,
+    Greater,
+    NotEqual,
+    Always,
+}
+
+impl CompareFunction {
+    pub fn passes<T: PartialOrd>(self, new: T, stored: T) -> bool {
+        match self {
+            Self::Never => false,
+            Self::Less => new < stored,
+            Self::LessEqual => new <= stored,
+            Self::Equal => new == stored,
+            Self::GreaterEqual => new >= stored,
+            Self::Greater => new > stored,
+            Self::NotEqual => new != stored,
+            Self::Always => true,
+        }
+    }
+}
+
+// Fixed function state of a draw, changed on the screen between draws
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DrawState {
+    pub blend: BlendMode,
+    // Less keeps the closest surface, LessEqual also lets surfaces at the same depth
+    // through, e.g. skyboxes at the far plane or decals, Always draws overlays on top
+    pub depth_compare: CompareFunction,
+    // transparent surfaces are tested against the z buffer without writing it, so
+    // that the surfaces behind them are still drawn
+    pub depth_write: bool,
+    // no stencil test when None
+    pub stencil: Option<StencilState>,
+    // fragments with a lower alpha are discarded, for cutouts like foliage and fences
+    pub alpha_cutoff: Option<f32>,
+}
+
+impl Default for DrawState {
+    fn default() -> Self {
+        Self {
+            blend: BlendMode::Opaque,
+            depth_compare: CompareFunction::Less,
+            depth_write: true,
+            stencil: None,
+            alpha_cutoff: None,
+        }
+    }
+}
+
+impl DrawState {
+    pub fn transparent(blend: BlendMode) -> Self {
+        Self {
+            blend,
+            depth_write: false,
+            ..Default::default()
+        }
+    }
+
+    // for cameras with `reversed_z`, closer surfaces have a greater depth
+    pub fn reversed_z(self) -> Self {
+        let depth_compare = match self.depth_compare {
+            CompareFunction::Less => CompareFunction::Greater,
+            CompareFunction::LessEqual => CompareFunction::GreaterEqual,
+            CompareFunction::GreaterEqual => CompareFunction::LessEqual,
+            CompareFunction::Greater => CompareFunction::Less,
+            compare => compare,
+        };
+        Self {
+            depth_compare,
+            ..self
+        }
+    }
+
+    // opaque, with the fragments below the cutoff discarded
+    pub fn cutout(alpha_cutoff: f32) -> Self {
+        Self {
+            alpha_cutoff: Some(alpha_cutoff),
+            ..Default::default()
+        }
+    }
+}
+
+const CLIPPING_DEBUG_COLORS: [Vec3; 3] = [
+    Vec3::new(0.0, 1.0, 0.0),
+    Vec3::new(0.0, 0.0, 1.0),
+    Vec3::new(1.0, 0.0, 0.0),
+];
+
 impl Screen {
     // creates necessary buffers
     pub fn create(screen_width: usize, screen_height: usize) -> Self {
+        Self::create_with_depth_clear_value(screen_width, screen_height, f32::INFINITY)
+    }
+
+    // the z buffer starts out cleared to the value, e.g. 0 for reversed z
+    pub fn create_with_depth_clear_value(
+        screen_width: usize,
+        screen_height: usize,
+        depth_clear_value: f32,
+    ) -> Self {
         Self {
             width: screen_width,
             height: screen_height,
             data: vec![0; screen_width * screen_height],
-            z_buffer: vec![f32::INFINITY; screen_width * screen_height],
+            hdr_buffer: vec![Vec4::ZERO; screen_width * screen_height],
+            output: OutputSettings::default(),
+            z_buffer: vec![depth_clear_value; screen_width * screen_height],
+            depth_clear_value,
+            stencil_buffer: vec![0; screen_width * screen_height],
+            stencil_clear_value: 0,
+            oit_buffer: Vec::new(),
+            settings: RenderSettings::default(),
+            state: DrawState::default(),
+            workers: Workers::default(),
+        }
+    }
+
+    // Screen with only a z buffer, for depth-only passes like shadow maps. Draws run the
+    // depth test and write, and the fragment shaders for the alpha cutoff, but there is no
+    // color, stencil or transparency to write.
+    pub fn create_depth_only(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            z_buffer: vec![f32::INFINITY; width * height],
+            ..Self::create(0, 0)
+        }
+    }
+
+    // depth-only screen rasterizing on the threads of this one, with the same settings
+    pub fn depth_target(&self, width: usize, height: usize) -> Self {
+        Self {
+            settings: self.settings,
+            workers: self.workers.clone(),
+            ..Self::create_depth_only(width, height)
         }
     }
 
+    pub fn is_depth_only(&self) -> bool {
+        self.hdr_buffer.is_empty()
+    }
+
     // clears the screen
     pub fn clear(&mut self) {
         self.data.fill(0);
-        self.z_buffer.fill(f32::INFINITY);
+        self.hdr_buffer.fill(Vec4::ZERO);
+        self.z_buffer.fill(self.depth_clear_value);
+        self.stencil_buffer.fill(self.stencil_clear_value);
+        self.oit_buffer.clear();
+    }
+
+    // composites the weighted blended draws over the hdr buffer and empties the
+    // transparency buffer for the next frame
+    pub fn resolve_transparency(&mut self) {
+        for (color, texel) in self.hdr_buffer.iter_mut().zip(&self.oit_buffer) {
+            *color = texel.resolve(*color);
+        }
+        self.oit_buffer.clear();
+    }
+
+    // maps the hdr buffer to the presentation buffer with the output settings, once
+    // at the end of the frame after all draws
+    pub fn present(&mut self) {
+        for (pixel, color) in self.data.iter_mut().zip(&self.hdr_buffer) {
+            *pixel = self.output.present(color.xyz());
+        }
+    }
+
+    // writes the color buffer to a png or ppm file
+    pub fn save_color(&self, path: &Path) -> io::Result<()> {
+        write_image(path, self.width, self.height, &self.data)
+    }
+
+    // writes the z buffer as a grayscale png or ppm file
+    pub fn save_depth(&self, path: &Path) -> io::Result<()> {
+        let pixels = depth_to_pixels(&self.z_buffer);
+        write_image(path, self.width, self.height, &pixels)
     }
 
     // outputs indicies as a color
     pub fn _output_index(&mut self) {
-        for (i, pixel) in self.data.iter_mut().enumerate() {
+        for (i, pixel) in self.hdr_buffer.iter_mut().enumerate() {
             let index_as_color = i as f32 / (self.width * self.height) as f32;
-            let index_as_color = (index_as_color * 255.0) as u8;
-            *pixel = from_u8_rgb(index_as_color, index_as_color, index_as_color)
+            *pixel = Vec3::splat(index_as_color).extend(1.0)
         }
     }
 
     // outputs coords as a color
     pub fn _output_coords(&mut self) {
-        for (i, pixel) in self.data.iter_mut().enumerate() {
+        for (i, pixel) in self.hdr_buffer.iter_mut().enumerate() {
             let coords = from_index_coords(i, self.width);
-            *pixel = from_u8_rgb(
-                (coords.0 as f32 * 255.0 / self.width as f32) as u8,
-                (coords.1 as f32 * 255.0 / self.height as f32) as u8,
-                0,
+            *pixel = glam::vec4(
+                coords.0 as f32 / self.width as f32,
+                coords.1 as f32 / self.height as f32,
+                0.0,
+                1.0,
             )
         }
     }
 
     // tests edge function with green and red colors
     pub fn _output_edge_function(&mut self, v0: Vec2, v1: Vec2) {
-        for (i, pixel) in self.data.iter_mut().enumerate() {
+        for (i, pixel) in self.hdr_buffer.iter_mut().enumerate() {
             let coords = from_index_coords(i, self.width);
-            let mut color: u32 = 0;
+            let mut color = Vec4::W;
             let edge_func = edge_function(glam::vec2(coords.0 as f32, coords.1 as f32), v0, v1);
 
             if edge_func > 0.0 {
-                color = from_u8_rgb(255, 0, 0);
+                color = glam::vec4(1.0, 0.0, 0.0, 1.0);
             } else if edge_func < 0.0 {
-                color = from_u8_rgb(0, 255, 0);
+                color = glam::vec4(0.0, 1.0, 0.0, 1.0);
             }
 
             *pixel = color;
@@ -72,33 +282,29 @@ impl Screen {
 
     // tests triangle with green and red colors
     pub fn _output_triangle1(&mut self, v0: Vec2, v1: Vec2, v2: Vec2) {
-        for (i, pixel) in self.data.iter_mut().enumerate() {
+        for (i, pixel) in self.hdr_buffer.iter_mut().enumerate() {
             let coords = from_index_coords(i, self.width);
             let ef0 = edge_function(glam::vec2(coords.0 as f32, coords.1 as f32), v1, v2);
             let ef1 = edge_function(glam::vec2(coords.0 as f32, coords.1 as f32), v2, v0);
             let ef2 = edge_function(glam::vec2(coords.0 as f32, coords.1 as f32), v0, v1);
 
             if ef0 > 0.0 && ef1 > 0.0 && ef2 > 0.0 || ef0 < 0.0 && ef1 < 0.0 && ef2 < 0.0 {
-                *pixel = from_u8_rgb(0, 255, 0);
+                *pixel = glam::vec4(0.0, 1.0, 0.0, 1.0);
             } else {
-                *pixel = from_u8_rgb(255, 0, 0);
+                *pixel = glam::vec4(1.0, 0.0, 0.0, 1.0);
             }
         }
     }
 
     // tests triangle with edge function outputs
     pub fn _output_triangle2(&mut self, v0: Vec2, v1: Vec2, v2: Vec2) {
-        for (i, pixel) in self.data.iter_mut().enumerate() {
+        for (i, pixel) in self.hdr_buffer.iter_mut().enumerate() {
             let coords = from_index_coords(i, self.width);
             let ef0 = edge_function(glam::vec2(coords.0 as f32, coords.1 as f32), v0, v1);
             let ef1 = edge_function(glam::vec2(coords.0 as f32, coords.1 as f32), v1, v2);
             let ef2 = edge_function(glam::vec2(coords.0 as f32, coords.1 as f32), v2, v0);
 
-            *pixel = from_u8_rgb(
-                (ef0 * 255.0) as u8,
-                (ef1 * 255.0) as u8,
-                (ef2 * 255.0) as u8,
-            );
+            *pixel = glam::vec4(ef0, ef1, ef2, 1.0);
         }
     }
 
@@ -106,7 +312,7 @@ impl Screen {
     pub fn _output_barycentric(&mut self, v0: &Vertex, v1: &Vertex, v2: &Vertex) {
         let triangle_area = edge_function(v0.position.xy(), v1.position.xy(), v2.position.xy());
 
-        for (i, pixel) in self.data.iter_mut().enumerate() {
+        for (i, pixel) in self.hdr_buffer.iter_mut().enumerate() {
             let coords = from_index_coords(i, self.width);
             let coords = glam::vec2(coords.0 as f32, coords.1 as f32) + 0.5;
 
@@ -122,7 +328,7 @@ impl Screen {
             if let Some(bary) = barycentric {
                 color = bary.x * v0.color + bary.y * v1.color + bary.z * v2.color;
             }
-            *pixel = from_rgb_u32(color);
+            *pixel = color.extend(1.0);
         }
     }
 
@@ -137,15 +343,15 @@ impl Screen {
 
         if bb.left >= viewport_size.x
             || bb.right < 0.0
-            || bb.bottom >= viewport_size.y
-            || bb.top < 0.0
+            || bb.top >= viewport_size.y
+            || bb.bottom < 0.0
         {
             None
         } else {
             let left = bb.left.max(0.0);
             let right = bb.right.min(viewport_size.x - 1.0);
-            let bottom = bb.bottom.max(0.0);
-            let top = bb.top.min(viewport_size.y - 1.0);
+            let top = bb.top.max(0.0);
+            let bottom = bb.bottom.min(viewport_size.y - 1.0);
 
             Some(BoundingBox2D {
                 left,
@@ -156,172 +362,75 @@ impl Screen {
         }
     }
 
-    pub fn view_frustum_culling(triangle: &Triangle) -> bool {
-        // X
-        if triangle.v0.position.x > triangle.v0.position.w
-            && triangle.v1.position.x > triangle.v1.position.w
-            && triangle.v2.position.x > triangle.v2.position.w
-        {
-            return true;
-        }
-        if triangle.v0.position.x < -triangle.v0.position.w
-            && triangle.v1.position.x < -triangle.v1.position.w
-            && triangle.v2.position.x < -triangle.v2.position.w
-        {
-            return true;
-        }
-        //Y
-        if triangle.v0.position.y > triangle.v0.position.w
-            && triangle.v1.position.y > triangle.v1.position.w
-            && triangle.v2.position.y > triangle.v2.position.w
-        {
-            return true;
-        }
-        if triangle.v0.position.y < -triangle.v0.position.w
-            && triangle.v1.position.y < -triangle.v1.position.w
-            && triangle.v2.position.y < -triangle.v2.position.w
-        {
-            return true;
-        }
-        //Z
-        if triangle.v0.position.z > triangle.v0.position.w
-            && triangle.v1.position.z > triangle.v1.position.w
-            && triangle.v2.position.z > triangle.v2.position.w
-        {
-            return true;
-        }
-        if triangle.v0.position.z < 0.0
-            && triangle.v1.position.z < 0.0
-            && triangle.v2.position.z < 0.0
-        {
-            return true;
-        }
-
-        false
+    // true when all vertices are outside of the same frustum plane
+    pub fn view_frustum_culling<V: Varyings>(triangle: &Triangle<V>) -> bool {
+        outcode(triangle.v0.position())
+            & outcode(triangle.v1.position())
+            & outcode(triangle.v2.position())
+            != 0
     }
 
-    pub fn clip_triangle_two(triangle: &Triangle) -> (Triangle, Triangle) {
-        // calculate alpha values for getting adjusted vertices
-        let alpha_a = (-triangle.v0.position.z) / (triangle.v1.position.z - triangle.v0.position.z);
-        let alpha_b = (-triangle.v0.position.z) / (triangle.v2.position.z - triangle.v0.position.z);
-
-        // interpolate to get v0a and v0b
-        let v0_a = lerp(triangle.v0, triangle.v1, alpha_a);
-        let v0_b = lerp(triangle.v0, triangle.v2, alpha_b);
-
-        // draw triangles
-        let mut result_a = *triangle;
-        let mut result_b = *triangle;
-
-        result_a.v0 = v0_a;
-
-        result_b.v0 = v0_a;
-        result_b.v1 = v0_b;
-
-        let green = Vec3::new(0.0, 1.0, 0.0);
-        let blue = Vec3::new(0.0, 0.0, 1.0);
-
-        result_a.v0.color = green;
-        result_a.v1.color = green;
-        result_a.v2.color = green;
-        result_b.v0.color = blue;
-        result_b.v1.color = blue;
-        result_b.v2.color = blue;
-
-        (result_a, result_b)
+    pub fn cull_triangle_backface<V: Varyings>(triangle: &Triangle<V>) -> bool {
+        // the determinant of the homogeneous 2d positions has the sign of the
+        // screen space winding, and stays valid for vertices behind the camera
+        let determinant = Mat3::from_cols(
+            triangle.v0.position().xyw(),
+            triangle.v1.position().xyw(),
+            triangle.v2.position().xyw(),
+        )
+        .determinant();
+        // counter-clockwise triangles are facing the camera
+        determinant <= 0.0
     }
 
-    pub fn clip_triangle_one(triangle: &Triangle) -> Triangle {
-        // calculate alpha values for getting adjusted vertices
-        let alpha_a = (-triangle.v0.position.z) / (triangle.v2.position.z - triangle.v0.position.z);
-        let alpha_b = (-triangle.v1.position.z) / (triangle.v2.position.z - triangle.v1.position.z);
-
-        // interpolate to get v0a and v0b
-        let mut v0 = lerp(triangle.v0, triangle.v2, alpha_a);
-        let mut v1 = lerp(triangle.v1, triangle.v2, alpha_b);
-
-        let mut v2 = triangle.v2;
-
-        let red = Vec3::new(1.0, 0.0, 0.0);
-
-        v0.color = red;
-        v1.color = red;
-        v2.color = red;
-
-        Triangle { v0, v1, v2 }
-    }
+    pub fn clip_cull_triangle<V: Varyings>(triangle: &Triangle<V>) -> Option<ClippedPolygon<V>> {
+        if Self::cull_triangle_backface(triangle) {
+            return None;
+        }
 
-    pub fn cull_triangle_backface(triangle: &Triangle) -> bool {
-        let normal = (triangle.v1.position.xyz() - triangle.v0.position.xyz())
-            .cross(triangle.v2.position.xyz() - triangle.v0.position.xyz());
-        // any is vertex valid
-        let view_dir = -Vec3::Z;
-        // also we don't care about normalizing
-        // if negative facing the camera
-        normal.dot(view_dir) >= 0.0
-    }
+        let outcodes = [
+            outcode(triangle.v0.position()),
+            outcode(triangle.v1.position()),
+            outcode(triangle.v2.position()),
+        ];
+        if outcodes[0] & outcodes[1] & outcodes[2] != 0 {
+            // completely outside one of the planes
+            return None;
+        }
 
-    pub fn clip_cull_triangle(triangle: &Triangle) -> ClipResult {
-        if Self::cull_triangle_backface(triangle) {
-            return ClipResult::None;
+        let polygon = ClippedPolygon::from_triangle(triangle);
+        let crossed_planes = outcodes[0] | outcodes[1] | outcodes[2];
+        if crossed_planes == 0 {
+            // no clipping necessary
+            return Some(polygon);
         }
-        if Self::view_frustum_culling(triangle) {
-            ClipResult::None
+
+        let polygon = polygon.clip(crossed_planes);
+        if polygon.is_empty() {
+            None
         } else {
-            // clipping routines
-            if triangle.v0.position.z < 0.0 {
-                if triangle.v1.position.z < 0.0 {
-                    ClipResult::One(Self::clip_triangle_one(triangle))
-                } else if triangle.v2.position.z < 0.0 {
-                    ClipResult::One(Self::clip_triangle_one(
-                        &triangle.reorder(VerticesOrder::ACB),
-                    ))
-                } else {
-                    ClipResult::Two(Self::clip_triangle_two(
-                        &triangle.reorder(VerticesOrder::ACB),
-                    ))
-                }
-            } else if triangle.v1.position.z < 0.0 {
-                if triangle.v2.position.z < 0.0 {
-                    ClipResult::One(Self::clip_triangle_one(
-                        &triangle.reorder(VerticesOrder::BCA),
-                    ))
-                } else {
-                    ClipResult::Two(Self::clip_triangle_two(
-                        &triangle.reorder(VerticesOrder::BAC),
-                    ))
-                }
-            } else if triangle.v2.position.z < 0.0 {
-                ClipResult::Two(Self::clip_triangle_two(
-                    &triangle.reorder(VerticesOrder::CBA),
-                ))
-            } else {
-                // no near clipping necessary
-                //return original
-                ClipResult::One(*triangle)
-            }
+            Some(polygon)
         }
     }
 
-    // rasterize textured triangle
-    pub fn raster_clipped_triangle(&mut self, clip_triangle: &Triangle, texture: Option<&Texture>) {
+    // projects a clipped triangle to the screen and prepares it for rasterization
+    pub fn setup_clipped_triangle<V: Varyings>(
+        &self,
+        clip_triangle: &Triangle<V>,
+        tint: Option<Vec3>,
+    ) -> Option<TriangleSetup<V>> {
         let viewport_size = glam::vec2(self.width as f32, self.height as f32);
 
-        let rec0 = 1.0 / clip_triangle.v0.position.w;
-        let rec1 = 1.0 / clip_triangle.v1.position.w;
-        let rec2 = 1.0 / clip_triangle.v2.position.w;
+        let rec0 = 1.0 / clip_triangle.v0.position().w;
+        let rec1 = 1.0 / clip_triangle.v1.position().w;
+        let rec2 = 1.0 / clip_triangle.v2.position().w;
 
-        // This would be the output of the vertex shader (clip space)
+        // This is the output of the vertex shader (clip space)
         // then we perform perspective division to transform in ndc
         // now x,y,z componend of ndc are between -1 and 1
-        let ndc0 = clip_triangle.v0.position * rec0;
-        let ndc1 = clip_triangle.v1.position * rec1;
-        let ndc2 = clip_triangle.v2.position * rec2;
-
-        // perspective division on all attributes
-        let v0 = clip_triangle.v0 * rec0;
-        let v1 = clip_triangle.v1 * rec1;
-        let v2 = clip_triangle.v2 * rec2;
+        let ndc0 = clip_triangle.v0.position() * rec0;
+        let ndc1 = clip_triangle.v1.position() * rec1;
+        let ndc2 = clip_triangle.v2.position() * rec2;
 
         // screeen coordinates remapped to window
         let sc0 = glam::vec2(
@@ -337,84 +446,306 @@ impl Screen {
             map_to_range(-ndc2.y, -1.0, 1.0, 0.0, viewport_size.y),
         );
 
-        if let Some(bb) = Self::triangle_screen_bounding_box(&[sc0, sc1, sc2], viewport_size) {
-            for y in (bb.top as usize)..=bb.bottom as usize {
-                for x in (bb.left as usize)..=bb.right as usize {
-                    let coords = glam::vec2(x as f32, y as f32) + 0.5;
-                    let pixel_id = from_coords_index(coords, viewport_size.x as usize);
-                    let area = edge_function(sc0, sc1, sc2);
-
-                    if let Some(bary) = barycentric_coordinates(coords, sc0, sc1, sc2, area) {
-                        let correction = bary.x * rec0 + bary.y * rec1 + bary.z * rec2;
-                        let correction = 1.0 / correction;
-                        let depth = bary.x * ndc0.z + bary.y * ndc1.z + bary.z * ndc2.z;
-                        if depth < self.z_buffer[pixel_id] {
-                            self.z_buffer[pixel_id] = depth;
-
-                            let normal =
-                                bary.x * v0.normal + bary.y * v1.normal + bary.z * v2.normal;
-                            let normal = normal * correction;
-                            let n_dot_l = normal.dot(Vec3::ONE.normalize());
-
-                            let color = bary.x * v0.color + bary.y * v1.color + bary.z * v2.color;
-                            let mut output = color * correction;
-                            if let Some(tex) = texture {
-                                let tex_coords = bary.x * v0.uv + bary.y * v1.uv + bary.z * v2.uv;
-                                let tex_coords = tex_coords * correction;
-                                output = tex.sample_at_uv_rgb(tex_coords.x, tex_coords.y);
-                            }
-
-                            let ambient = glam::vec3(0.2, 0.2, 0.2);
-                            output = output * n_dot_l + ambient;
-                            self.data[pixel_id] = from_rgb_u32(output);
+        let bounds = Self::triangle_screen_bounding_box(&[sc0, sc1, sc2], viewport_size)?;
+        let edges = TriangleEdges::new(&[sc0, sc1, sc2])?;
+        Some(TriangleSetup {
+            screen: [sc0, sc1, sc2],
+            depth: [ndc0.z, ndc1.z, ndc2.z],
+            rec_w: [rec0, rec1, rec2],
+            varyings: [clip_triangle.v0, clip_triangle.v1, clip_triangle.v2],
+            edges,
+            bounds,
+            tint,
+        })
+    }
+
+    // rasterizes the part of the triangle inside of the tile
+    pub fn raster_triangle_in_tile<V: Varyings, FS: FragmentShader<V>>(
+        tile: &mut Tile,
+        triangle: &TriangleSetup<V>,
+        fragment_shader: &FS,
+        state: &DrawState,
+    ) {
+        let Some((left, right, top, bottom)) = tile.rect.intersect(&triangle.bounds) else {
+            return;
+        };
+        let edges = &triangle.edges;
+        let [rec0, rec1, rec2] = triangle.rec_w;
+        let [z0, z1, z2] = triangle.depth;
+        let [v0, v1, v2] = triangle.varyings;
+        let (v01, v02) = (v1 - v0, v2 - v0);
+
+        // Pixels are processed in 2x2 quads aligned to even coordinates, four at a time.
+        // Lanes outside of the triangle or the tile are still interpolated to provide
+        // the derivatives, but they are never written.
+        let (quad_left, quad_top) = (left & !1, top & !1);
+        let mut row_weights = edges.evaluate_quad(quad_left, quad_top);
+        for y in (quad_top..=bottom).step_by(2) {
+            let mut weights = row_weights;
+            row_weights += edges.quad_step_y;
+            for x in (quad_left..=right).step_by(2) {
+                let quad_weights = weights;
+                weights += edges.quad_step_x;
+
+                // lanes as bits, see QUAD_LANES
+                let mut mask = edges.quad_coverage(&quad_weights);
+                if x < left {
+                    mask &= !0b0101;
+                }
+                if x + 1 > right {
+                    mask &= !0b1010;
+                }
+                if y < top {
+                    mask &= !0b0011;
+                }
+                if y + 1 > bottom {
+                    mask &= !0b1100;
+                }
+                if mask == 0 {
+                    continue;
+                }
+
+                let [b0, b1, b2] = edges.quad_barycentric(&quad_weights);
+                let depth = b0 * z0 + b1 * z1 + b2 * z2;
+                for (lane, (dx, dy)) in QUAD_LANES.iter().enumerate() {
+                    if mask & (1 << lane) == 0 {
+                        continue;
+                    }
+                    let (row, column) = (y + dy - tile.rect.y0, x + dx - tile.rect.x0);
+                    if let Some(stencil_state) = &state.stencil {
+                        let stencil = &mut tile.stencil_rows[row][column];
+                        if !stencil_state.passes(*stencil) {
+                            *stencil = stencil_state.update(stencil_state.fail, *stencil);
+                            mask &= !(1 << lane);
+                            continue;
+                        }
+                    }
+                    if !state
+                        .depth_compare
+                        .passes(depth[lane], tile.depth_rows[row][column])
+                    {
+                        if let Some(stencil_state) = &state.stencil {
+                            let stencil = &mut tile.stencil_rows[row][column];
+                            *stencil = stencil_state.update(stencil_state.depth_fail, *stencil);
                         }
+                        mask &= !(1 << lane);
                     }
                 }
+                if mask == 0 {
+                    continue;
+                }
+
+                // perspective correct barycentric coordinates of v1 and v2
+                let (b1, b2) = (b1 * rec1, b2 * rec2);
+                let correction = (b0 * rec0 + b1 + b2).recip();
+                let (b1, b2) = (b1 * correction, b2 * correction);
+                let interpolate = |lane: usize| v0 + v01 * b1[lane] + v02 * b2[lane];
+                // the bottom right lane is only needed when it is written
+                let varyings = [
+                    interpolate(0),
+                    interpolate(1),
+                    interpolate(2),
+                    if mask & 0b1000 != 0 {
+                        interpolate(3)
+                    } else {
+                        v0
+                    },
+                ];
+                // coarse derivatives, shared by the whole quad
+                let ddx = varyings[1] - varyings[0];
+                let ddy = varyings[2] - varyings[0];
+
+                for (lane, (dx, dy)) in QUAD_LANES.iter().enumerate() {
+                    if mask & (1 << lane) == 0 {
+                        continue;
+                    }
+                    let (px, py) = (x + dx, y + dy);
+                    let (row, column) = (py - tile.rect.y0, px - tile.rect.x0);
+                    let fragment = Fragment {
+                        position: glam::vec2(px as f32, py as f32) + 0.5,
+                        depth: depth[lane],
+                        varyings: varyings[lane],
+                        ddx,
+                        ddy,
+                    };
+
+                    let mut output = fragment_shader.fragment(&fragment);
+                    // discarded fragments leave the z buffer alone, so cutouts don't occlude
+                    if state.alpha_cutoff.is_some_and(|cutoff| output.w < cutoff) {
+                        continue;
+                    }
+                    if state.depth_write {
+                        tile.depth_rows[row][column] = depth[lane];
+                    }
+                    if let Some(stencil_state) = &state.stencil {
+                        let stencil = &mut tile.stencil_rows[row][column];
+                        *stencil = stencil_state.update(stencil_state.pass, *stencil);
+                    }
+                    // depth-only screens have no color to write
+                    if tile.hdr_rows.is_empty() {
+                        continue;
+                    }
+                    if let Some(tint) = triangle.tint {
+                        output = output.xyz().lerp(tint, 0.5).extend(output.w);
+                    }
+                    if state.blend == BlendMode::WeightedBlended {
+                        tile.oit_rows[row][column].add(output, correction[lane]);
+                        continue;
+                    }
+                    let color = &mut tile.hdr_rows[row][column];
+                    *color = state.blend.blend(output, *color);
+                }
             }
         }
     }
 
-    pub fn raster_triangle(
+    // clips, culls and rasterizes triangles that are already in clip space.
+    // The screen is split into tiles and every tile rasterizes the triangles
+    // touching it in submission order, so the result doesn't depend on threading.
+    pub fn raster_clip_space_triangles<V: Varyings, FS: FragmentShader<V>>(
         &mut self,
-        vertices: &[&Vertex; 3],
-        texture: Option<&Texture>,
-        mvp: &Mat4,
-        model: &Mat4,
+        clip_triangles: impl Iterator<Item = Triangle<V>>,
+        fragment_shader: &FS,
     ) {
-        let cof_model = cofactor(model);
-        let triangle = Triangle {
-            v0: *vertices[0],
-            v1: *vertices[1],
-            v2: *vertices[2],
-        };
-        let mut clip_tri = triangle.transform(mvp);
-        clip_tri.v0.normal = (cof_model * clip_tri.v0.normal.extend(0.0)).xyz();
-        clip_tri.v1.normal = (cof_model * clip_tri.v1.normal.extend(0.0)).xyz();
-        clip_tri.v2.normal = (cof_model * clip_tri.v2.normal.extend(0.0)).xyz();
-
-        match Self::clip_cull_triangle(&clip_tri) {
-            ClipResult::None => {}
-            ClipResult::One(tri) => {
-                self.raster_clipped_triangle(&tri, texture);
+        let mut setups = Vec::new();
+        for clip_triangle in clip_triangles {
+            if let Some(polygon) = Self::clip_cull_triangle(&clip_triangle) {
+                let debug_clipping = self.settings.debug_clipping && polygon.was_clipped();
+                for (i, triangle) in polygon.triangles().enumerate() {
+                    let tint = debug_clipping
+                        .then(|| CLIPPING_DEBUG_COLORS[i % CLIPPING_DEBUG_COLORS.len()]);
+                    setups.extend(self.setup_clipped_triangle(&triangle, tint));
+                }
             }
-            ClipResult::Two(tri) => {
-                self.raster_clipped_triangle(&tri.0, texture);
-                self.raster_clipped_triangle(&tri.1, texture);
+        }
+        if setups.is_empty() {
+            return;
+        }
+        let mut state = self.state;
+        if self.is_depth_only() {
+            // nothing to blend into or to mark, only the depth test and write remain
+            state.blend = BlendMode::Opaque;
+            state.stencil = None;
+        }
+        if state.blend == BlendMode::WeightedBlended && self.oit_buffer.is_empty() {
+            self.oit_buffer = vec![OitTexel::default(); self.width * self.height];
+        }
+
+        // binning
+        let grid = TileGrid::new(self.width, self.height, self.settings.tile_size);
+        let mut bins: Vec<Vec<usize>> = vec![Vec::new(); grid.len()];
+        for (index, setup) in setups.iter().enumerate() {
+            for tile in grid.tiles_overlapping(&setup.bounds) {
+                bins[tile].push(index);
             }
         }
+
+        // only the tiles touched by the triangles are handed to the workers
+        let used: Vec<bool> = bins.iter().map(|bin| !bin.is_empty()).collect();
+        let tiles: Vec<Tile> = grid.split(
+            &used,
+            &mut self.hdr_buffer,
+            &mut self.z_buffer,
+            &mut self.stencil_buffer,
+            &mut self.oit_buffer,
+        );
+
+        let threads = self.settings.threads;
+        self.workers.for_each_tile(tiles, threads, |tile| {
+            for index in &bins[tile.index] {
+                let setup = &setups[*index];
+                Self::raster_triangle_in_tile(tile, setup, fragment_shader, &state);
+            }
+        });
+    }
+
+    pub fn raster_triangle<VS: VertexShader, FS: FragmentShader<VS::Output>>(
+        &mut self,
+        vertices: &[&VS::Input; 3],
+        vertex_shader: &VS,
+        fragment_shader: &FS,
+    ) {
+        let clip_triangle = Triangle::create(
+            vertex_shader.vertex(vertices[0]),
+            vertex_shader.vertex(vertices[1]),
+            vertex_shader.vertex(vertices[2]),
+        );
+        self.raster_clip_space_triangles(std::iter::once(clip_triangle), fragment_shader);
     }
 
-    pub fn raster_mesh(
+    pub fn raster_mesh<VS: VertexShader, FS: FragmentShader<VS::Output>>(
         &mut self,
-        mesh: &Mesh,
-        mvp: &Mat4,
-        model: &Mat4,
-        texture: Option<&Texture>,
+        mesh: &Mesh<VS::Input>,
+        vertex_shader: &VS,
+        fragment_shader: &FS,
     ) {
-        for indecies in mesh.get_triangles() {
-            let vertices = mesh.get_vertices_from_triangle(*indecies);
+        let clip_vertices = Self::shade_vertices(mesh, vertex_shader);
+        let clip_triangles = Self::clip_triangles(&clip_vertices, mesh.get_triangles());
+        self.raster_clip_space_triangles(clip_triangles, fragment_shader);
+    }
 
-            self.raster_triangle(&vertices, texture, mvp, model);
+    // draws every submesh with its own fragment shader, e.g. one per material
+    pub fn raster_submeshes<VS: VertexShader, FS: FragmentShader<VS::Output>>(
+        &mut self,
+        mesh: &Mesh<VS::Input>,
+        vertex_shader: &VS,
+        fragment_shader: impl Fn(&Submesh) -> FS,
+    ) {
+        let clip_vertices = Self::shade_vertices(mesh, vertex_shader);
+        for submesh in &mesh.submeshes {
+            self.raster_submesh(mesh, submesh, &clip_vertices, &fragment_shader(submesh));
         }
     }
+
+    // Draws a single submesh, e.g. when the submeshes of a scene are sorted. The vertices
+    // come from `shade_vertices`, so that the submeshes of a mesh are only shaded once.
+    pub fn raster_submesh<T, V: Varyings, FS: FragmentShader<V>>(
+        &mut self,
+        mesh: &Mesh<T>,
+        submesh: &Submesh,
+        clip_vertices: &[V],
+        fragment_shader: &FS,
+    ) {
+        let triangles = &mesh.get_triangles()[submesh.triangles.clone()];
+        let clip_triangles = Self::clip_triangles(clip_vertices, triangles);
+        self.raster_clip_space_triangles(clip_triangles, fragment_shader);
+    }
+
+    // shades every vertex once, triangles share them through the indices
+    pub fn shade_vertices<VS: VertexShader>(
+        mesh: &Mesh<VS::Input>,
+        vertex_shader: &VS,
+    ) -> Vec<VS::Output> {
+        mesh.get_vertices()
+            .iter()
+            .map(|vertex| vertex_shader.vertex(vertex))
+            .collect()
+    }
+
+    fn clip_triangles<'a, V: Copy>(
+        clip_vertices: &'a [V],
+        triangles: &'a [UVec3],
+    ) -> impl Iterator<Item = Triangle<V>> + 'a {
+        triangles.iter().map(|indices| {
+            Triangle::create(
+                clip_vertices[indices.x as usize],
+                clip_vertices[indices.y as usize],
+                clip_vertices[indices.z as usize],
+            )
+        })
+    }
+}
+
+// Triangle projected to the screen, ready to be rasterized in any tile
+pub struct TriangleSetup<V> {
+    // window coordinates of the vertices
+    pub screen: [Vec2; 3],
+    // normalized device depth of the vertices
+    pub depth: [f32; 3],
+    // reciprocal of the clip space w for perspective correction
+    pub rec_w: [f32; 3],
+    pub varyings: [V; 3],
+    pub edges: TriangleEdges,
+    pub bounds: BoundingBox2D,
+    pub tint: Option<Vec3>,
 }
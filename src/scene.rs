@@ -0,0 +1,236 @@
+use crate::error::{Error, Result};
+use crate::light::Light;
+use crate::material::{AlphaMode, Material};
+use crate::texture::{Sampler, Texture};
+use crate::transform::Transform;
+use crate::utils::geometry::{Mesh, Submesh};
+use crate::utils::import_gltf;
+use glam::{Mat4, Vec3};
+use std::path::Path;
+
+// A node of the hierarchy, `transform` is relative to the parent
+pub struct Node {
+    pub name: Option<String>,
+    pub transform: Transform,
+    // composed transforms of the node and all of its parents
+    pub world: Mat4,
+    // index into the meshes of the scene
+    pub mesh: Option<usize>,
+    // index into the lights of the scene
+    pub light: Option<usize>,
+    pub parent: Option<usize>,
+    pub children: Vec<usize>,
+}
+
+// A submesh of an instance, see `Scene::draws`
+pub struct Draw<'a> {
+    // index of the instance in `Scene::instances`, its vertices are shaded once for all
+    // of its submeshes
+    pub instance: usize,
+    pub mesh: &'a Mesh,
+    pub world: Mat4,
+    pub submesh: &'a Submesh,
+    // the material blends, so the submesh must not write the z buffer
+    pub transparent: bool,
+}
+
+// Every node of a glTF scene with the meshes, images and materials of the file
+pub struct Scene {
+    // parents come before their children
+    pub nodes: Vec<Node>,
+    pub roots: Vec<usize>,
+    // one mesh per glTF mesh, in the order of the document
+    pub meshes: Vec<Mesh>,
+    // one texture per glTF image, in the order of the document
+    pub textures: Vec<Texture>,
+    pub materials: Vec<Material>,
+    // KHR_lights_punctual lights at the origin of their nodes, see `world_lights`
+    pub lights: Vec<Light>,
+}
+
+impl Scene {
+    // loads the default scene of a .gltf or .glb file, or the first one if there is no
+    // default, with all images, whether they are external files, data uris or glb buffer views
+    pub fn load(path: &Path) -> Result<Self> {
+        let (document, buffers) = import_gltf(path)?;
+        let base = path.parent().unwrap_or(Path::new("."));
+        let mut textures = document
+            .images()
+            .map(|image| load_image(&image, base, &buffers, path))
+            .collect::<Result<Vec<_>>>()?;
+        let materials: Vec<Material> = document
+            .materials()
+            .map(|m| Material::from_gltf(&m))
+            .collect();
+        // glTF stores the colors of these two sRGB encoded, every other texture is linear
+        for material in &materials {
+            let bindings = [material.base_color_texture, material.emissive_texture];
+            for binding in bindings.into_iter().flatten() {
+                textures[binding.texture].set_srgb(true);
+            }
+        }
+        let lights = document
+            .lights()
+            .map(|lights| lights.map(|light| Light::from_gltf(&light)).collect())
+            .unwrap_or_default();
+        let meshes = document
+            .meshes()
+            .map(|mesh| Mesh::from_gltf_mesh(&mesh, &buffers))
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut scene = Self {
+            nodes: Vec::new(),
+            roots: Vec::new(),
+            meshes,
+            textures,
+            materials,
+            lights,
+        };
+        let gltf_scene = document
+            .default_scene()
+            .or_else(|| document.scenes().next());
+        let mut visited = vec![false; document.nodes().len()];
+        for node in gltf_scene.iter().flat_map(|scene| scene.nodes()) {
+            let root = scene.add_node(&node, None, &mut visited, path)?;
+            scene.roots.push(root);
+        }
+        scene.update_world_matrices();
+
+        Ok(scene)
+    }
+
+    // a node reached twice is part of a cycle or the child of several parents, which
+    // glTF doesn't allow, the nodes have to form disjoint trees
+    fn add_node(
+        &mut self,
+        node: &gltf::Node,
+        parent: Option<usize>,
+        visited: &mut [bool],
+        path: &Path,
+    ) -> Result<usize> {
+        if std::mem::replace(&mut visited[node.index()], true) {
+            let message = format!("node {} is reached more than once", node.index());
+            return Err(Error::decode(path.display(), message));
+        }
+        let index = self.nodes.len();
+        self.nodes.push(Node {
+            name: node.name().map(String::from),
+            transform: Transform::from_gltf(node.transform()),
+            world: Mat4::IDENTITY,
+            mesh: node.mesh().map(|mesh| mesh.index()),
+            light: node.light().map(|light| light.index()),
+            parent,
+            children: Vec::new(),
+        });
+        for child in node.children() {
+            let child = self.add_node(&child, Some(index), visited, path)?;
+            self.nodes[index].children.push(child);
+        }
+        Ok(index)
+    }
+
+    // recomputes the world matrices after changing the transforms of nodes
+    pub fn update_world_matrices(&mut self) {
+        for i in 0..self.nodes.len() {
+            let parent = match self.nodes[i].parent {
+                Some(parent) => self.nodes[parent].world,
+                None => Mat4::IDENTITY,
+            };
+            self.nodes[i].world = parent * self.nodes[i].transform.get_local();
+        }
+    }
+
+    // every mesh placed in the world, once per node that uses it
+    pub fn instances(&self) -> impl Iterator<Item = (&Mesh, Mat4)> {
+        self.nodes
+            .iter()
+            .filter_map(|node| Some((&self.meshes[node.mesh?], node.world)))
+    }
+
+    // Every submesh of every instance in drawing order: the opaque ones in the order of
+    // the scene, then the blended ones from back to front as seen from the camera, so
+    // that they are blended over everything behind them.
+    pub fn draws(&self, camera_position: Vec3) -> Vec<Draw<'_>> {
+        let mut opaque = Vec::new();
+        let mut transparent = Vec::new();
+        for (instance, (mesh, world)) in self.instances().enumerate() {
+            for submesh in &mesh.submeshes {
+                let blended = self
+                    .material(submesh.material)
+                    .is_some_and(|material| material.alpha_mode == AlphaMode::Blend);
+                let draw = Draw {
+                    instance,
+                    mesh,
+                    world,
+                    submesh,
+                    transparent: blended,
+                };
+                if blended {
+                    let center = world.transform_point3(mesh.submesh_center(submesh));
+                    transparent.push((center.distance_squared(camera_position), draw));
+                } else {
+                    opaque.push(draw);
+                }
+            }
+        }
+        transparent.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+        opaque.extend(transparent.into_iter().map(|(_, draw)| draw));
+        opaque
+    }
+
+    // the lights of the nodes placed in the world, to be passed to the shaders every frame
+    pub fn world_lights(&self) -> Vec<Light> {
+        self.nodes
+            .iter()
+            .filter_map(|node| Some(self.lights[node.light?].transformed(&node.world)))
+            .collect()
+    }
+
+    // material of a submesh, None for the glTF default material
+    pub fn material(&self, index: Option<usize>) -> Option<&Material> {
+        index.map(|index| &self.materials[index])
+    }
+
+    // base color texture of a material with its sampler
+    pub fn base_color_texture(&self, material: &Material) -> Option<(&Texture, Sampler)> {
+        let binding = material.base_color_texture?;
+        Some((&self.textures[binding.texture], binding.sampler))
+    }
+}
+
+fn load_image(
+    image: &gltf::Image,
+    base: &Path,
+    buffers: &[gltf::buffer::Data],
+    path: &Path,
+) -> Result<Texture> {
+    let name = format!("image {} of {}", image.index(), path.display());
+    match image.source() {
+        gltf::image::Source::View { view, .. } => {
+            let buffer = &buffers[view.buffer().index()];
+            let bytes = buffer
+                .get(view.offset()..view.offset() + view.length())
+                .ok_or_else(|| Error::decode(&name, "buffer view is outside of its buffer"))?;
+            Texture::from_memory(bytes, &name)
+        }
+        gltf::image::Source::Uri { uri, .. } => match uri.strip_prefix("data:") {
+            Some(data) => {
+                let (header, _) = data
+                    .split_once(',')
+                    .ok_or_else(|| Error::decode(&name, "data uri without ','"))?;
+                if !header.ends_with(";base64") {
+                    return Err(Error::unsupported(&name, "data uri isn't base64 encoded"));
+                }
+                // gltf decodes data uris of images the same way as those of buffers
+                let source = gltf::buffer::Source::Uri(uri);
+                let bytes = gltf::buffer::Data::from_source(source, None)
+                    .map_err(|e| Error::decode(&name, e))?;
+                Texture::from_memory(&bytes, &name)
+            }
+            None => {
+                let relative = urlencoding::decode(uri).map_err(|e| Error::decode(&name, e))?;
+                Texture::load(&base.join(relative.as_ref()))
+            }
+        },
+    }
+}
@@ -0,0 +1,374 @@
+use crate::light::Light;
+use crate::material::{Material, TextureBinding};
+use crate::shadow::ShadowMap;
+use crate::texture::{Sampler, Texture};
+use crate::utils::{geometry::*, *};
+use glam::{Mat4, Vec2, Vec3, Vec3Swizzles, Vec4, Vec4Swizzles};
+use std::f32::consts::PI;
+
+// Input of the fragment stage, the varyings are already perspective corrected
+#[derive(Debug, Copy, Clone)]
+pub struct Fragment<V = Vertex> {
+    // pixel center in window coordinates
+    pub position: Vec2,
+    // normalized device depth
+    pub depth: f32,
+    pub varyings: V,
+    // change of the varyings to the next pixel in x and y, computed per 2x2 quad
+    pub ddx: V,
+    pub ddy: V,
+}
+
+// Runs once per mesh vertex, the uniforms are the fields of the implementing type.
+// The output holds the clip space position, every other attribute is interpolated
+// across the triangle and handed to the fragment shader.
+pub trait VertexShader {
+    type Input;
+    type Output: Varyings;
+
+    fn vertex(&self, vertex: &Self::Input) -> Self::Output;
+}
+
+// Runs once per covered pixel that passed the depth test and returns the linear color
+// with its alpha, which only matters for blended draws. Tiles are shaded in parallel,
+// so the uniforms are shared between threads.
+pub trait FragmentShader<V: Varyings = Vertex>: Sync {
+    fn fragment(&self, fragment: &Fragment<V>) -> Vec4;
+}
+
+// Vertex shaders
+
+// transforms positions with the model-view-projection matrix and normals with the
+// cofactor of the model matrix. Tangents lie in the surface, so unlike the normals
+// they are transformed with the model matrix itself.
+pub struct StandardVertexShader {
+    pub mvp: Mat4,
+    pub model: Mat4,
+    pub normal_matrix: Mat4,
+}
+
+impl StandardVertexShader {
+    pub fn new(mvp: &Mat4, model: &Mat4) -> Self {
+        Self {
+            mvp: *mvp,
+            model: *model,
+            normal_matrix: cofactor(model),
+        }
+    }
+}
+
+impl VertexShader for StandardVertexShader {
+    type Input = Vertex;
+    type Output = Vertex;
+
+    fn vertex(&self, vertex: &Vertex) -> Vertex {
+        let mut result = *vertex;
+        result.position = self.mvp * vertex.position.xyz().extend(1.0);
+        result.normal = (self.normal_matrix * vertex.normal.extend(0.0)).xyz();
+        result.tangent = transform_tangent(&self.model, vertex.tangent);
+        result
+    }
+}
+
+// varyings of the lit shaders, which also need the surface in world space
+#[derive(Debug, Copy, Clone)]
+pub struct SurfaceVaryings {
+    pub position: Vec4,
+    pub world_position: Vec3,
+    pub normal: Vec3,
+    pub tangent: Vec4,
+    pub uv: Vec2,
+    pub color: Vec3,
+}
+
+crate::impl_interpolate!(SurfaceVaryings {
+    position,
+    world_position,
+    normal,
+    tangent,
+    uv,
+    color
+});
+
+impl Varyings for SurfaceVaryings {
+    fn position(&self) -> Vec4 {
+        self.position
+    }
+}
+
+// like the standard vertex shader, but also outputs the world position
+pub struct SurfaceVertexShader {
+    pub view_projection: Mat4,
+    pub model: Mat4,
+    pub normal_matrix: Mat4,
+}
+
+impl SurfaceVertexShader {
+    pub fn new(view_projection: &Mat4, model: &Mat4) -> Self {
+        Self {
+            view_projection: *view_projection,
+            model: *model,
+            normal_matrix: cofactor(model),
+        }
+    }
+}
+
+impl VertexShader for SurfaceVertexShader {
+    type Input = Vertex;
+    type Output = SurfaceVaryings;
+
+    fn vertex(&self, vertex: &Vertex) -> SurfaceVaryings {
+        let world_position = self.model * vertex.position.xyz().extend(1.0);
+        SurfaceVaryings {
+            position: self.view_projection * world_position,
+            world_position: world_position.xyz(),
+            normal: (self.normal_matrix * vertex.normal.extend(0.0)).xyz(),
+            tangent: transform_tangent(&self.model, vertex.tangent),
+            uv: vertex.uv,
+            color: vertex.color,
+        }
+    }
+}
+
+// keeps the handedness in w
+fn transform_tangent(model: &Mat4, tangent: Vec4) -> Vec4 {
+    (*model * tangent.xyz().extend(0.0)).xyz().extend(tangent.w)
+}
+
+// Fragment shaders
+
+// outputs the texture or the vertex color without any lighting
+#[derive(Default)]
+pub struct UnlitShader<'a> {
+    pub texture: Option<&'a Texture>,
+    pub sampler: Sampler,
+}
+
+impl FragmentShader for UnlitShader<'_> {
+    fn fragment(&self, fragment: &Fragment) -> Vec4 {
+        let (varyings, ddx, ddy) = (&fragment.varyings, &fragment.ddx, &fragment.ddy);
+        let uv = [varyings.uv, ddx.uv, ddy.uv];
+        base_color(self.texture, &self.sampler, uv, varyings.color)
+    }
+}
+
+// diffuse lighting of the texture or the vertex color by the lights, plus an ambient
+// term, like the diffuse part of the pbr shader
+pub struct LambertShader<'a> {
+    pub texture: Option<&'a Texture>,
+    pub sampler: Sampler,
+    // in world space, like the varyings
+    pub lights: &'a [Light],
+    pub ambient: Vec3,
+}
+
+impl<'a> LambertShader<'a> {
+    pub fn new(texture: Option<&'a Texture>, lights: &'a [Light]) -> Self {
+        Self {
+            texture,
+            sampler: Sampler::default(),
+            lights,
+            ambient: Vec3::splat(0.2),
+        }
+    }
+}
+
+impl FragmentShader<SurfaceVaryings> for LambertShader<'_> {
+    fn fragment(&self, fragment: &Fragment<SurfaceVaryings>) -> Vec4 {
+        let (varyings, ddx, ddy) = (&fragment.varyings, &fragment.ddx, &fragment.ddy);
+        let uv = [varyings.uv, ddx.uv, ddy.uv];
+        let color = base_color(self.texture, &self.sampler, uv, varyings.color);
+        let n = varyings.normal.normalize_or_zero();
+        let mut irradiance = Vec3::ZERO;
+        for light in self.lights {
+            if let Some((l, radiance)) = light.incident(varyings.world_position) {
+                irradiance += radiance * n.dot(l).max(0.0);
+            }
+        }
+        (color.xyz() * (irradiance / PI + self.ambient)).extend(color.w)
+    }
+}
+
+// for passes that only need the z buffer, like shadow maps
+pub struct DepthOnlyShader;
+
+impl FragmentShader for DepthOnlyShader {
+    fn fragment(&self, _fragment: &Fragment) -> Vec4 {
+        Vec4::ZERO
+    }
+}
+
+// for shadow passes of cutouts, the alpha of the base color decides which fragments are
+// discarded, see `DrawState::cutout`
+pub struct CutoutDepthShader<'a> {
+    pub material: &'a Material,
+    pub textures: &'a [Texture],
+}
+
+impl FragmentShader for CutoutDepthShader<'_> {
+    fn fragment(&self, fragment: &Fragment) -> Vec4 {
+        let alpha = match &self.material.base_color_texture {
+            Some(texture) => {
+                let (varyings, ddx, ddy) = (&fragment.varyings, &fragment.ddx, &fragment.ddy);
+                texture.sample(self.textures, varyings.uv, ddx.uv, ddy.uv).w
+            }
+            None => 1.0,
+        };
+        Vec4::ZERO.with_w(self.material.base_color_factor.w * alpha)
+    }
+}
+
+// visualizes the interpolated normal remapped from [-1, 1] to [0, 1]
+pub struct NormalDebugShader;
+
+impl FragmentShader for NormalDebugShader {
+    fn fragment(&self, fragment: &Fragment) -> Vec4 {
+        (fragment.varyings.normal.normalize_or_zero() * 0.5 + 0.5).extend(1.0)
+    }
+}
+
+// Cook-Torrance shading of a glTF metallic-roughness material, with a GGX distribution,
+// Smith-GGX visibility and Schlick's Fresnel
+pub struct PbrShader<'a> {
+    pub material: &'a Material,
+    pub textures: &'a [Texture],
+    // in world space, like the varyings
+    pub lights: &'a [Light],
+    // shadows of the lights that cast them, empty for no shadows
+    pub shadow_maps: &'a [ShadowMap],
+    pub camera_position: Vec3,
+    pub ambient: Vec3,
+}
+
+impl<'a> PbrShader<'a> {
+    pub fn new(
+        material: &'a Material,
+        textures: &'a [Texture],
+        lights: &'a [Light],
+        camera_position: Vec3,
+    ) -> Self {
+        Self {
+            material,
+            textures,
+            lights,
+            shadow_maps: &[],
+            camera_position,
+            ambient: Vec3::splat(0.2),
+        }
+    }
+
+    fn sample(
+        &self,
+        texture: &Option<TextureBinding>,
+        fragment: &Fragment<SurfaceVaryings>,
+    ) -> Vec4 {
+        match texture {
+            Some(texture) => texture.sample(
+                self.textures,
+                fragment.varyings.uv,
+                fragment.ddx.uv,
+                fragment.ddy.uv,
+            ),
+            None => Vec4::ONE,
+        }
+    }
+
+    // interpolated normal, perturbed by the normal map when the mesh has tangents
+    fn normal(&self, fragment: &Fragment<SurfaceVaryings>) -> Vec3 {
+        let normal = fragment.varyings.normal.normalize_or_zero();
+        let tangent = fragment.varyings.tangent;
+        if self.material.normal_texture.is_none() || tangent.xyz().length_squared() < 1e-12 {
+            return normal;
+        }
+        // interpolation leaves the tangent slightly off the surface
+        let t = (tangent.xyz() - normal * normal.dot(tangent.xyz())).normalize_or_zero();
+        let b = normal.cross(t) * tangent.w.signum();
+        let texel = self.sample(&self.material.normal_texture, fragment).xyz() * 2.0 - 1.0;
+        let scaled = (texel.xy() * self.material.normal_scale).extend(texel.z);
+        (t * scaled.x + b * scaled.y + normal * scaled.z).normalize_or_zero()
+    }
+}
+
+impl FragmentShader<SurfaceVaryings> for PbrShader<'_> {
+    fn fragment(&self, fragment: &Fragment<SurfaceVaryings>) -> Vec4 {
+        let material = self.material;
+        let base_color = material.base_color_factor
+            * self.sample(&material.base_color_texture, fragment)
+            * fragment.varyings.color.extend(1.0);
+        let (base_color, alpha) = (base_color.xyz(), base_color.w);
+        let metallic_roughness = self.sample(&material.metallic_roughness_texture, fragment);
+        let metallic = (material.metallic_factor * metallic_roughness.z).clamp(0.0, 1.0);
+        let roughness = (material.roughness_factor * metallic_roughness.y).clamp(0.0, 1.0);
+        let occlusion = self.sample(&material.occlusion_texture, fragment).x;
+        let occlusion = 1.0 + material.occlusion_strength * (occlusion - 1.0);
+        let emissive =
+            material.emissive_factor * self.sample(&material.emissive_texture, fragment).xyz();
+
+        let n = self.normal(fragment);
+        let position = fragment.varyings.world_position;
+        let v = (self.camera_position - position).normalize_or_zero();
+        // the bias of the shadow lookup depends on the geometry, not the normal map
+        let geometric_normal = fragment.varyings.normal.normalize_or_zero();
+        let mut direct = Vec3::ZERO;
+        for (index, light) in self.lights.iter().enumerate() {
+            let Some((l, radiance)) = light.incident(position) else {
+                continue;
+            };
+            let shadow_map = self.shadow_maps.iter().find(|map| map.light == index);
+            let visibility = match shadow_map {
+                Some(map) => map.visibility(position, geometric_normal.dot(l)),
+                None => 1.0,
+            };
+            if visibility > 0.0 {
+                let reflected = cook_torrance(n, v, l, base_color, metallic, roughness);
+                direct += reflected * radiance * visibility;
+            }
+        }
+        (direct + self.ambient * base_color * occlusion + emissive).extend(alpha)
+    }
+}
+
+// reflected fraction of the light arriving from l, including the cosine term
+fn cook_torrance(
+    n: Vec3,
+    v: Vec3,
+    l: Vec3,
+    base_color: Vec3,
+    metallic: f32,
+    roughness: f32,
+) -> Vec3 {
+    let h = (v + l).normalize_or_zero();
+    let n_dot_l = n.dot(l).max(0.0);
+    if n_dot_l == 0.0 {
+        return Vec3::ZERO;
+    }
+    // surfaces seen exactly from the side would divide by zero
+    let n_dot_v = n.dot(v).abs().max(1e-4);
+    let n_dot_h = n.dot(h).max(0.0);
+    let v_dot_h = v.dot(h).max(0.0);
+
+    // dielectrics reflect 4% at normal incidence, metals tint the reflection
+    let f0 = Vec3::splat(0.04).lerp(base_color, metallic);
+    let fresnel = f0 + (Vec3::ONE - f0) * (1.0 - v_dot_h).powi(5);
+    // alpha is clamped so that smooth surfaces keep a visible highlight
+    let alpha = (roughness * roughness).max(1e-3);
+    let alpha2 = alpha * alpha;
+    let d = n_dot_h * n_dot_h * (alpha2 - 1.0) + 1.0;
+    let distribution = alpha2 / (PI * d * d);
+    let visibility = 0.5
+        / (n_dot_l * (n_dot_v * n_dot_v * (1.0 - alpha2) + alpha2).sqrt()
+            + n_dot_v * (n_dot_l * n_dot_l * (1.0 - alpha2) + alpha2).sqrt())
+        .max(1e-6);
+
+    let specular = fresnel * distribution * visibility;
+    let diffuse = (Vec3::ONE - fresnel) * (1.0 - metallic) * base_color / PI;
+    (diffuse + specular) * n_dot_l
+}
+
+// the uvs with their change in x and y
+fn base_color(texture: Option<&Texture>, sampler: &Sampler, uv: [Vec2; 3], color: Vec3) -> Vec4 {
+    match texture {
+        Some(texture) => texture.sample(sampler, uv[0], uv[1], uv[2]),
+        None => color.extend(1.0),
+    }
+}
@@ -0,0 +1,245 @@
+use crate::camera::Camera;
+use crate::light::Light;
+use crate::material::{AlphaMode, Material};
+use crate::raster::{DrawState, Screen};
+use crate::shader::{CutoutDepthShader, DepthOnlyShader, StandardVertexShader};
+use crate::texture::Texture;
+use crate::transform::Transform;
+use crate::utils::geometry::Mesh;
+use glam::{Mat4, Quat, Vec3, Vec4Swizzles};
+
+// widest field of view of a spot light map, the perspective projection degenerates as
+// the outer cone approaches 90 degrees
+const MAX_SPOT_FOV: f32 = 170.0 * std::f32::consts::PI / 180.0;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ShadowSettings {
+    // width and height of the shadow maps in texels
+    pub size: usize,
+    // constant offset as a fraction of the depth range of the map, against acne on surfaces
+    // facing the light
+    pub depth_bias: f32,
+    // offset in texels of the map scaled by the tangent of the angle to the light, for
+    // surfaces at grazing angles. Multiplied with the filter radius + 1 since the filter
+    // compares against texels further away.
+    pub slope_bias: f32,
+    // lookups average (2 * radius + 1)^2 depth comparisons
+    pub pcf_radius: u32,
+}
+
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        Self {
+            size: 1024,
+            depth_bias: 0.002,
+            slope_bias: 1.0,
+            pcf_radius: 1,
+        }
+    }
+}
+
+// Depth of the shadow casters as seen from a light
+pub struct ShadowMap {
+    // index of the light in the list passed to the shaders
+    pub light: usize,
+    pub view_projection: Mat4,
+    // distance from the light between the near and far plane mapped to 0..1, rows from the
+    // top like the z buffer of the screen
+    pub depth: Vec<f32>,
+    pub settings: ShadowSettings,
+    // only for spot lights, orthographic maps have a linear depth
+    perspective: Option<Perspective>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Perspective {
+    near: f32,
+    far: f32,
+    // tangent of half the field of view
+    tan_half_fov: f32,
+}
+
+impl ShadowMap {
+    // Depth-only pass of the casters with their model matrices. Directional lights get an
+    // orthographic projection around all casters, spot lights a perspective one covering
+    // their outer cone. None for point lights, lights without a direction and when there is
+    // nothing to cast a shadow.
+    // The submeshes index into the materials, cutouts only cast where they aren't cut out.
+    // The pass renders into `target`, a depth-only screen like `Screen::depth_target` that
+    // is kept for all lights and frames, and is resized when the map size changes.
+    pub fn render(
+        target: &mut Screen,
+        light_index: usize,
+        light: &Light,
+        casters: &[(&Mesh, Mat4)],
+        materials: &[Material],
+        textures: &[Texture],
+        settings: ShadowSettings,
+    ) -> Option<Self> {
+        let (center, radius) = bounding_sphere(casters)?;
+        let mut perspective = None;
+        let view_projection = match *light {
+            Light::Directional { direction, .. } => {
+                let direction = direction.try_normalize()?;
+                let eye = center - direction * radius * 2.0;
+                let view = Mat4::look_at_rh(eye, center, up_vector(direction));
+                let projection =
+                    Mat4::orthographic_rh(-radius, radius, -radius, radius, radius, radius * 3.0);
+                projection * view
+            }
+            Light::Spot {
+                position,
+                direction,
+                range,
+                outer_cone_angle,
+                ..
+            } => {
+                let direction = direction.try_normalize()?;
+                let fov = (outer_cone_angle * 2.0).min(MAX_SPOT_FOV);
+                let far = range.unwrap_or(position.distance(center) + radius);
+                perspective = Some(Perspective {
+                    near: far * 1e-2,
+                    far,
+                    tan_half_fov: (fov * 0.5).tan(),
+                });
+                let camera = Camera {
+                    near_plane: far * 1e-2,
+                    far_plane: far,
+                    fov,
+                    aspect_ratio: 1.0,
+                    transform: Transform::from_translation_rotation(
+                        position,
+                        Quat::from_rotation_arc(-Vec3::Z, direction),
+                    ),
+                    ..Default::default()
+                };
+                camera.projection() * camera.view()
+            }
+            Light::Point { .. } => return None,
+        };
+
+        if (target.width, target.height) != (settings.size, settings.size) {
+            *target = target.depth_target(settings.size, settings.size);
+        }
+        target.clear();
+        for (mesh, model) in casters {
+            let mvp = view_projection * *model;
+            let vertex_shader = StandardVertexShader::new(&mvp, model);
+            let clip_vertices = Screen::shade_vertices(mesh, &vertex_shader);
+            for submesh in &mesh.submeshes {
+                let material = submesh.material.map(|index| &materials[index]);
+                match material.filter(|material| material.alpha_mode == AlphaMode::Mask) {
+                    Some(material) => {
+                        target.state = DrawState::cutout(material.alpha_cutoff);
+                        let shader = CutoutDepthShader { material, textures };
+                        target.raster_submesh(mesh, submesh, &clip_vertices, &shader);
+                    }
+                    None => {
+                        target.state = DrawState::default();
+                        target.raster_submesh(mesh, submesh, &clip_vertices, &DepthOnlyShader);
+                    }
+                }
+            }
+        }
+
+        let mut map = Self {
+            light: light_index,
+            view_projection,
+            depth: Vec::new(),
+            settings,
+            perspective,
+        };
+        map.depth = target
+            .z_buffer
+            .iter()
+            .map(|&z| map.linear_depth(z))
+            .collect();
+        Some(map)
+    }
+
+    // fraction of the light reaching a point in world space, n_dot_l is the cosine between
+    // the surface normal and the direction towards the light
+    pub fn visibility(&self, position: Vec3, n_dot_l: f32) -> f32 {
+        let clip = self.view_projection * position.extend(1.0);
+        if clip.w <= 0.0 {
+            return 1.0;
+        }
+        let ndc = clip.xyz() / clip.w;
+        // everything outside of the map is lit
+        if ndc.x.abs() > 1.0 || ndc.y.abs() > 1.0 || ndc.z > 1.0 {
+            return 1.0;
+        }
+
+        let cos = n_dot_l.clamp(1e-2, 1.0);
+        let tan = ((1.0 - cos * cos).sqrt() / cos).min(10.0);
+        let depth = self.linear_depth(ndc.z);
+        let slope_bias = self.settings.slope_bias * (self.settings.pcf_radius + 1) as f32;
+        let depth = depth - self.settings.depth_bias - slope_bias * tan * self.texel_depth(depth);
+
+        let size = self.settings.size;
+        let x = ((ndc.x + 1.0) * 0.5 * size as f32) as i64;
+        let y = ((1.0 - ndc.y) * 0.5 * size as f32) as i64;
+        let radius = self.settings.pcf_radius as i64;
+        let mut lit = 0;
+        for dy in -radius..=radius {
+            for dx in -radius..=radius {
+                let sx = (x + dx).clamp(0, size as i64 - 1) as usize;
+                let sy = (y + dy).clamp(0, size as i64 - 1) as usize;
+                if depth <= self.depth[sy * size + sx] {
+                    lit += 1;
+                }
+            }
+        }
+        lit as f32 / ((2 * radius + 1) * (2 * radius + 1)) as f32
+    }
+
+    // so that the bias is the same distance anywhere in the map, the cleared depth stays
+    fn linear_depth(&self, ndc_z: f32) -> f32 {
+        match self.perspective {
+            Some(Perspective { near, far, .. }) if ndc_z.is_finite() => {
+                let distance = far * near / (far - ndc_z * (far - near));
+                (distance - near) / (far - near)
+            }
+            _ => ndc_z,
+        }
+    }
+
+    // width of a texel at a linear depth, in the units of the depth
+    fn texel_depth(&self, depth: f32) -> f32 {
+        let size = self.settings.size as f32;
+        match self.perspective {
+            Some(Perspective {
+                near,
+                far,
+                tan_half_fov,
+            }) => {
+                let distance = near + depth * (far - near);
+                2.0 * distance * tan_half_fov / size / (far - near)
+            }
+            // the orthographic maps are as wide as they are deep
+            None => 1.0 / size,
+        }
+    }
+}
+
+// around the world space bounding box of all vertices
+fn bounding_sphere(casters: &[(&Mesh, Mat4)]) -> Option<(Vec3, f32)> {
+    let mut points = casters.iter().flat_map(|(mesh, model)| {
+        let vertices = mesh.get_vertices();
+        vertices
+            .iter()
+            .map(move |v| model.transform_point3(v.position.xyz()))
+    });
+    let first = points.next()?;
+    let (min, max) = points.fold((first, first), |(min, max), p| (min.min(p), max.max(p)));
+    Some(((min + max) * 0.5, ((max - min).length() * 0.5).max(1e-3)))
+}
+
+// any vector that isn't parallel to the viewing direction
+fn up_vector(direction: Vec3) -> Vec3 {
+    if direction.normalize_or_zero().y.abs() > 0.99 {
+        Vec3::Z
+    } else {
+        Vec3::Y
+    }
+}
@@ -0,0 +1,94 @@
+use crate::raster::CompareFunction;
+
+// What happens to the stencil value of a pixel after the tests
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum StencilOp {
+    #[default]
+    Keep,
+    Zero,
+    // writes the reference value of the state
+    Replace,
+    IncrementClamp,
+    DecrementClamp,
+    Invert,
+    IncrementWrap,
+    DecrementWrap,
+}
+
+impl StencilOp {
+    pub fn apply(self, stored: u8, reference: u8) -> u8 {
+        match self {
+            Self::Keep => stored,
+            Self::Zero => 0,
+            Self::Replace => reference,
+            Self::IncrementClamp => stored.saturating_add(1),
+            Self::DecrementClamp => stored.saturating_sub(1),
+            Self::Invert => !stored,
+            Self::IncrementWrap => stored.wrapping_add(1),
+            Self::DecrementWrap => stored.wrapping_sub(1),
+        }
+    }
+}
+
+// Stencil test of a draw. A fragment passes when `reference <compare> stored`, both
+// masked with the read mask. The stencil test runs before the depth test, the buffer
+// is then updated with the op of the outcome, only the bits of the write mask change.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct StencilState {
+    pub compare: CompareFunction,
+    pub reference: u8,
+    pub read_mask: u8,
+    pub write_mask: u8,
+    // the stencil test failed
+    pub fail: StencilOp,
+    // the stencil test passed, but the depth test failed
+    pub depth_fail: StencilOp,
+    // both tests passed and the fragment wasn't discarded
+    pub pass: StencilOp,
+}
+
+// passes everything and leaves the buffer alone
+impl Default for StencilState {
+    fn default() -> Self {
+        Self {
+            compare: CompareFunction::Always,
+            reference: 0,
+            read_mask: 0xff,
+            write_mask: 0xff,
+            fail: StencilOp::Keep,
+            depth_fail: StencilOp::Keep,
+            pass: StencilOp::Keep,
+        }
+    }
+}
+
+impl StencilState {
+    // writes the reference wherever the draw ends up visible, e.g. to mark a selection
+    pub fn write(reference: u8) -> Self {
+        Self {
+            reference,
+            pass: StencilOp::Replace,
+            ..Default::default()
+        }
+    }
+
+    // only draws where `reference <compare> stored`, without changing the buffer
+    pub fn test(compare: CompareFunction, reference: u8) -> Self {
+        Self {
+            compare,
+            reference,
+            ..Default::default()
+        }
+    }
+
+    pub fn passes(&self, stored: u8) -> bool {
+        self.compare
+            .passes(self.reference & self.read_mask, stored & self.read_mask)
+    }
+
+    // the new value of the buffer after applying an op
+    pub fn update(&self, op: StencilOp, stored: u8) -> u8 {
+        let value = op.apply(stored, self.reference);
+        (stored & !self.write_mask) | (value & self.write_mask)
+    }
+}
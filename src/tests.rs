@@ -1,547 +1,3242 @@
-pub mod tests {
+// Meshes, shaders, comparisons and files shared by the tests
+mod helpers {
+    use crate::shader::{Fragment, FragmentShader, StandardVertexShader};
+    use crate::utils::geometry::Mesh;
+    use crate::Screen;
+    use glam::{Mat4, Vec3, Vec4};
+    use std::path::PathBuf;
+
+    // covers the whole screen at a normalized device depth when drawn without a transform
+    pub fn fullscreen(depth: f32) -> Mesh {
+        Mesh::quad(Vec3::Z * depth, Vec3::X, Vec3::Y)
+    }
+
+    // the same color and alpha everywhere
+    pub struct ColorShader(pub Vec4);
+
+    impl FragmentShader for ColorShader {
+        fn fragment(&self, _fragment: &Fragment) -> Vec4 {
+            self.0
+        }
+    }
+
+    pub fn draw_fullscreen(screen: &mut Screen, depth: f32, color: Vec4) {
+        let vertex_shader = StandardVertexShader::new(&Mat4::IDENTITY, &Mat4::IDENTITY);
+        screen.raster_mesh(&fullscreen(depth), &vertex_shader, &ColorShader(color));
+    }
+
+    pub fn approx_eq(a: Vec3, b: Vec3, epsilon: f32) -> bool {
+        (a - b).abs().max_element() <= epsilon
+    }
+
+    // like approx_eq, alpha included
+    pub fn approx_eq_rgba(a: Vec4, b: Vec4, epsilon: f32) -> bool {
+        (a - b).abs().max_element() <= epsilon
+    }
+
+    // standard base64 with padding, for data uris
+    pub fn encode_base64(bytes: &[u8]) -> String {
+        const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+        let mut encoded = String::new();
+        for chunk in bytes.chunks(3) {
+            let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, &byte)| {
+                bits | (byte as u32) << (16 - 8 * i)
+            });
+            for i in 0..4 {
+                if i <= chunk.len() {
+                    encoded.push(ALPHABET[(bits >> (18 - 6 * i) & 63) as usize] as char);
+                } else {
+                    encoded.push('=');
+                }
+            }
+        }
+        encoded
+    }
+
+    // Path of a file written by a test. All tests share the directory, so the names must
+    // be unique.
+    pub fn temp_path(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join("rusterizer-tests");
+        std::fs::create_dir_all(&directory).unwrap();
+        directory.join(name)
+    }
+
+    // Buffer of the glTF fixtures: three positions, the indices 0 1 2 0 1 5, two normals
+    // and three uvs. See gltf_document for the accessors.
+    pub fn gltf_buffer() -> Vec<u8> {
+        let mut bin = Vec::new();
+        for value in [0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0] {
+            bin.extend_from_slice(&value.to_le_bytes());
+        }
+        for index in [0u16, 1, 2, 0, 1, 5] {
+            bin.extend_from_slice(&index.to_le_bytes());
+        }
+        for value in [0.0f32, 0.0, 1.0, 0.0, 0.0, 1.0] {
+            bin.extend_from_slice(&value.to_le_bytes());
+        }
+        for value in [0.0f32, 0.0, 1.0, 0.0, 0.0, 1.0] {
+            bin.extend_from_slice(&value.to_le_bytes());
+        }
+        bin
+    }
+
+    // Document with a single node drawing `primitive` from gltf_buffer. Accessors: 0 the
+    // positions, 1 the first three indices, 2 the last three, 3 the normals, 4 more
+    // positions than the buffer holds, 5 the uvs. `extra_json` holds more top level
+    // properties like materials, `buffer` more properties of the buffer like its uri and
+    // `extra_views` more buffer views, each starting with a comma when not empty.
+    pub fn gltf_document(
+        primitive: &str,
+        extra_json: &str,
+        buffer: &str,
+        buffer_length: usize,
+        extra_views: &str,
+    ) -> String {
+        let position = |count: usize| {
+            format!(
+                r#"{{"bufferView": 0, "componentType": 5126, "count": {}, "type": "VEC3",
+                    "min": [0, 0, 0], "max": [1, 1, 0]}}"#,
+                count
+            )
+        };
+        format!(
+            r#"{{
+                "asset": {{"version": "2.0"}},
+                "scenes": [{{"nodes": [0]}}],
+                "nodes": [{{"mesh": 0}}],
+                "meshes": [{{"primitives": [{primitive}]}}],
+                "buffers": [{{"byteLength": {buffer_length}{buffer}}}],
+                "bufferViews": [
+                    {{"buffer": 0, "byteOffset": 0, "byteLength": 36}},
+                    {{"buffer": 0, "byteOffset": 36, "byteLength": 12}},
+                    {{"buffer": 0, "byteOffset": 48, "byteLength": 24}},
+                    {{"buffer": 0, "byteOffset": 72, "byteLength": 24}}{extra_views}
+                ],
+                "accessors": [
+                    {position_3},
+                    {{"bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR"}},
+                    {{"bufferView": 1, "byteOffset": 6, "componentType": 5123, "count": 3,
+                      "type": "SCALAR"}},
+                    {{"bufferView": 2, "componentType": 5126, "count": 2, "type": "VEC3"}},
+                    {position_100},
+                    {{"bufferView": 3, "componentType": 5126, "count": 3, "type": "VEC2"}}
+                ]{extra_json}
+            }}"#,
+            position_3 = position(3),
+            position_100 = position(100),
+        )
+    }
+
+    // writes `<name>.gltf` next to `<name>.bin`, see gltf_document
+    pub fn write_gltf(name: &str, primitive: &str, extra_json: &str) -> PathBuf {
+        let bin = gltf_buffer();
+        std::fs::write(temp_path(&format!("{}.bin", name)), &bin).unwrap();
+        let uri = format!(r#", "uri": "{}.bin""#, name);
+        let json = gltf_document(primitive, extra_json, &uri, bin.len(), "");
+        let path = temp_path(&format!("{}.gltf", name));
+        std::fs::write(&path, json).unwrap();
+        path
+    }
+}
+
+// Golden-image regression tests
+//
+// Every scenario is rendered headlessly and compared against `assets/golden/<name>.png`.
+// Run with `RUSTERIZER_BLESS=1 cargo test` to (re)generate the references after an intended
+// change in the output. On failure the actual image and a diff are written to `target/golden/`.
+mod golden {
     use crate::camera::Camera;
-    use crate::texture::*;
+    use crate::color::ToneMapper;
+    use crate::demos::*;
+    use crate::output::{read_png, write_png};
+    use crate::scene::Scene;
     use crate::transform::Transform;
+    use crate::utils::*;
+    use crate::Screen;
+    use std::path::{Path, PathBuf};
+
+    // maximum allowed difference per color channel
+    const TOLERANCE: u8 = 2;
+
+    fn check_golden(name: &str, width: usize, height: usize, draw: impl FnOnce(&mut Screen)) {
+        let mut screen = Screen::create(width, height);
+        draw(&mut screen);
+        screen.present();
+
+        let reference_path = PathBuf::from(format!("assets/golden/{}.png", name));
+        if std::env::var_os("RUSTERIZER_BLESS").is_some() {
+            std::fs::create_dir_all("assets/golden").unwrap();
+            write_png(&reference_path, width, height, &screen.data).unwrap();
+            return;
+        }
+
+        let (ref_width, ref_height, reference) = read_png(&reference_path).unwrap_or_else(|e| {
+            panic!(
+                "can't read {}: {} (run with RUSTERIZER_BLESS=1 to create it)",
+                reference_path.display(),
+                e
+            )
+        });
+        assert_eq!(
+            (ref_width, ref_height),
+            (width, height),
+            "{}: reference size mismatch",
+            name
+        );
+
+        let mut mismatches = 0;
+        let diff: Vec<u32> = screen
+            .data
+            .iter()
+            .zip(reference.iter())
+            .map(|(actual, expected)| {
+                let (ar, ag, ab) = from_u32_u8(*actual);
+                let (er, eg, eb) = from_u32_u8(*expected);
+                let max_delta = ar.abs_diff(er).max(ag.abs_diff(eg)).max(ab.abs_diff(eb));
+                if max_delta > TOLERANCE {
+                    mismatches += 1;
+                    from_u8_rgb(255, 0, 0)
+                } else {
+                    let gray = ((ar as u32 + ag as u32 + ab as u32) / 12) as u8;
+                    from_u8_rgb(gray, gray, gray)
+                }
+            })
+            .collect();
+
+        if mismatches > 0 {
+            let output_dir = Path::new("target/golden");
+            std::fs::create_dir_all(output_dir).unwrap();
+            let actual_path = output_dir.join(format!("{}.actual.png", name));
+            let diff_path = output_dir.join(format!("{}.diff.png", name));
+            write_png(&actual_path, width, height, &screen.data).unwrap();
+            write_png(&diff_path, width, height, &diff).unwrap();
+            panic!(
+                "{}: {} pixels differ by more than {} (see {} and {})",
+                name,
+                mismatches,
+                TOLERANCE,
+                actual_path.display(),
+                diff_path.display()
+            );
+        }
+    }
+
+    fn test_camera(translation: glam::Vec3) -> Camera {
+        Camera {
+            far_plane: 100.0,
+            near_plane: 0.1,
+            aspect_ratio: 1.0,
+            transform: Transform::from_translation(translation),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn golden_barycentric() {
+        check_golden("barycentric", 512, 512, _test_barycentric);
+    }
+
+    #[test]
+    fn golden_textured_quad() {
+        check_golden("textured_quad", 256, 256, _test_textured_quad);
+    }
+
+    #[test]
+    fn golden_textured_cube() {
+        check_golden("textured_cube", 256, 256, |screen| {
+            _test_textured_cube(screen, &mut 0.6)
+        });
+    }
+
+    #[test]
+    fn golden_clipped_camera() {
+        // the camera sits in front of the tilted quad, so the quad crosses the near plane
+        let camera = test_camera(glam::vec3(0.0, 0.0, 0.5));
+        check_golden("clipped_camera", 256, 256, |screen| {
+            _test_camera_inputs(screen, &mut 1.45, &camera)
+        });
+    }
+
+    #[test]
+    fn golden_gltf_cube() {
+        let mesh = load_gltf(Path::new("assets/tests/cube.gltf")).unwrap();
+        let camera = test_camera(glam::vec3(0.0, 0.0, 6.0));
+        check_golden("gltf_cube", 256, 256, |screen| {
+            _test_gltf(screen, &mut 0.5, &camera, &mesh)
+        });
+    }
+
+    #[test]
+    fn golden_gltf_scene() {
+        let scene = Scene::load(Path::new("assets/tests/scene.gltf")).unwrap();
+        let camera = test_camera(glam::vec3(0.0, 1.0, 9.0));
+        check_golden("gltf_scene", 256, 256, |screen| {
+            _test_gltf_textured(screen, &mut 0.0, &camera, &scene)
+        });
+    }
+
+    #[test]
+    fn golden_pbr_spheres() {
+        let camera = test_camera(glam::vec3(0.0, 0.0, 7.0));
+        check_golden("pbr_spheres", 256, 256, |screen| {
+            _test_pbr_spheres(screen, &camera)
+        });
+    }
+
+    #[test]
+    fn golden_normal_map() {
+        let camera = test_camera(glam::vec3(0.0, 0.0, 5.0));
+        check_golden("normal_map", 256, 256, |screen| {
+            _test_normal_map(screen, &camera)
+        });
+    }
+
+    #[test]
+    fn golden_lights() {
+        let mut camera = test_camera(glam::vec3(0.0, 2.0, 7.0));
+        camera.transform.rotation = glam::Quat::from_rotation_x(-0.3);
+        check_golden("lights", 256, 256, |screen| _test_lights(screen, &camera));
+    }
+
+    #[test]
+    fn golden_shadows() {
+        let mut camera = test_camera(glam::vec3(0.0, 2.0, 7.0));
+        camera.transform.rotation = glam::Quat::from_rotation_x(-0.3);
+        check_golden("shadows", 256, 256, |screen| _test_shadows(screen, &camera));
+    }
+
+    #[test]
+    fn golden_blend_modes() {
+        let camera = test_camera(glam::vec3(0.0, 0.0, 6.0));
+        check_golden("blend_modes", 256, 256, |screen| {
+            _test_blend_modes(screen, &camera)
+        });
+    }
+
+    #[test]
+    fn golden_cutout() {
+        let camera = test_camera(glam::vec3(0.0, 0.0, 5.0));
+        check_golden("cutout", 256, 256, |screen| _test_cutout(screen, &camera));
+    }
+
+    #[test]
+    fn golden_stencil_outline() {
+        let camera = test_camera(glam::vec3(0.0, 0.0, 6.0));
+        check_golden("stencil_outline", 256, 256, |screen| {
+            _test_stencil_outline(screen, &camera)
+        });
+    }
+
+    // each tone mapper goes through the output settings of the screen when presented
+    #[test]
+    fn golden_tone_mapping() {
+        let camera = test_camera(glam::vec3(0.0, 0.0, 6.0));
+        let tone_mappers = [
+            ("clamp", ToneMapper::Clamp),
+            ("reinhard", ToneMapper::Reinhard),
+            ("aces", ToneMapper::Aces),
+            ("exposure", ToneMapper::Exposure),
+        ];
+        for (name, tone_mapper) in tone_mappers {
+            let name = format!("tone_mapping_{}", name);
+            check_golden(&name, 128, 128, |screen| {
+                _test_tone_mapping(screen, &camera, tone_mapper)
+            });
+        }
+    }
+
+    #[test]
+    fn golden_gltf_teapot() {
+        let mesh = load_gltf(Path::new("assets/gltf/teapot.gltf")).unwrap();
+        let camera = test_camera(glam::vec3(0.0, 1.0, 5.0));
+        check_golden("gltf_teapot", 256, 256, |screen| {
+            _test_gltf(screen, &mut 0.5, &camera, &mesh)
+        });
+    }
+}
+
+mod clipping {
+    use crate::clip::{outcode, ClippedPolygon, CLIP_PLANES, MAX_CLIPPED_VERTICES};
     use crate::utils::geometry::*;
     use crate::Screen;
-    use glam::Vec4Swizzles;
-    use std::path::Path;
 
-    pub fn _test_indices(screen: &mut Screen) {
-        screen._output_index();
+    fn vertex(position: glam::Vec4, color: glam::Vec3, uv: glam::Vec2) -> Vertex {
+        Vertex::create(position, color, glam::Vec3::Z, uv)
     }
 
-    pub fn _test_coords(screen: &mut Screen) {
-        screen._output_coords();
+    #[test]
+    fn near_clipping_interpolates_attributes() {
+        // v0 is behind the near plane (z < 0), the other two are inside the frustum
+        let triangle = Triangle::create(
+            vertex(
+                glam::vec4(0.0, 0.0, -1.0, 1.0),
+                glam::Vec3::X,
+                glam::vec2(0.0, 0.0),
+            ),
+            vertex(
+                glam::vec4(0.5, 0.0, 1.0, 2.0),
+                glam::Vec3::Y,
+                glam::vec2(1.0, 0.0),
+            ),
+            vertex(
+                glam::vec4(0.0, 0.5, 1.0, 2.0),
+                glam::Vec3::Z,
+                glam::vec2(0.0, 1.0),
+            ),
+        );
+
+        let polygon = Screen::clip_cull_triangle(&triangle).expect("triangle is visible");
+        assert!(polygon.was_clipped());
+        assert_eq!(polygon.vertices().len(), 4);
+
+        for v in polygon.vertices() {
+            assert!(v.position.z >= 0.0);
+            // halfway between v0 and the other vertices along the clipped edges
+            if v.position.z == 0.0 {
+                assert!((v.color.x - 0.5).abs() < 1e-6);
+                assert!((v.color.y + v.color.z - 0.5).abs() < 1e-6);
+                assert!((v.uv.x + v.uv.y - 0.5).abs() < 1e-6);
+            }
+        }
     }
 
-    pub fn _test_edge_function(screen: &mut Screen) {
-        let v0 = Vertex {
-            position: glam::vec4(100.0, 100.0, 1.0, 1.0),
-            color: glam::vec3(0.0, 1.0, 0.0),
-            normal: glam::vec3(0.0, 0.0, 1.0),
-            uv: glam::vec2(0.0, 0.0),
-        };
+    #[test]
+    fn unclipped_triangle_is_untouched() {
+        let triangle = Triangle::create(
+            vertex(
+                glam::vec4(0.0, 0.0, 0.5, 1.0),
+                glam::Vec3::X,
+                glam::Vec2::ZERO,
+            ),
+            vertex(glam::vec4(0.5, 0.0, 0.5, 1.0), glam::Vec3::Y, glam::Vec2::X),
+            vertex(glam::vec4(0.0, 0.5, 0.5, 1.0), glam::Vec3::Z, glam::Vec2::Y),
+        );
 
-        let v1 = Vertex {
-            position: glam::vec4(250.0, 400.0, 1.0, 1.0),
-            color: glam::vec3(1.0, 0.0, 0.0),
-            normal: glam::vec3(0.0, 0.0, 1.0),
-            uv: glam::vec2(0.0, 0.0),
-        };
+        let polygon = ClippedPolygon::from_triangle(&triangle).clip(0b111111);
+        assert!(!polygon.was_clipped());
+        let colors: Vec<glam::Vec3> = polygon.vertices().iter().map(|v| v.color).collect();
+        assert_eq!(colors, vec![glam::Vec3::X, glam::Vec3::Y, glam::Vec3::Z]);
+    }
+
+    // every plane crossed by one of the vertices
+    fn outcode_of(triangle: &Triangle) -> u8 {
+        outcode(triangle.v0.position)
+            | outcode(triangle.v1.position)
+            | outcode(triangle.v2.position)
+    }
+
+    fn is_inside_frustum(position: glam::Vec4) -> bool {
+        // a little slack for the interpolated intersections
+        CLIP_PLANES.iter().all(|plane| plane.dot(position) >= -1e-5)
+    }
+
+    #[test]
+    fn near_clipping_of_two_vertices_keeps_a_triangle() {
+        // only v2 is in front of the near plane
+        let triangle = Triangle::create(
+            vertex(
+                glam::vec4(0.0, 0.0, -1.0, 1.0),
+                glam::Vec3::X,
+                glam::Vec2::ZERO,
+            ),
+            vertex(
+                glam::vec4(0.5, 0.0, -1.0, 1.0),
+                glam::Vec3::Y,
+                glam::Vec2::X,
+            ),
+            vertex(glam::vec4(0.0, 0.5, 1.0, 1.0), glam::Vec3::Z, glam::Vec2::Y),
+        );
+        let polygon = ClippedPolygon::from_triangle(&triangle).clip(outcode_of(&triangle));
+        assert!(polygon.was_clipped());
+        assert_eq!(polygon.vertices().len(), 3);
+        let depths: Vec<f32> = polygon.vertices().iter().map(|v| v.position.z).collect();
+        assert_eq!(depths.iter().filter(|&&z| z == 0.0).count(), 2);
+        assert_eq!(polygon.triangles().count(), 1);
+    }
+
+    #[test]
+    fn clipping_against_several_planes_stays_inside_the_frustum() {
+        // sticks out of the left, right and top planes and crosses the near plane
+        let triangle = Triangle::create(
+            vertex(
+                glam::vec4(-3.0, -0.5, 0.5, 1.0),
+                glam::Vec3::X,
+                glam::Vec2::ZERO,
+            ),
+            vertex(
+                glam::vec4(3.0, -0.5, 0.5, 1.0),
+                glam::Vec3::Y,
+                glam::Vec2::X,
+            ),
+            vertex(
+                glam::vec4(0.0, 3.0, -0.5, 1.0),
+                glam::Vec3::Z,
+                glam::Vec2::Y,
+            ),
+        );
+        let crossed = outcode_of(&triangle);
+        assert_eq!(crossed, 0b011011);
+
+        let polygon = ClippedPolygon::from_triangle(&triangle).clip(crossed);
+        assert!(polygon.was_clipped());
+        // every plane can add one vertex, here four planes cut off a corner each
+        assert!(
+            polygon.vertices().len() > 3,
+            "{:?}",
+            polygon.vertices().len()
+        );
+        assert!(polygon.vertices().len() <= MAX_CLIPPED_VERTICES);
+        assert!(polygon
+            .vertices()
+            .iter()
+            .all(|v| is_inside_frustum(v.position)));
+        assert_eq!(polygon.triangles().count(), polygon.vertices().len() - 2);
+        // the colors are still a blend of the three original ones
+        for v in polygon.vertices() {
+            assert!((v.color.element_sum() - 1.0).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn triangle_larger_than_the_frustum_becomes_the_viewport() {
+        // covers the whole screen, so the four side planes cut out the viewport square
+        let triangle = Triangle::create(
+            vertex(
+                glam::vec4(-10.0, -10.0, 0.5, 1.0),
+                glam::Vec3::X,
+                glam::Vec2::ZERO,
+            ),
+            vertex(
+                glam::vec4(10.0, -10.0, 0.5, 1.0),
+                glam::Vec3::Y,
+                glam::Vec2::X,
+            ),
+            vertex(
+                glam::vec4(0.0, 20.0, 0.5, 1.0),
+                glam::Vec3::Z,
+                glam::Vec2::Y,
+            ),
+        );
+        let polygon = Screen::clip_cull_triangle(&triangle).expect("triangle is visible");
+        let mut corners: Vec<(i32, i32)> = polygon
+            .vertices()
+            .iter()
+            .map(|v| (v.position.x.round() as i32, v.position.y.round() as i32))
+            .collect();
+        corners.sort();
+        corners.dedup();
+        assert_eq!(corners, [(-1, -1), (-1, 1), (1, -1), (1, 1)]);
+    }
+
+    #[test]
+    fn triangle_outside_of_one_plane_is_culled() {
+        // entirely to the right, although it spans the near and far planes
+        let triangle = Triangle::create(
+            vertex(
+                glam::vec4(2.0, -1.0, -1.0, 1.0),
+                glam::Vec3::X,
+                glam::Vec2::ZERO,
+            ),
+            vertex(
+                glam::vec4(3.0, -1.0, 2.0, 1.0),
+                glam::Vec3::Y,
+                glam::Vec2::X,
+            ),
+            vertex(glam::vec4(2.5, 1.0, 0.5, 1.0), glam::Vec3::Z, glam::Vec2::Y),
+        );
+        assert!(Screen::view_frustum_culling(&triangle));
+        assert!(Screen::clip_cull_triangle(&triangle).is_none());
+    }
+
+    #[test]
+    fn screen_bounding_box_is_clamped_between_top_and_bottom() {
+        // y points down, so top is the smallest y
+        let positions = [
+            glam::vec2(10.0, -5.0),
+            glam::vec2(20.0, 40.0),
+            glam::vec2(-3.0, 12.0),
+        ];
+        let bb = Screen::triangle_screen_bounding_box(&positions, glam::vec2(32.0, 32.0))
+            .expect("overlaps the viewport");
+        assert_eq!(
+            (bb.left, bb.right, bb.top, bb.bottom),
+            (0.0, 20.0, 0.0, 31.0)
+        );
+        let below = positions.map(|p| p + glam::vec2(0.0, 50.0));
+        assert!(Screen::triangle_screen_bounding_box(&below, glam::vec2(32.0, 32.0)).is_none());
+    }
+}
+
+mod varyings {
+    use crate::impl_interpolate;
+    use crate::shader::*;
+    use crate::utils::{geometry::*, *};
+    use crate::Screen;
+    use glam::{Vec2, Vec4, Vec4Swizzles};
+
+    // custom payload with a second uv set and joint weights
+    #[derive(Debug, Copy, Clone)]
+    struct SkinnedVertex {
+        position: Vec4,
+        uv1: Vec2,
+        weights: Vec4,
+    }
+
+    impl_interpolate!(SkinnedVertex {
+        position,
+        uv1,
+        weights
+    });
+
+    impl Varyings for SkinnedVertex {
+        fn position(&self) -> Vec4 {
+            self.position
+        }
+    }
+
+    struct PassThroughShader;
 
-        screen._output_edge_function(v0.position.xy(), v1.position.xy());
+    impl VertexShader for PassThroughShader {
+        type Input = SkinnedVertex;
+        type Output = SkinnedVertex;
+
+        fn vertex(&self, vertex: &SkinnedVertex) -> SkinnedVertex {
+            *vertex
+        }
+    }
+
+    struct WeightsShader;
+
+    impl FragmentShader<SkinnedVertex> for WeightsShader {
+        fn fragment(&self, fragment: &Fragment<SkinnedVertex>) -> Vec4 {
+            (fragment.varyings.weights.xyz() * fragment.varyings.uv1.x).extend(1.0)
+        }
     }
 
-    pub fn _test_triangle1(screen: &mut Screen) {
-        // Nice triangle
-        let v0 = Vertex {
-            position: glam::vec4(100.0, 100.0, 1.0, 1.0),
-            color: glam::vec3(0.0, 1.0, 0.0),
-            normal: glam::vec3(0.0, 0.0, 1.0),
-            uv: glam::vec2(0.0, 0.0),
+    #[test]
+    fn custom_varyings_are_interpolated() {
+        // a triangle covering the whole screen, each vertex owns one joint
+        let vertex = |x: f32, y: f32, weights: Vec4| SkinnedVertex {
+            position: glam::vec4(x, y, 0.5, 1.0),
+            uv1: Vec2::ONE,
+            weights,
         };
+        let vertices = [
+            vertex(-1.0, -1.0, Vec4::X),
+            vertex(3.0, -1.0, Vec4::Y),
+            vertex(-1.0, 3.0, Vec4::Z),
+        ];
+        let mesh = Mesh::from_vertices(&[glam::uvec3(0, 1, 2)], &vertices);
+
+        let mut screen = Screen::create(4, 4);
+        screen.raster_mesh(&mesh, &PassThroughShader, &WeightsShader);
+
+        // the interpolated weights always sum up to one
+        for color in &screen.hdr_buffer {
+            let sum = color.xyz().element_sum();
+            assert!((sum - 1.0).abs() < 1e-5, "weights sum to {}", sum);
+        }
+        // bottom left pixel is closest to the first vertex
+        screen.present();
+        let (r, g, b) = from_u32_u8(screen.data[12]);
+        assert!(r > g && r > b);
+    }
+}
+
+mod tiling {
+    use super::helpers::*;
+    use crate::demos::*;
+    use crate::shader::{Fragment, FragmentShader};
+    use crate::tile::TileGrid;
+    use crate::Screen;
+    use std::collections::HashSet;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Mutex;
+    use std::thread::ThreadId;
+
+    // remembers the threads that shaded a fragment
+    #[derive(Default)]
+    struct ThreadShader(Mutex<HashSet<ThreadId>>);
+
+    impl FragmentShader for ThreadShader {
+        fn fragment(&self, _fragment: &Fragment) -> glam::Vec4 {
+            self.0.lock().unwrap().insert(std::thread::current().id());
+            glam::Vec4::ONE
+        }
+    }
+
+    fn render(threads: usize, tile_size: usize) -> Screen {
+        let mut screen = Screen::create(200, 150);
+        screen.settings.threads = threads;
+        screen.settings.tile_size = tile_size;
+        _test_textured_cube(&mut screen, &mut 0.6);
+        _test_raster_mesh(&mut screen);
+        screen.present();
+        screen
+    }
+
+    #[test]
+    fn parallel_tiles_match_serial_output() {
+        let serial = render(1, 1 << 16);
+        for (threads, tile_size) in [(4, 16), (8, 7), (3, 64)] {
+            let parallel = render(threads, tile_size);
+            assert!(
+                serial.data == parallel.data,
+                "color differs with {} threads",
+                threads
+            );
+            let depth_matches = serial
+                .z_buffer
+                .iter()
+                .zip(parallel.z_buffer.iter())
+                .all(|(a, b)| a.to_bits() == b.to_bits());
+            assert!(depth_matches, "depth differs with {} threads", threads);
+        }
+    }
+
+    #[test]
+    fn split_only_returns_the_used_tiles() {
+        // 3x2 tiles, the last column and row are smaller
+        let grid = TileGrid::new(10, 7, 4);
+        let used = [false, true, false, false, false, true];
+        let mut hdr = vec![glam::Vec4::ZERO; 70];
+        let (mut depth, mut stencil) = (vec![0.0; 70], vec![0; 70]);
+        let tiles = grid.split(&used, &mut hdr, &mut depth, &mut stencil, &mut []);
+        let indices: Vec<usize> = tiles.iter().map(|tile| tile.index).collect();
+        assert_eq!(indices, [1, 5]);
+        for tile in &tiles {
+            assert_eq!(tile.hdr_rows.len(), tile.rect.height());
+            assert!(tile
+                .depth_rows
+                .iter()
+                .all(|row| row.len() == tile.rect.width()));
+            assert!(tile.oit_rows.is_empty());
+        }
+        assert_eq!((tiles[1].rect.width(), tiles[1].rect.height()), (2, 3));
+    }
+
+    #[test]
+    fn worker_threads_are_kept_between_draws() {
+        let mut screen = Screen::create(64, 64);
+        screen.settings.threads = 3;
+        screen.settings.tile_size = 8;
+        let quad = fullscreen(0.5);
+        let shader = ThreadShader::default();
+        let vertex_shader =
+            crate::shader::StandardVertexShader::new(&glam::Mat4::IDENTITY, &glam::Mat4::IDENTITY);
+        for _ in 0..10 {
+            screen.clear();
+            screen.raster_mesh(&quad, &vertex_shader, &shader);
+        }
+        let threads = shader.0.lock().unwrap().len();
+        assert!((1..=3).contains(&threads), "{} threads", threads);
+    }
+
+    #[test]
+    fn depth_targets_share_the_worker_threads_and_only_write_depth() {
+        let mut screen = Screen::create(64, 64);
+        screen.settings.threads = 3;
+        screen.settings.tile_size = 8;
+        let mut target = screen.depth_target(64, 64);
+        let quad = fullscreen(0.5);
+        let shader = ThreadShader::default();
+        let vertex_shader =
+            crate::shader::StandardVertexShader::new(&glam::Mat4::IDENTITY, &glam::Mat4::IDENTITY);
+        for _ in 0..10 {
+            screen.raster_mesh(&quad, &vertex_shader, &shader);
+            target.clear();
+            target.raster_mesh(&quad, &vertex_shader, &shader);
+        }
+        let threads = shader.0.lock().unwrap().len();
+        assert!((1..=3).contains(&threads), "{} threads", threads);
+        assert!(target.hdr_buffer.is_empty() && target.stencil_buffer.is_empty());
+        assert!(target.z_buffer.iter().all(|&z| z == 0.5));
+    }
+
+    // draws into a target sharing the worker threads from another thread, while the
+    // screen it was made from is still drawing
+    struct NestedDrawShader {
+        target: Mutex<Screen>,
+        drawn: AtomicBool,
+    }
+
+    impl FragmentShader for NestedDrawShader {
+        fn fragment(&self, _fragment: &Fragment) -> glam::Vec4 {
+            if !self.drawn.swap(true, Ordering::Relaxed) {
+                std::thread::scope(|scope| {
+                    scope.spawn(|| {
+                        draw_fullscreen(&mut self.target.lock().unwrap(), 0.25, glam::Vec4::ONE)
+                    });
+                });
+            }
+            glam::Vec4::ONE
+        }
+    }
 
-        let v1 = Vertex {
-            position: glam::vec4(250.0, 400.0, 1.0, 1.0),
-            color: glam::vec3(1.0, 0.0, 0.0),
-            normal: glam::vec3(0.0, 0.0, 1.0),
-            uv: glam::vec2(0.0, 0.0),
+    #[test]
+    fn targets_sharing_the_worker_threads_draw_while_the_screen_draws() {
+        let mut screen = Screen::create(64, 64);
+        screen.settings.threads = 3;
+        screen.settings.tile_size = 8;
+        let shader = NestedDrawShader {
+            target: Mutex::new(screen.depth_target(64, 64)),
+            drawn: AtomicBool::new(false),
         };
+        let vertex_shader =
+            crate::shader::StandardVertexShader::new(&glam::Mat4::IDENTITY, &glam::Mat4::IDENTITY);
+        screen.raster_mesh(&fullscreen(0.5), &vertex_shader, &shader);
+        let target = shader.target.into_inner().unwrap();
+        assert!(target.z_buffer.iter().all(|&z| z == 0.25));
+    }
 
-        let v2 = Vertex {
-            position: glam::vec4(400.0, 100.0, 1.0, 1.0),
-            color: glam::vec3(0.0, 0.0, 1.0),
-            normal: glam::vec3(0.0, 0.0, 1.0),
-            uv: glam::vec2(0.0, 0.0),
+    #[test]
+    fn nothing_overlaps_an_empty_grid() {
+        let bb = crate::utils::geometry::BoundingBox2D {
+            left: 0.0,
+            right: 10.0,
+            top: 0.0,
+            bottom: 10.0,
         };
+        for grid in [
+            TileGrid::new(0, 0, 8),
+            TileGrid::new(0, 16, 8),
+            TileGrid::new(16, 0, 8),
+        ] {
+            assert_eq!(grid.tiles_overlapping(&bb).count(), 0);
+        }
+    }
+}
+
+mod fill_rule {
+    use crate::edge::{TriangleEdges, QUAD_LANES};
+    use crate::shader::{Fragment, FragmentShader};
+    use crate::utils::geometry::*;
+    use crate::Screen;
+    use glam::Vec2;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    // counts how often every pixel gets shaded
+    struct CoverageShader {
+        width: usize,
+        counts: Vec<AtomicU32>,
+    }
+
+    impl CoverageShader {
+        fn new(width: usize, height: usize) -> Self {
+            Self {
+                width,
+                counts: (0..width * height).map(|_| AtomicU32::new(0)).collect(),
+            }
+        }
+
+        fn count(&self, x: usize, y: usize) -> u32 {
+            self.counts[y * self.width + x].load(Ordering::Relaxed)
+        }
+    }
+
+    impl FragmentShader for CoverageShader {
+        fn fragment(&self, fragment: &Fragment) -> glam::Vec4 {
+            let index = fragment.position.y as usize * self.width + fragment.position.x as usize;
+            self.counts[index].fetch_add(1, Ordering::Relaxed);
+            glam::Vec4::ONE
+        }
+    }
+
+    // vertex at the given window coordinates
+    fn vertex(screen: &Screen, position: Vec2) -> Vertex {
+        let ndc = position / glam::vec2(screen.width as f32, screen.height as f32) * 2.0 - 1.0;
+        Vertex::create(
+            glam::vec4(ndc.x, -ndc.y, 0.5, 1.0),
+            glam::Vec3::ONE,
+            glam::Vec3::Z,
+            Vec2::ZERO,
+        )
+    }
+
+    // Draws every triangle on its own with a cleared depth buffer, so overlapping
+    // triangles show up in the counts instead of being hidden by the depth test.
+    // The triangles are given counter-clockwise on screen, which faces the camera.
+    fn coverage(width: usize, height: usize, triangles: &[[Vec2; 3]]) -> CoverageShader {
+        let mut screen = Screen::create(width, height);
+        let shader = CoverageShader::new(width, height);
+        for [a, b, c] in triangles {
+            screen.clear();
+            let triangle = Triangle::create(
+                vertex(&screen, *a),
+                vertex(&screen, *b),
+                vertex(&screen, *c),
+            );
+            screen.raster_clip_space_triangles(std::iter::once(triangle), &shader);
+        }
+        shader
+    }
 
-        screen._output_triangle1(v0.position.xy(), v1.position.xy(), v2.position.xy());
+    // splits a grid of quads into two triangles each
+    fn grid_triangles(points: &[Vec<Vec2>]) -> Vec<[Vec2; 3]> {
+        let mut triangles = Vec::new();
+        for y in 0..points.len() - 1 {
+            for x in 0..points[y].len() - 1 {
+                let (a, b) = (points[y][x], points[y][x + 1]);
+                let (c, d) = (points[y + 1][x + 1], points[y + 1][x]);
+                triangles.push([a, c, b]);
+                triangles.push([a, d, c]);
+            }
+        }
+        triangles
     }
 
-    pub fn _test_triangle2(screen: &mut Screen) {
-        // Nice triangle
-        let v0 = Vertex {
-            position: glam::vec4(100.0, 100.0, 1.0, 1.0),
-            color: glam::vec3(0.0, 1.0, 0.0),
-            normal: glam::vec3(0.0, 0.0, 1.0),
-            uv: glam::vec2(0.0, 0.0),
+    #[test]
+    fn pixel_centers_on_edges_follow_top_left_rule() {
+        // square with every edge running through pixel centers
+        let (a, b) = (glam::vec2(0.5, 0.5), glam::vec2(2.5, 0.5));
+        let (c, d) = (glam::vec2(2.5, 2.5), glam::vec2(0.5, 2.5));
+        let shader = coverage(4, 4, &[[a, c, b], [a, d, c]]);
+        for y in 0..4 {
+            for x in 0..4 {
+                // the top and left edges are included, right and bottom are not
+                let expected = (x < 2 && y < 2) as u32;
+                assert_eq!(shader.count(x, y), expected, "pixel {} {}", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn diagonal_through_pixel_centers_is_drawn_once() {
+        let size = 32.0;
+        let (a, b) = (Vec2::ZERO, glam::vec2(size, 0.0));
+        let (c, d) = (glam::vec2(size, size), glam::vec2(0.0, size));
+        let shader = coverage(32, 32, &[[a, c, b], [a, d, c]]);
+        assert!(shader.counts.iter().all(|c| c.load(Ordering::Relaxed) == 1));
+    }
+
+    #[test]
+    fn jittered_mesh_is_watertight() {
+        let (width, height) = (64, 48);
+        let cells = 8;
+        // deterministic pseudo random offsets for the interior vertices
+        let mut seed = 0x2545f491u32;
+        let mut random = move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            seed as f32 / u32::MAX as f32 - 0.5
         };
 
-        let v1 = Vertex {
-            position: glam::vec4(250.0, 400.0, 1.0, 1.0),
-            color: glam::vec3(1.0, 0.0, 0.0),
-            normal: glam::vec3(0.0, 0.0, 1.0),
-            uv: glam::vec2(0.0, 0.0),
+        let cell_size = glam::vec2(width as f32, height as f32) / cells as f32;
+        let points: Vec<Vec<Vec2>> = (0..=cells)
+            .map(|y| {
+                (0..=cells)
+                    .map(|x| {
+                        let mut point = glam::vec2(x as f32, y as f32) * cell_size;
+                        if x > 0 && x < cells {
+                            point.x += random() * cell_size.x * 0.8;
+                        }
+                        if y > 0 && y < cells {
+                            point.y += random() * cell_size.y * 0.8;
+                        }
+                        point
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let shader = coverage(width, height, &grid_triangles(&points));
+        for y in 0..height {
+            for x in 0..width {
+                assert_eq!(shader.count(x, y), 1, "pixel {} {}", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn triangle_fan_is_watertight() {
+        // a fan around an off-center point, covering the whole screen
+        let center = glam::vec2(13.37, 9.21);
+        let corners = [
+            glam::vec2(0.0, 0.0),
+            glam::vec2(11.3, 0.0),
+            glam::vec2(32.0, 0.0),
+            glam::vec2(32.0, 17.7),
+            glam::vec2(32.0, 24.0),
+            glam::vec2(5.5, 24.0),
+            glam::vec2(0.0, 24.0),
+            glam::vec2(0.0, 6.25),
+        ];
+        let triangles: Vec<[Vec2; 3]> = (0..corners.len())
+            .map(|i| [center, corners[(i + 1) % corners.len()], corners[i]])
+            .collect();
+
+        let shader = coverage(32, 24, &triangles);
+        assert!(shader.counts.iter().all(|c| c.load(Ordering::Relaxed) == 1));
+    }
+
+    // the lanes of a quad agree with the edge functions of each pixel
+    #[test]
+    fn quad_lanes_match_the_pixel_edge_functions() {
+        let triangles = [
+            [
+                Vec2::new(1.0, 1.0),
+                Vec2::new(13.0, 2.5),
+                Vec2::new(4.0, 11.0),
+            ],
+            // clockwise, with a horizontal edge through pixel centers
+            [
+                Vec2::new(2.0, 4.5),
+                Vec2::new(5.5, 14.0),
+                Vec2::new(12.0, 4.5),
+            ],
+        ];
+        for screen in &triangles {
+            let edges = TriangleEdges::new(screen).unwrap();
+            for y in (0..16).step_by(2) {
+                for x in (0..16).step_by(2) {
+                    let quad = edges.evaluate_quad(x, y);
+                    let mask = edges.quad_coverage(&quad);
+                    for (lane, (dx, dy)) in QUAD_LANES.iter().enumerate() {
+                        let pixel = edges.evaluate(x + dx, y + dy);
+                        let lanes = quad.0.map(|weights| weights.to_array()[lane]);
+                        assert_eq!(lanes, pixel.to_array());
+                        // strictly inside is always covered, outside never, pixels on
+                        // an edge depend on the fill rule
+                        let covered = mask & (1 << lane) != 0;
+                        assert_eq!(edges.covers(pixel), covered);
+                        if lanes.iter().all(|&w| w > 0) {
+                            assert!(covered, "{} {}", x + dx, y + dy);
+                        }
+                        if lanes.iter().any(|&w| w < 0) {
+                            assert!(!covered, "{} {}", x + dx, y + dy);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+mod derivatives {
+    use crate::shader::{Fragment, FragmentShader};
+    use crate::utils::geometry::*;
+    use crate::Screen;
+    use glam::{Vec2, Vec3, Vec4};
+    use std::sync::Mutex;
+
+    // remembers the largest deviation from the expected uv derivatives
+    struct DerivativeShader {
+        expected_ddx: Vec2,
+        expected_ddy: Vec2,
+        error: Mutex<f32>,
+    }
+
+    impl FragmentShader for DerivativeShader {
+        fn fragment(&self, fragment: &Fragment) -> Vec4 {
+            let error = (fragment.ddx.uv - self.expected_ddx)
+                .abs()
+                .max_element()
+                .max((fragment.ddy.uv - self.expected_ddy).abs().max_element());
+            let mut max_error = self.error.lock().unwrap();
+            *max_error = max_error.max(error);
+            Vec4::ONE
+        }
+    }
+
+    #[test]
+    fn uv_derivatives_match_screen_mapping() {
+        let (width, height) = (16, 8);
+        let mut screen = Screen::create(width, height);
+        // uv goes from 0 to 1 across the screen, v pointing down
+        let vertex = |u: f32, v: f32| {
+            Vertex::create(
+                glam::vec4(u * 2.0 - 1.0, 1.0 - v * 2.0, 0.5, 1.0),
+                Vec3::ONE,
+                Vec3::Z,
+                glam::vec2(u, v),
+            )
+        };
+        let (a, b, c, d) = (
+            vertex(0.0, 0.0),
+            vertex(1.0, 0.0),
+            vertex(1.0, 1.0),
+            vertex(0.0, 1.0),
+        );
+        let shader = DerivativeShader {
+            expected_ddx: glam::vec2(1.0 / width as f32, 0.0),
+            expected_ddy: glam::vec2(0.0, 1.0 / height as f32),
+            error: Mutex::new(0.0),
         };
+        let triangles = [Triangle::create(a, c, b), Triangle::create(a, d, c)];
+        screen.raster_clip_space_triangles(triangles.into_iter(), &shader);
+        screen.present();
+
+        assert!(screen.data.iter().all(|pixel| *pixel != 0));
+        assert!(*shader.error.lock().unwrap() < 1e-5);
+    }
+}
+
+mod sampling {
+    use super::helpers::approx_eq_rgba;
+    use crate::texture::*;
+    use glam::{Vec2, Vec3, Vec4};
+
+    // compares with an opaque color
+    fn matches_opaque(a: Vec4, b: Vec3) -> bool {
+        approx_eq_rgba(a, b.extend(1.0), 1e-4)
+    }
+
+    fn rgb_texture(width: usize, height: usize, data: Vec<Vec3>) -> Texture {
+        let data = data.into_iter().map(|color| color.extend(1.0)).collect();
+        Texture::new(width, height, Texels::Rgba32F(data))
+    }
+
+    fn texels(level: &MipLevel) -> impl Iterator<Item = Vec4> + '_ {
+        (0..level.texels.len()).map(|i| level.texels.get(i))
+    }
 
-        let v2 = Vertex {
-            position: glam::vec4(400.0, 100.0, 1.0, 1.0),
-            color: glam::vec3(0.0, 0.0, 1.0),
-            normal: glam::vec3(0.0, 0.0, 1.0),
-            uv: glam::vec2(0.0, 0.0),
+    // alternating black and white rows
+    fn horizontal_stripes(size: usize) -> Texture {
+        let data = (0..size * size)
+            .map(|i| Vec3::splat(((i / size) % 2) as f32))
+            .collect();
+        rgb_texture(size, size, data)
+    }
+
+    #[test]
+    fn mip_chain_goes_down_to_one_texel() {
+        let texture = rgb_texture(5, 3, vec![Vec3::ONE; 15]);
+        let sizes: Vec<(usize, usize)> = texture
+            .levels
+            .iter()
+            .map(|level| (level.width, level.height))
+            .collect();
+        assert_eq!(sizes, [(5, 3), (2, 1), (1, 1)]);
+        assert!(texture
+            .levels
+            .iter()
+            .all(|level| texels(level).all(|texel| matches_opaque(texel, Vec3::ONE))));
+    }
+
+    #[test]
+    fn odd_mip_levels_include_the_last_row_and_column() {
+        // only the last column and row are white
+        let data = (0..5 * 3)
+            .map(|i| Vec3::splat((i % 5 == 4 || i / 5 == 2) as u32 as f32))
+            .collect();
+        let texture = rgb_texture(5, 3, data);
+        let level: Vec<Vec4> = texels(&texture.levels[1]).collect();
+        // each of the two texels covers 2.5 columns and all 3 rows, the last column is
+        // 1 / 2.5 of the second one
+        let expected = [1.0 / 3.0, (0.4 + 0.4 + 1.0) / 3.0];
+        for (texel, expected) in level.iter().zip(expected) {
+            assert!(matches_opaque(*texel, Vec3::splat(expected)), "{}", texel);
+        }
+    }
+
+    #[test]
+    fn odd_mip_levels_keep_the_average() {
+        let data: Vec<Vec3> = (0..7 * 5)
+            .map(|i| Vec3::new((i % 3) as f32, (i % 7) as f32 / 7.0, (i * i % 11) as f32))
+            .collect();
+        let average = |texels: &mut dyn Iterator<Item = Vec4>, count: usize| {
+            texels.fold(Vec4::ZERO, |sum, texel| sum + texel) / count as f32
         };
+        let texture = rgb_texture(7, 5, data);
+        let base = average(&mut texels(&texture.levels[0]), 35);
+        for level in &texture.levels[1..] {
+            let mean = average(&mut texels(level), level.width * level.height);
+            assert!((mean - base).abs().max_element() < 1e-4, "{}", mean);
+        }
+    }
 
-        screen._output_triangle2(v0.position.xy(), v1.position.xy(), v2.position.xy());
+    #[test]
+    fn mip_levels_average_the_texels() {
+        let texture = horizontal_stripes(8);
+        assert!(texture.levels[1..]
+            .iter()
+            .all(|level| texels(level).all(|t| matches_opaque(t, Vec3::splat(0.5)))));
     }
 
-    pub fn _test_barycentric(screen: &mut Screen) {
-        // Nice triangle
-        let v0 = Vertex {
-            position: glam::vec4(100.0, 100.0, 1.0, 1.0),
-            color: glam::vec3(1.0, 0.0, 0.0),
-            normal: glam::vec3(0.0, 0.0, 1.0),
-            uv: glam::vec2(0.0, 0.0),
+    #[test]
+    fn bilinear_blends_neighbouring_texels() {
+        let texture = horizontal_stripes(8);
+        let (u, row_center) = (0.5, 1.0 / 16.0);
+        let sample = |v: f32| {
+            let sampler = Sampler::BILINEAR;
+            texture.sample(&sampler, glam::vec2(u, v), Vec2::ZERO, Vec2::ZERO)
         };
+        assert!(matches_opaque(sample(row_center), Vec3::ZERO));
+        assert!(matches_opaque(sample(row_center + 1.0 / 8.0), Vec3::ONE));
+        assert!(matches_opaque(
+            sample(row_center + 1.0 / 16.0),
+            Vec3::splat(0.5)
+        ));
+        // rows wrap around at the top
+        assert!(matches_opaque(sample(0.0), Vec3::splat(0.5)));
+    }
 
-        let v1 = Vertex {
-            position: glam::vec4(250.0, 400.0, 1.0, 1.0),
-            color: glam::vec3(0.0, 1.0, 0.0),
-            normal: glam::vec3(0.0, 0.0, 1.0),
-            uv: glam::vec2(0.0, 0.0),
+    #[test]
+    fn minification_uses_the_mip_chain() {
+        let texture = horizontal_stripes(8);
+        let uv = glam::vec2(0.5, 3.0 / 16.0);
+        // a pixel covers 4x4 texels
+        let (ddx, ddy) = (glam::vec2(4.0 / 8.0, 0.0), glam::vec2(0.0, 4.0 / 8.0));
+
+        let nearest = texture.sample(&Sampler::NEAREST, uv, ddx, ddy);
+        assert!(matches_opaque(nearest, Vec3::ONE));
+        let trilinear = texture.sample(&Sampler::TRILINEAR, uv, ddx, ddy);
+        assert!(matches_opaque(trilinear, Vec3::splat(0.5)));
+    }
+
+    #[test]
+    fn anisotropic_filtering_keeps_detail_across_the_footprint() {
+        let texture = horizontal_stripes(8);
+        // stretched along u, the stripes change along v
+        let uv = glam::vec2(0.5, 3.0 / 16.0);
+        let (ddx, ddy) = (glam::vec2(4.0 / 8.0, 0.0), glam::vec2(0.0, 1.0 / 8.0));
+
+        let isotropic = texture.sample(&Sampler::TRILINEAR, uv, ddx, ddy);
+        assert!(matches_opaque(isotropic, Vec3::splat(0.5)));
+        let anisotropic = texture.sample(&Sampler::anisotropic(4), uv, ddx, ddy);
+        assert!(matches_opaque(anisotropic, Vec3::ONE));
+    }
+
+    // 4x1 texture with the values 0, 1, 2, 3 in the red channel
+    fn ramp() -> Texture {
+        rgb_texture(
+            4,
+            1,
+            (0..4).map(|i| glam::vec3(i as f32, 0.0, 0.0)).collect(),
+        )
+    }
+
+    fn red_at(texture: &Texture, sampler: Sampler, u: f32) -> f32 {
+        let sampler = Sampler {
+            mag_filter: Filter::Nearest,
+            ..sampler
         };
+        let uv = glam::vec2(u, 0.5);
+        texture.sample(&sampler, uv, Vec2::ZERO, Vec2::ZERO).x
+    }
 
-        let v2 = Vertex {
-            position: glam::vec4(400.0, 100.0, 1.0, 1.0),
-            color: glam::vec3(0.0, 0.0, 1.0),
-            normal: glam::vec3(0.0, 0.0, 1.0),
-            uv: glam::vec2(0.0, 0.0),
+    #[test]
+    fn wrap_modes_map_texel_coordinates() {
+        let coords = [-5, -1, 0, 3, 4, 7];
+        let wrapped = |wrap: Wrap| coords.map(|c| wrap.apply(c, 4));
+        let some = |values: [usize; 6]| values.map(Some);
+        assert_eq!(wrapped(Wrap::Repeat), some([3, 3, 0, 3, 0, 3]));
+        assert_eq!(wrapped(Wrap::MirroredRepeat), some([3, 0, 0, 3, 3, 0]));
+        assert_eq!(wrapped(Wrap::ClampToEdge), some([0, 0, 0, 3, 3, 3]));
+        assert_eq!(
+            wrapped(Wrap::ClampToBorder),
+            [None, None, Some(0), Some(3), None, None]
+        );
+    }
+
+    #[test]
+    fn negative_uvs_are_wrapped() {
+        let texture = ramp();
+        let sampler = Sampler::default();
+        // -0.1 is in the last texel of the previous repetition
+        assert_eq!(red_at(&texture, sampler, -0.1), 3.0);
+        assert_eq!(red_at(&texture, sampler, -0.9), 0.0);
+        let mirrored = sampler.with_wrap(Wrap::MirroredRepeat);
+        assert_eq!(red_at(&texture, mirrored, -0.1), 0.0);
+        assert_eq!(red_at(&texture, mirrored, 1.1), 3.0);
+        let clamped = sampler.with_wrap(Wrap::ClampToEdge);
+        assert_eq!(red_at(&texture, clamped, -3.0), 0.0);
+        assert_eq!(red_at(&texture, clamped, 5.0), 3.0);
+
+        let border = Sampler {
+            border_color: glam::vec4(9.0, 0.0, 0.0, 1.0),
+            ..sampler.with_wrap(Wrap::ClampToBorder)
         };
+        assert_eq!(red_at(&texture, border, -0.1), 9.0);
+        assert_eq!(red_at(&texture, border, 1.5), 9.0);
+        assert_eq!(red_at(&texture, border, 0.6), 2.0);
+
+        // the unfiltered lookup repeats as well
+        assert_eq!(texture.uv_to_index(-0.1, -0.5), 3);
+    }
+
+    #[test]
+    fn gltf_samplers_map_to_wrap_modes() {
+        let json = r#"{
+            "asset": {"version": "2.0"},
+            "samplers": [{"wrapS": 33648, "wrapT": 33071, "magFilter": 9728, "minFilter": 9985}]
+        }"#;
+        let document = gltf::Gltf::from_slice(json.as_bytes()).unwrap();
+        let sampler = Sampler::from_gltf(&document.samplers().next().unwrap());
+        assert_eq!(sampler.wrap_u, Wrap::MirroredRepeat);
+        assert_eq!(sampler.wrap_v, Wrap::ClampToEdge);
+        assert_eq!(sampler.mag_filter, Filter::Nearest);
+        assert_eq!(sampler.min_filter, Filter::Linear);
+        assert_eq!(sampler.mipmap_filter, Some(Filter::Nearest));
+    }
+}
 
-        screen._output_barycentric(&v0, &v1, &v2);
+mod texture_formats {
+    use super::helpers::{approx_eq_rgba, temp_path};
+    use crate::texture::*;
+    use glam::Vec4;
+    use std::path::PathBuf;
+
+    // 2x1 image, the samples are big endian for 16 bit depths
+    fn write_png(name: &str, color: png::ColorType, depth: png::BitDepth, data: &[u8]) -> PathBuf {
+        let path = temp_path(name);
+        let file = std::fs::File::create(&path).unwrap();
+        let mut encoder = png::Encoder::new(std::io::BufWriter::new(file), 2, 1);
+        encoder.set_color(color);
+        encoder.set_depth(depth);
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(data).unwrap();
+        writer.finish().unwrap();
+        path
+    }
+
+    #[test]
+    fn rgba8_keeps_alpha() {
+        let data = [255, 0, 0, 128, 0, 255, 0, 255];
+        let path = write_png(
+            "rgba8.png",
+            png::ColorType::Rgba,
+            png::BitDepth::Eight,
+            &data,
+        );
+        let texture = Texture::load(&path).unwrap();
+        let texels = &texture.levels[0].texels;
+        assert!(matches!(texels, Texels::Rgba8(_)));
+        assert!(approx_eq_rgba(
+            texels.get(0),
+            glam::vec4(1.0, 0.0, 0.0, 128.0 / 255.0),
+            1e-6
+        ));
+        assert!(approx_eq_rgba(
+            texels.get(1),
+            glam::vec4(0.0, 1.0, 0.0, 1.0),
+            1e-6
+        ));
     }
 
-    pub fn _test_textured_triangle(screen: &mut Screen) {
-        let v0 = Vertex {
-            position: glam::vec4(-1.0, 1.0, 1.0, 1.0),
-            color: glam::vec3(0.0, 1.0, 0.0),
-            normal: glam::vec3(0.0, 0.0, 1.0),
-            uv: glam::vec2(0.0, 0.0),
+    #[test]
+    fn grayscale_is_a_single_channel() {
+        let data = [0, 51];
+        let path = write_png(
+            "gray8.png",
+            png::ColorType::Grayscale,
+            png::BitDepth::Eight,
+            &data,
+        );
+        let texture = Texture::load(&path).unwrap();
+        let texels = &texture.levels[0].texels;
+        assert!(matches!(texels, Texels::R8(_)));
+        assert!(approx_eq_rgba(
+            texels.get(1),
+            glam::vec4(0.2, 0.2, 0.2, 1.0),
+            1e-6
+        ));
+    }
+
+    #[test]
+    fn sixteen_bit_precision_is_kept() {
+        // 1 and 65535, the first one would round to 0 with 8 bits
+        let data = [0, 1, 255, 255];
+        let path = write_png(
+            "gray16.png",
+            png::ColorType::Grayscale,
+            png::BitDepth::Sixteen,
+            &data,
+        );
+        let texture = Texture::load(&path).unwrap();
+        let texels = &texture.levels[0].texels;
+        assert!(matches!(texels, Texels::R16(_)));
+        assert!(approx_eq_rgba(
+            texels.get(0),
+            Vec4::new(1.0, 1.0, 1.0, 65535.0) / 65535.0,
+            1e-6
+        ));
+        assert!(approx_eq_rgba(texels.get(1), Vec4::ONE, 1e-6));
+
+        let data = [0, 1, 0, 2, 0, 3, 255, 255, 0, 0, 0, 0, 0, 0, 0, 0];
+        let path = write_png(
+            "rgba16.png",
+            png::ColorType::Rgba,
+            png::BitDepth::Sixteen,
+            &data,
+        );
+        let texture = Texture::load(&path).unwrap();
+        let texels = &texture.levels[0].texels;
+        assert!(matches!(texels, Texels::Rgba16(_)));
+        assert!(approx_eq_rgba(
+            texels.get(0),
+            glam::vec4(1.0, 2.0, 3.0, 65535.0) / 65535.0,
+            1e-6
+        ));
+    }
+
+    #[test]
+    fn hdr_values_above_one_are_kept() {
+        // uncompressed radiance file, the rgb mantissas are scaled by 2^(e - 136)
+        let mut data = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 1 +X 2\n".to_vec();
+        data.extend_from_slice(&[128, 64, 32, 131, 128, 128, 128, 128]);
+        let path = temp_path("radiance.hdr");
+        std::fs::write(&path, data).unwrap();
+
+        let texture = Texture::load(&path).unwrap();
+        let texels = &texture.levels[0].texels;
+        assert!(matches!(texels, Texels::Rgba32F(_)));
+        assert!(approx_eq_rgba(
+            texels.get(0),
+            glam::vec4(4.0, 2.0, 1.0, 1.0),
+            1e-6
+        ));
+        assert!(approx_eq_rgba(
+            texels.get(1),
+            glam::vec4(0.5, 0.5, 0.5, 1.0),
+            1e-6
+        ));
+    }
+
+    #[test]
+    fn mip_levels_keep_the_format() {
+        let texture = Texture::new(2, 2, Texels::R16(vec![0, 65535, 65535, 0]));
+        assert_eq!(texture.levels.len(), 2);
+        let level = &texture.levels[1].texels;
+        assert!(matches!(level, Texels::R16(data) if data == &[32768]));
+    }
+}
+
+mod loading_errors {
+    use super::helpers::{temp_path, write_gltf};
+    use crate::error::Error;
+    use crate::texture::{check_decoded, Texture};
+    use crate::utils::load_gltf;
+    use std::path::PathBuf;
+
+    fn write(name: &str, data: &[u8]) -> PathBuf {
+        let path = temp_path(name);
+        std::fs::write(&path, data).unwrap();
+        path
+    }
+
+    #[test]
+    fn valid_gltf_loads() {
+        let path = write_gltf(
+            "valid",
+            r#"{"attributes": {"POSITION": 0}, "indices": 1}"#,
+            "",
+        );
+        let mesh = load_gltf(&path).unwrap();
+        assert_eq!(mesh.get_vertices().len(), 3);
+        assert_eq!(mesh.get_triangles().len(), 1);
+    }
+
+    #[test]
+    fn missing_files_are_not_found() {
+        let path = temp_path("does_not_exist.png");
+        assert!(matches!(Texture::load(&path), Err(Error::NotFound(p)) if p == path));
+        let path = temp_path("does_not_exist.gltf");
+        assert!(matches!(load_gltf(&path), Err(Error::NotFound(p)) if p == path));
+    }
+
+    #[test]
+    fn missing_gltf_buffer_is_not_found() {
+        let path = write_gltf("missing_buffer", r#"{"attributes": {"POSITION": 0}}"#, "");
+        std::fs::remove_file(temp_path("missing_buffer.bin")).unwrap();
+        let buffer = temp_path("missing_buffer.bin");
+        assert!(matches!(load_gltf(&path), Err(Error::NotFound(p)) if p == buffer));
+    }
+
+    #[test]
+    fn broken_images_fail_to_decode() {
+        let path = write("broken.png", b"\x89PNG\r\n\x1a\nthis is not a png");
+        assert!(matches!(Texture::load(&path), Err(Error::Decode { .. })));
+    }
+
+    // decoders that report a size not matching their data are errors, not a panic in
+    // Texture::new
+    #[test]
+    fn decoded_sizes_must_match_the_data() {
+        assert!(check_decoded("image", (2, 3, 4), 24).is_ok());
+        assert!(matches!(
+            check_decoded("image", (2, 3, 4), 23),
+            Err(Error::Decode { .. })
+        ));
+        assert!(matches!(
+            check_decoded("image", (0, 3, 4), 0),
+            Err(Error::Decode { .. })
+        ));
+        assert!(matches!(
+            check_decoded("image", (2, 3, 0), 0),
+            Err(Error::UnsupportedFormat { .. })
+        ));
+        assert!(matches!(
+            check_decoded("image", (usize::MAX, 2, 1), 0),
+            Err(Error::Decode { .. })
+        ));
+    }
+
+    #[test]
+    fn unknown_images_are_unsupported() {
+        let path = write("text.png", b"just some text");
+        assert!(matches!(
+            Texture::load(&path),
+            Err(Error::UnsupportedFormat { .. })
+        ));
+    }
+
+    #[test]
+    fn invalid_gltf_fails_to_decode() {
+        let path = write("invalid.gltf", b"{\"asset\": ");
+        assert!(matches!(load_gltf(&path), Err(Error::Decode { .. })));
+    }
+
+    #[test]
+    fn unsupported_gltf_features() {
+        let path = write_gltf(
+            "points",
+            r#"{"attributes": {"POSITION": 0}, "mode": 0}"#,
+            "",
+        );
+        assert!(matches!(
+            load_gltf(&path),
+            Err(Error::UnsupportedFormat { .. })
+        ));
+
+        let json = br#"{
+            "asset": {"version": "2.0"},
+            "buffers": [{"byteLength": 4, "uri": "https://example.com/buffer.bin"}]
+        }"#;
+        let path = write("remote_buffer.gltf", json);
+        assert!(matches!(
+            load_gltf(&path),
+            Err(Error::UnsupportedFormat { .. })
+        ));
+    }
+
+    #[test]
+    fn malformed_accessors() {
+        let primitives = [
+            // index 5 with three vertices
+            r#"{"attributes": {"POSITION": 0}, "indices": 2}"#,
+            // two normals for three positions
+            r#"{"attributes": {"POSITION": 0, "NORMAL": 3}, "indices": 1}"#,
+            // positions reaching past the end of the buffer view
+            r#"{"attributes": {"POSITION": 4}}"#,
+        ];
+        for (i, primitive) in primitives.iter().enumerate() {
+            let path = write_gltf(&format!("malformed_{}", i), primitive, "");
+            let result = load_gltf(&path);
+            assert!(
+                matches!(
+                    result,
+                    Err(Error::MalformedAccessor {
+                        mesh: 0,
+                        primitive: 0,
+                        ..
+                    })
+                ),
+                "primitive {}: {:?}",
+                i,
+                result.err()
+            );
+        }
+    }
+}
+
+mod gltf_textures {
+    use super::helpers::{encode_base64, gltf_buffer, gltf_document, temp_path, write_gltf};
+    use crate::error::Error;
+    use crate::scene::Scene;
+    use crate::texture::*;
+    use glam::Vec4;
+
+    // 2x1 png, red on the left and green on the right
+    fn png_bytes() -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let mut encoder = png::Encoder::new(&mut bytes, 2, 1);
+        encoder.set_color(png::ColorType::Rgb);
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&[255, 0, 0, 0, 255, 0]).unwrap();
+        writer.finish().unwrap();
+        bytes
+    }
+
+    // the fixture triangle with uvs and material 0
+    const PRIMITIVE: &str = r#"{"attributes": {"POSITION": 0, "TEXCOORD_0": 5}, "material": 0}"#;
+
+    // base color only, sampling texture 0
+    const ALBEDO: &str = r#"{"name": "albedo", "pbrMetallicRoughness": {
+        "baseColorTexture": {"index": 0}
+    }}"#;
+
+    // `material` is material 0, its texture 0 samples `image` with a nearest, clamped sampler
+    fn textured(material: &str, image: &str) -> String {
+        format!(
+            r#",
+                "materials": [{material}],
+                "textures": [{{"source": 0, "sampler": 0}}],
+                "samplers": [{{"magFilter": 9728, "minFilter": 9728,
+                               "wrapS": 33071, "wrapT": 33071}}],
+                "images": [{image}]"#,
+        )
+    }
+
+    fn chunk(glb: &mut Vec<u8>, kind: &[u8; 4], mut data: Vec<u8>, padding: u8) {
+        while data.len() % 4 != 0 {
+            data.push(padding);
+        }
+        glb.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        glb.extend_from_slice(kind);
+        glb.extend_from_slice(&data);
+    }
+
+    fn assert_bound(scene: &Scene) {
+        let (mesh, _) = scene.instances().next().unwrap();
+        let material = scene.material(mesh.submeshes[0].material).unwrap();
+        assert_eq!(material.name.as_deref(), Some("albedo"));
+        let (texture, sampler) = scene.base_color_texture(material).unwrap();
+        assert_eq!((texture.width, texture.height), (2, 1));
+        assert_eq!(sampler.mag_filter, Filter::Nearest);
+        assert_eq!(sampler.wrap_u, Wrap::ClampToEdge);
+        let uv = glam::vec2(0.75, 0.5);
+        let color = texture.sample(&sampler, uv, glam::Vec2::ZERO, glam::Vec2::ZERO);
+        assert!((color - Vec4::new(0.0, 1.0, 0.0, 1.0)).abs().max_element() < 1e-6);
+    }
+
+    #[test]
+    fn data_uri_images_are_decoded() {
+        let uri = format!("data:image/png;base64,{}", encode_base64(&png_bytes()));
+        let path = write_gltf(
+            "data_uri",
+            PRIMITIVE,
+            &textured(ALBEDO, &format!(r#"{{"uri": "{}"}}"#, uri)),
+        );
+        assert_bound(&Scene::load(&path).unwrap());
+    }
+
+    #[test]
+    fn external_images_are_loaded_relative_to_the_document() {
+        std::fs::write(temp_path("external albedo.png"), png_bytes()).unwrap();
+        let path = write_gltf(
+            "external",
+            PRIMITIVE,
+            &textured(ALBEDO, r#"{"uri": "external%20albedo.png"}"#),
+        );
+        assert_bound(&Scene::load(&path).unwrap());
+    }
+
+    #[test]
+    fn missing_external_images_are_not_found() {
+        let path = write_gltf(
+            "missing_image",
+            PRIMITIVE,
+            &textured(ALBEDO, r#"{"uri": "missing.png"}"#),
+        );
+        match Scene::load(&path) {
+            Err(Error::NotFound(missing)) => assert_eq!(missing, temp_path("missing.png")),
+            other => panic!("expected NotFound, got {:?}", other.err()),
+        }
+    }
+
+    #[test]
+    fn glb_buffer_view_images_are_decoded() {
+        let mut bin = gltf_buffer();
+        let image = png_bytes();
+        let view = format!(
+            r#", {{"buffer": 0, "byteOffset": {}, "byteLength": {}}}"#,
+            bin.len(),
+            image.len()
+        );
+        bin.extend_from_slice(&image);
+        let image = textured(ALBEDO, r#"{"bufferView": 4, "mimeType": "image/png"}"#);
+        let json = gltf_document(PRIMITIVE, &image, "", bin.len(), &view);
+
+        let mut chunks = Vec::new();
+        chunk(&mut chunks, b"JSON", json.into_bytes(), b' ');
+        chunk(&mut chunks, b"BIN\0", bin, 0);
+        let mut glb = Vec::new();
+        glb.extend_from_slice(b"glTF");
+        glb.extend_from_slice(&2u32.to_le_bytes());
+        glb.extend_from_slice(&(12 + chunks.len() as u32).to_le_bytes());
+        glb.extend_from_slice(&chunks);
+        let path = temp_path("buffer_view.glb");
+        std::fs::write(&path, glb).unwrap();
+
+        assert_bound(&Scene::load(&path).unwrap());
+    }
+
+    #[test]
+    fn pbr_material_properties_are_loaded() {
+        let uri = format!("data:image/png;base64,{}", encode_base64(&png_bytes()));
+        let material = r#"{
+            "pbrMetallicRoughness": {
+                "baseColorTexture": {"index": 0}, "baseColorFactor": [0.5, 0.5, 1, 1],
+                "metallicFactor": 0.25, "roughnessFactor": 0.75,
+                "metallicRoughnessTexture": {"index": 0}
+            },
+            "normalTexture": {"index": 0, "scale": 2},
+            "occlusionTexture": {"index": 0, "strength": 0.5},
+            "emissiveTexture": {"index": 0}, "emissiveFactor": [1, 0.5, 0]
+        }"#;
+        let image = format!(r#"{{"uri": "{}"}}"#, uri);
+        let path = write_gltf("pbr", PRIMITIVE, &textured(material, &image));
+
+        let scene = Scene::load(&path).unwrap();
+        let material = &scene.materials[0];
+        assert_eq!(material.base_color_factor, Vec4::new(0.5, 0.5, 1.0, 1.0));
+        assert_eq!(material.metallic_factor, 0.25);
+        assert_eq!(material.roughness_factor, 0.75);
+        assert_eq!(material.normal_scale, 2.0);
+        assert_eq!(material.occlusion_strength, 0.5);
+        assert_eq!(material.emissive_factor, glam::vec3(1.0, 0.5, 0.0));
+        assert_eq!(material.clone().textures_mut().count(), 5);
+    }
+
+    #[test]
+    fn models_without_materials_have_no_texture() {
+        let scene = Scene::load(std::path::Path::new("assets/tests/cube.gltf")).unwrap();
+        assert!(scene.textures.is_empty());
+        assert!(scene.meshes[0]
+            .submeshes
+            .iter()
+            .all(|s| s.material.is_none()));
+    }
+}
+
+mod scenes {
+    use super::helpers::{approx_eq, encode_base64, temp_path};
+    use crate::error::{Error, Result};
+    use crate::scene::Scene;
+    use crate::utils::geometry::{Mesh, Submesh, Vertex};
+    use crate::utils::load_gltf;
+    use glam::{Mat4, UVec3, Vec3, Vec4};
+    use std::path::Path;
+
+    #[test]
+    fn nodes_are_loaded_recursively() {
+        let scene = Scene::load(Path::new("assets/tests/scene.gltf")).unwrap();
+        let names: Vec<_> = scene.nodes.iter().map(|n| n.name.as_deref()).collect();
+        assert_eq!(
+            names,
+            [
+                Some("parent"),
+                Some("child"),
+                Some("grandchild"),
+                Some("matrix")
+            ]
+        );
+        assert_eq!(scene.roots, [0, 3]);
+        assert_eq!(scene.nodes[0].children, [1]);
+        assert_eq!(scene.nodes[2].parent, Some(1));
+        assert_eq!(scene.meshes.len(), 1);
+        // the child without a mesh is skipped, the shared mesh is drawn three times
+        assert_eq!(scene.instances().count(), 3);
+    }
+
+    #[test]
+    fn world_matrices_compose_parent_transforms() {
+        let scene = Scene::load(Path::new("assets/tests/scene.gltf")).unwrap();
+        let grandchild = scene.nodes[2].world;
+        let expected = scene.nodes[0].transform.get_local()
+            * scene.nodes[1].transform.get_local()
+            * scene.nodes[2].transform.get_local();
+        assert!(grandchild.abs_diff_eq(expected, 1e-6));
+
+        // the grandchild sits 1.5 above the child, which is scaled by a half
+        let origin = grandchild.transform_point3(Vec3::ZERO);
+        let child_origin = scene.nodes[1].world.transform_point3(Vec3::ZERO);
+        assert!(approx_eq(
+            origin - child_origin,
+            Vec3::new(0.0, 0.75, 0.0),
+            1e-5
+        ));
+
+        let matrix = scene.nodes[3].world;
+        assert!(approx_eq(
+            matrix.transform_point3(Vec3::ONE),
+            Vec3::new(2.75, -0.25, 0.75),
+            1e-5
+        ));
+    }
+
+    #[test]
+    fn world_matrices_follow_changed_transforms() {
+        let mut scene = Scene::load(Path::new("assets/tests/scene.gltf")).unwrap();
+        scene.nodes[0].transform.translation = Vec3::ZERO;
+        scene.update_world_matrices();
+        let origin = scene.nodes[2].world.transform_point3(Vec3::ZERO);
+        let rotation = Mat4::from_quat(scene.nodes[0].transform.rotation);
+        let expected = rotation.transform_point3(Vec3::new(0.0, 2.5 + 0.75, 0.0));
+        assert!(approx_eq(origin, expected, 1e-5));
+    }
+
+    // scene.gltf with `node` replaced, which must appear exactly once so that an edit of the
+    // asset can't make the test pass without the broken node
+    fn load_with_replaced_node(name: &str, node: &str, replacement: &str) -> Result<Scene> {
+        let json = std::fs::read_to_string("assets/tests/scene.gltf").unwrap();
+        assert_eq!(json.matches(node).count(), 1, "{} in scene.gltf", node);
+        let path = temp_path(&format!("{}.gltf", name));
+        std::fs::write(&path, json.replacen(node, replacement, 1)).unwrap();
+        Scene::load(&path)
+    }
+
+    #[test]
+    fn node_cycles_and_shared_children_are_errors() {
+        let grandchild = r#"{"name": "grandchild", "translation": [0, 1.5, 0], "mesh": 0}"#;
+        let cycle = r#"{"name": "grandchild", "children": [0], "mesh": 0}"#;
+        let result = load_with_replaced_node("node_cycle", grandchild, cycle);
+        assert!(
+            matches!(result, Err(Error::Decode { message, .. }) if message.contains("more than once"))
+        );
+
+        let matrix = r#"{"name": "matrix","#;
+        let shared = r#"{"name": "matrix", "children": [2],"#;
+        let result = load_with_replaced_node("shared_child", matrix, shared);
+        assert!(
+            matches!(result, Err(Error::Decode { message, .. }) if message.contains("more than once"))
+        );
+    }
+
+    // the cube split into two primitives with their own index accessors and materials,
+    // the second primitive has its own copy of the positions
+    const TWO_PRIMITIVES: &str = "assets/tests/two_primitives.gltf";
+
+    #[test]
+    fn primitives_are_loaded_as_submeshes() {
+        let scene = Scene::load(Path::new(TWO_PRIMITIVES)).unwrap();
+        let mesh = &scene.meshes[0];
+        assert_eq!(mesh.vertices.len(), 48);
+        assert_eq!(
+            mesh.submeshes,
+            [
+                Submesh {
+                    triangles: 0..6,
+                    material: Some(1)
+                },
+                Submesh {
+                    triangles: 6..12,
+                    material: Some(0)
+                },
+            ]
+        );
+        // the indices of the second primitive point at its own vertices
+        for triangle in &mesh.triangles[6..] {
+            assert!(triangle.min_element() >= 24);
+        }
+        let cube = load_gltf(Path::new("assets/tests/cube.gltf")).unwrap();
+        for (a, b) in mesh.triangles[6..].iter().zip(&cube.triangles[6..]) {
+            assert_eq!(*a, *b + 24);
+        }
+        let name = |submesh: &Submesh| scene.material(submesh.material).unwrap().name.clone();
+        assert_eq!(name(&mesh.submeshes[0]).as_deref(), Some("second"));
+        // attributes missing from one primitive don't shift the others
+        assert_eq!(mesh.vertices[24].uv, cube.vertices[0].uv);
+        assert_eq!(mesh.vertices[4].normal, cube.vertices[4].normal);
+    }
+
+    #[test]
+    fn adding_meshes_keeps_their_submeshes() {
+        let cube = load_gltf(Path::new("assets/tests/cube.gltf")).unwrap();
+        let mut two = Scene::load(Path::new(TWO_PRIMITIVES)).unwrap();
+        let sum = cube + two.meshes.remove(0);
+        let ranges: Vec<_> = sum.submeshes.iter().map(|s| s.triangles.clone()).collect();
+        assert_eq!(ranges, [0..12, 12..18, 18..24]);
+        assert_eq!(sum.submeshes[2].material, Some(0));
+        assert_eq!(sum.triangles[12], sum.triangles[0] + 24);
+    }
+
+    // one triangle in the xy plane with u along x and v along y, optionally with tangents
+    fn write_triangle(name: &str, tangent: Option<[f32; 4]>) -> std::path::PathBuf {
+        let mut bin = Vec::new();
+        let mut push = |values: &[f32]| {
+            for value in values {
+                bin.extend_from_slice(&value.to_le_bytes());
+            }
         };
+        push(&[0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0]);
+        push(&[0.0, 0.0, 1.0].repeat(3));
+        push(&[0.0, 0.0, 1.0, 0.0, 0.0, 1.0]);
+        let mut attributes = r#""POSITION": 0, "NORMAL": 1, "TEXCOORD_0": 2"#.to_string();
+        if let Some(tangent) = tangent {
+            push(&tangent.repeat(3));
+            attributes += r#", "TANGENT": 3"#;
+        }
+        let json = format!(
+            r#"{{
+                "asset": {{"version": "2.0"}},
+                "scenes": [{{"nodes": [0]}}],
+                "nodes": [{{"mesh": 0}}],
+                "meshes": [{{"primitives": [{{"attributes": {{{attributes}}}}}]}}],
+                "buffers": [{{"byteLength": {length},
+                              "uri": "data:application/octet-stream;base64,{data}"}}],
+                "bufferViews": [
+                    {{"buffer": 0, "byteOffset": 0, "byteLength": 36}},
+                    {{"buffer": 0, "byteOffset": 36, "byteLength": 36}},
+                    {{"buffer": 0, "byteOffset": 72, "byteLength": 24}},
+                    {{"buffer": 0, "byteOffset": 96, "byteLength": 48}}
+                ],
+                "accessors": [
+                    {{"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                      "min": [0, 0, 0], "max": [1, 1, 0]}},
+                    {{"bufferView": 1, "componentType": 5126, "count": 3, "type": "VEC3"}},
+                    {{"bufferView": 2, "componentType": 5126, "count": 3, "type": "VEC2"}},
+                    {{"bufferView": 3, "componentType": 5126, "count": 3, "type": "VEC4"}}
+                ]
+            }}"#,
+            attributes = attributes,
+            length = bin.len(),
+            data = encode_base64(&bin),
+        );
+        let path = temp_path(&format!("{}.gltf", name));
+        std::fs::write(&path, json).unwrap();
+        path
+    }
+
+    #[test]
+    fn gltf_tangents_are_loaded() {
+        let path = write_triangle("tangents", Some([0.0, 1.0, 0.0, -1.0]));
+        let mesh = load_gltf(&path).unwrap();
+        for vertex in &mesh.vertices {
+            assert_eq!(vertex.tangent, glam::Vec4::new(0.0, 1.0, 0.0, -1.0));
+        }
+    }
 
-        let v1 = Vertex {
-            position: glam::vec4(0.0, -1.0, 1.0, 1.0),
-            color: glam::vec3(1.0, 0.0, 0.0),
-            normal: glam::vec3(0.0, 0.0, 1.0),
-            uv: glam::vec2(0.5, 1.0),
+    #[test]
+    fn missing_tangents_are_generated() {
+        let mesh = load_gltf(&write_triangle("no_tangents", None)).unwrap();
+        for vertex in &mesh.vertices {
+            // u grows along x and cross(normal, tangent) = y is the direction of v
+            assert!(vertex
+                .tangent
+                .abs_diff_eq(glam::Vec4::new(1.0, 0.0, 0.0, 1.0), 1e-5));
+        }
+
+        let cube = load_gltf(Path::new("assets/tests/cube.gltf")).unwrap();
+        for vertex in &cube.vertices {
+            let tangent = vertex.tangent.truncate();
+            assert!((tangent.length() - 1.0).abs() < 1e-4);
+            assert!(tangent.dot(vertex.normal).abs() < 1e-4);
+            assert_eq!(vertex.tangent.w.abs(), 1.0);
+        }
+    }
+
+    #[test]
+    fn mirrored_uv_seams_split_the_shared_vertices() {
+        // two triangles sharing the edge x = 0, the uvs of the right one are mirrored in u
+        let vertex = |x: f32, y: f32, u: f32| {
+            let position = glam::vec4(x, y, 0.0, 1.0);
+            Vertex::create(position, Vec3::ONE, Vec3::Z, glam::vec2(u, y))
         };
+        let vertices = [
+            vertex(-1.0, 0.0, 0.0),
+            vertex(0.0, 0.0, 1.0),
+            vertex(0.0, 1.0, 1.0),
+            vertex(1.0, 0.0, 0.0),
+        ];
+        let triangles = [UVec3::new(0, 1, 2), UVec3::new(1, 3, 2)];
+        let mut mesh = Mesh::from_vertices(&triangles, &vertices);
+        assert!(mesh.generate_tangents());
+
+        // both seam vertices exist once per side, with the positions and uvs of the input
+        assert_eq!(mesh.vertices.len(), 6);
+        assert_eq!(mesh.triangles.len(), 2);
+        let expected = [
+            Vec4::new(1.0, 0.0, 0.0, 1.0),
+            Vec4::new(-1.0, 0.0, 0.0, -1.0),
+        ];
+        for (triangle, (expected, input)) in
+            mesh.triangles.iter().zip(expected.iter().zip(triangles))
+        {
+            for (index, source) in triangle.to_array().into_iter().zip(input.to_array()) {
+                let vertex = &mesh.vertices[index as usize];
+                assert!(vertex.tangent.abs_diff_eq(*expected, 1e-5));
+                assert_eq!(vertex.position, vertices[source as usize].position);
+                assert_eq!(vertex.uv, vertices[source as usize].uv);
+            }
+        }
+    }
+}
 
-        let v2 = Vertex {
-            position: glam::vec4(1.0, 1.0, 1.0, 1.0),
-            color: glam::vec3(0.0, -0.0, 1.0),
-            normal: glam::vec3(0.0, 0.0, 1.0),
-            uv: glam::vec2(1.0, 0.0),
+mod pbr {
+    use super::helpers::approx_eq;
+    use crate::light::Light;
+    use crate::material::{Material, TextureBinding};
+    use crate::shader::*;
+    use crate::texture::*;
+    use glam::{Vec2, Vec3, Vec4, Vec4Swizzles};
+    use std::f32::consts::PI;
+
+    // fragment facing +z with its tangent along +x, seen from straight ahead
+    fn fragment() -> Fragment<SurfaceVaryings> {
+        let varyings = SurfaceVaryings {
+            position: Vec4::W,
+            world_position: Vec3::ZERO,
+            normal: Vec3::Z,
+            tangent: Vec4::new(1.0, 0.0, 0.0, 1.0),
+            uv: Vec2::splat(0.5),
+            color: Vec3::ONE,
+        };
+        let zero = SurfaceVaryings {
+            position: Vec4::ZERO,
+            world_position: Vec3::ZERO,
+            normal: Vec3::ZERO,
+            tangent: Vec4::ZERO,
+            uv: Vec2::ZERO,
+            color: Vec3::ZERO,
         };
+        Fragment {
+            position: Vec2::ZERO,
+            depth: 0.5,
+            varyings,
+            ddx: zero,
+            ddy: zero,
+        }
+    }
 
-        // Texture
-        let texture = Texture::load(Path::new("assets/bojan.jpg"));
+    // directional light coming from `to_light`
+    fn light(to_light: Vec3, color: Vec3) -> [Light; 1] {
+        [Light::Directional {
+            direction: -to_light,
+            color,
+            intensity: 1.0,
+        }]
+    }
 
-        let aspect_ratio: f32 = 1.0;
+    // lit head-on
+    fn shade(material: &Material, textures: &[Texture], light_color: Vec3, ambient: Vec3) -> Vec3 {
+        let lights = light(Vec3::Z, light_color);
+        let mut shader = PbrShader::new(material, textures, &lights, Vec3::Z * 5.0);
+        shader.ambient = ambient;
+        shader.fragment(&fragment()).xyz()
+    }
 
-        let camera = Camera {
-            far_plane: 100.0,
-            aspect_ratio,
-            transform: Transform::from_translation(glam::vec3(0.0, 0.0, 5.0)),
-            ..Default::default()
+    fn texture(rgba: [u8; 4]) -> Texture {
+        Texture::new(1, 1, Texels::from_u8(&rgba, 4))
+    }
+
+    fn binding(texture: usize) -> Option<TextureBinding> {
+        Some(TextureBinding {
+            texture,
+            sampler: Sampler::NEAREST,
+            tex_coord: 0,
+        })
+    }
+
+    #[test]
+    fn defaults_follow_gltf() {
+        let material = Material::default();
+        assert_eq!(material.base_color_factor, Vec4::ONE);
+        assert_eq!(
+            (material.metallic_factor, material.roughness_factor),
+            (1.0, 1.0)
+        );
+        assert_eq!(material.emissive_factor, Vec3::ZERO);
+    }
+
+    #[test]
+    fn lambert_sums_the_lights() {
+        let point = |z: f32| Light::Point {
+            position: Vec3::new(0.0, 0.0, z),
+            color: Vec3::ONE,
+            intensity: 4.0 * PI,
+            range: None,
         };
+        // the light below the surface only leaves the ambient term
+        let lights = [light(Vec3::Z, Vec3::X * PI)[0], point(2.0), point(-2.0)];
+        let mut shader = LambertShader::new(None, &lights);
+        shader.ambient = Vec3::splat(0.1);
+        let color = shader.fragment(&fragment()).xyz();
+        assert!(approx_eq(color, Vec3::new(2.1, 1.1, 1.1), 1e-4));
+    }
 
-        // MVP
-        let proj = camera.projection();
-        let view = camera.view();
-        let mvp = proj * view * Transform::IDENTITY.get_local();
+    #[test]
+    fn emissive_is_added_unlit() {
+        let material = Material {
+            base_color_factor: Vec4::W,
+            emissive_factor: Vec3::new(0.5, 1.0, 1.0),
+            emissive_texture: binding(0),
+            ..Default::default()
+        };
+        let textures = [texture([255, 128, 0, 255])];
+        let color = shade(&material, &textures, Vec3::ZERO, Vec3::ONE);
+        assert!(approx_eq(color, Vec3::new(0.5, 128.0 / 255.0, 0.0), 1e-4));
+    }
 
-        let triangle = [&v0, &v1, &v2];
+    #[test]
+    fn metallic_roughness_texture_scales_the_factors() {
+        // green is roughness, blue is metalness
+        let textures = [texture([0, 128, 255, 255])];
+        let textured = Material {
+            metallic_roughness_texture: binding(0),
+            metallic_factor: 0.5,
+            roughness_factor: 1.0,
+            ..Default::default()
+        };
+        let factors = Material {
+            metallic_factor: 0.5,
+            roughness_factor: 128.0 / 255.0,
+            ..Default::default()
+        };
+        let light = Vec3::splat(std::f32::consts::PI);
+        assert!(approx_eq(
+            shade(&textured, &textures, light, Vec3::ZERO),
+            shade(&factors, &[], light, Vec3::ZERO),
+            1e-4
+        ));
+    }
 
-        screen.raster_triangle(
-            &triangle,
-            Some(&texture),
-            &mvp,
-            &Transform::IDENTITY.get_local(),
+    #[test]
+    fn white_dielectric_reflects_the_light() {
+        // a rough white surface lit head-on by a light of intensity pi is close to white,
+        // with some of the energy in the specular reflection instead of the diffuse one
+        let material = Material {
+            metallic_factor: 0.0,
+            ..Default::default()
+        };
+        let light = Vec3::splat(std::f32::consts::PI);
+        let color = shade(&material, &[], light, Vec3::ZERO);
+        assert!(
+            color.min_element() > 0.9 && color.max_element() < 1.1,
+            "{}",
+            color
         );
     }
 
-    pub fn _test_textured_quad(screen: &mut Screen) {
-        // Quad
-        let v0 = Vertex {
-            position: glam::vec4(-1.0, -1.0, 0.0, 1.0),
-            color: glam::vec3(0.0, 1.0, 1.0),
-            normal: glam::vec3(0.0, 0.0, 1.0),
-            uv: glam::vec2(0.0, 1.0),
+    #[test]
+    fn metals_have_no_diffuse() {
+        let material = Material {
+            base_color_factor: Vec4::new(1.0, 0.5, 0.25, 1.0),
+            ..Default::default()
         };
-        let v1 = Vertex {
-            position: glam::vec4(-1.0, 1.0, 0.0, 1.0),
-            color: glam::vec3(1.0, 0.0, 0.0),
-            normal: glam::vec3(0.0, 0.0, 1.0),
-            uv: glam::vec2(0.0, 0.0),
+        // light from behind the surface
+        let lights = light(-Vec3::Z, Vec3::ONE);
+        let mut shader = PbrShader::new(&material, &[], &lights, Vec3::Z * 5.0);
+        shader.ambient = Vec3::ZERO;
+        assert_eq!(shader.fragment(&fragment()).xyz(), Vec3::ZERO);
+    }
+
+    #[test]
+    fn occlusion_only_darkens_the_ambient_light() {
+        let material = Material {
+            metallic_factor: 0.0,
+            occlusion_texture: binding(0),
+            occlusion_strength: 0.5,
+            ..Default::default()
         };
-        let v2 = Vertex {
-            position: glam::vec4(1.0, 1.0, 0.0, 1.0),
-            color: glam::vec3(0.0, 1.0, 0.0),
-            normal: glam::vec3(0.0, 0.0, 1.0),
-            uv: glam::vec2(1.0, 0.0),
+        let textures = [texture([64, 255, 255, 255])];
+        let color = shade(&material, &textures, Vec3::ZERO, Vec3::ONE);
+        let occlusion = 1.0 + 0.5 * (64.0 / 255.0 - 1.0);
+        assert!(approx_eq(color, Vec3::splat(occlusion), 1e-4));
+    }
+
+    // lit from +x, so only a normal tilted towards +x receives direct light
+    fn shade_from_side(material: &Material, textures: &[Texture], tangent: Vec4) -> Vec3 {
+        let lights = light(Vec3::X, Vec3::splat(std::f32::consts::PI));
+        let mut shader = PbrShader::new(material, textures, &lights, Vec3::Z * 5.0);
+        shader.ambient = Vec3::ZERO;
+        let mut fragment = fragment();
+        fragment.varyings.tangent = tangent;
+        shader.fragment(&fragment).xyz()
+    }
+
+    fn normal_mapped(normal: [u8; 4]) -> (Material, [Texture; 1]) {
+        let material = Material {
+            metallic_factor: 0.0,
+            normal_texture: binding(0),
+            ..Default::default()
         };
-        let v3 = Vertex {
-            position: glam::vec4(1.0, -1.0, 0.0, 1.0),
-            color: glam::vec3(0.0, 1.0, 1.0),
-            normal: glam::vec3(0.0, 0.0, 1.0),
-            uv: glam::vec2(1.0, 1.0),
+        (material, [texture(normal)])
+    }
+
+    #[test]
+    fn flat_normal_maps_keep_the_normal() {
+        let (material, textures) = normal_mapped([128, 128, 255, 255]);
+        let tangent = Vec4::new(1.0, 0.0, 0.0, 1.0);
+        let flat = shade_from_side(&material, &textures, tangent);
+        assert!(flat.max_element() < 0.01, "{}", flat);
+    }
+
+    #[test]
+    fn normal_maps_tilt_the_normal_along_the_tangent() {
+        // the texel points halfway between the normal and the tangent
+        let (material, textures) = normal_mapped([218, 128, 218, 255]);
+        let lit = shade_from_side(&material, &textures, Vec4::new(1.0, 0.0, 0.0, 1.0));
+        assert!(lit.min_element() > 0.5, "{}", lit);
+        // with the tangent pointing the other way the normal tilts away from the light
+        let away = shade_from_side(&material, &textures, Vec4::new(-1.0, 0.0, 0.0, 1.0));
+        assert!(away.max_element() < 0.01, "{}", away);
+        // without tangents the map is ignored
+        let ignored = shade_from_side(&material, &textures, Vec4::ZERO);
+        assert!(ignored.max_element() < 0.01, "{}", ignored);
+    }
+
+    #[test]
+    fn bitangent_follows_the_handedness() {
+        // the texel points halfway between the normal and the bitangent, light comes from +y
+        let (material, textures) = normal_mapped([128, 218, 218, 255]);
+        let shade = |w: f32| {
+            let lights = light(Vec3::Y, Vec3::splat(std::f32::consts::PI));
+            let mut shader = PbrShader::new(&material, &textures, &lights, Vec3::Z * 5.0);
+            shader.ambient = Vec3::ZERO;
+            let mut fragment = fragment();
+            fragment.varyings.tangent = Vec4::new(1.0, 0.0, 0.0, w);
+            shader.fragment(&fragment).xyz()
         };
+        // cross(+z, +x) is +y
+        assert!(shade(1.0).min_element() > 0.5);
+        assert!(shade(-1.0).max_element() < 0.01);
+    }
 
-        // Texture
-        let texture = Texture::load(Path::new("assets/bojan.jpg"));
+    #[test]
+    fn tangents_are_transformed_with_the_model_matrix() {
+        let model = glam::Mat4::from_scale_rotation_translation(
+            Vec3::new(2.0, 1.0, 1.0),
+            glam::Quat::from_rotation_z(std::f32::consts::FRAC_PI_2),
+            Vec3::ONE,
+        );
+        let mut vertex =
+            crate::utils::geometry::Vertex::create(Vec4::W, Vec3::ONE, Vec3::Z, Vec2::ZERO);
+        vertex.tangent = Vec4::new(1.0, 0.0, 0.0, -1.0);
+        let output = SurfaceVertexShader::new(&glam::Mat4::IDENTITY, &model).vertex(&vertex);
+        assert!(output
+            .tangent
+            .abs_diff_eq(Vec4::new(0.0, 2.0, 0.0, -1.0), 1e-6));
+        let output = StandardVertexShader::new(&model, &model).vertex(&vertex);
+        assert!(output
+            .tangent
+            .abs_diff_eq(Vec4::new(0.0, 2.0, 0.0, -1.0), 1e-6));
+    }
+}
 
-        let aspect_ratio: f32 = 1.0;
+mod lights {
+    use super::helpers::{approx_eq, temp_path};
+    use crate::light::Light;
+    use crate::scene::Scene;
+    use glam::{Mat4, Quat, Vec3};
+
+    fn point(range: Option<f32>) -> Light {
+        Light::Point {
+            position: Vec3::new(0.0, 2.0, 0.0),
+            color: Vec3::new(1.0, 0.5, 0.25),
+            intensity: 8.0,
+            range,
+        }
+    }
 
-        let camera = Camera {
-            far_plane: 100.0,
-            aspect_ratio,
-            transform: Transform::from_translation(glam::vec3(0.0, 0.0, 5.0)),
-            ..Default::default()
+    #[test]
+    fn directional_lights_come_from_the_opposite_direction() {
+        let light = Light::Directional {
+            direction: -Vec3::Y,
+            color: Vec3::ONE,
+            intensity: 2.0,
         };
+        let (to_light, radiance) = light.incident(Vec3::new(5.0, -3.0, 1.0)).unwrap();
+        assert_eq!(to_light, Vec3::Y);
+        assert_eq!(radiance, Vec3::splat(2.0));
+    }
+
+    #[test]
+    fn point_lights_fall_off_with_the_squared_distance() {
+        let (to_light, radiance) = point(None).incident(Vec3::ZERO).unwrap();
+        assert!(approx_eq(to_light, Vec3::Y, 1e-5));
+        assert!(approx_eq(radiance, Vec3::new(2.0, 1.0, 0.5), 1e-5));
+    }
 
-        // MVP
-        let proj = camera.projection();
-        let view = camera.view();
-        let mvp = proj * view * Transform::IDENTITY.get_local();
+    #[test]
+    fn the_range_fades_lights_out() {
+        let (_, radiance) = point(Some(4.0)).incident(Vec3::ZERO).unwrap();
+        let window = (1.0f32 - (4.0f32 / 16.0).powi(2)).powi(2);
+        assert!(approx_eq(radiance, Vec3::new(2.0, 1.0, 0.5) * window, 1e-5));
+        assert!(point(Some(4.0))
+            .incident(Vec3::new(0.0, -2.0, 0.0))
+            .is_none());
+    }
 
-        let triangle0 = [&v0, &v2, &v1];
-        let triangle1 = [&v0, &v3, &v2];
+    #[test]
+    fn spot_lights_fade_between_the_cones() {
+        let spot = Light::Spot {
+            position: Vec3::Y,
+            direction: -Vec3::Y,
+            color: Vec3::ONE,
+            intensity: 1.0,
+            range: None,
+            inner_cone_angle: 0.2,
+            outer_cone_angle: 0.4,
+        };
+        // at the given angle from the axis, one unit below the light
+        let at = |angle: f32| spot.incident(Vec3::new(angle.tan(), 0.0, 0.0));
+        let distance_squared = |angle: f32| 1.0 + angle.tan().powi(2);
+
+        let (_, center) = at(0.0).unwrap();
+        assert!(approx_eq(center, Vec3::ONE, 1e-5));
+        let (_, inner) = at(0.15).unwrap();
+        assert!(approx_eq(inner, Vec3::ONE / distance_squared(0.15), 1e-5));
+        let (_, between) = at(0.3).unwrap();
+        let falloff = between.x * distance_squared(0.3);
+        assert!(falloff > 0.0 && falloff < 1.0, "{}", falloff);
+        assert!(at(0.45).is_none());
+    }
 
-        screen.raster_triangle(
-            &triangle0,
-            Some(&texture),
-            &mvp,
-            &Transform::IDENTITY.get_local(),
+    #[test]
+    fn lights_follow_their_world_matrix() {
+        let spot = Light::Spot {
+            position: Vec3::ZERO,
+            direction: -Vec3::Z,
+            color: Vec3::ONE,
+            intensity: 1.0,
+            range: None,
+            inner_cone_angle: 0.0,
+            outer_cone_angle: 0.5,
+        };
+        let matrix = Mat4::from_rotation_translation(
+            Quat::from_rotation_x(-std::f32::consts::FRAC_PI_2),
+            Vec3::new(1.0, 2.0, 3.0),
         );
-        screen.raster_triangle(
-            &triangle1,
-            Some(&texture),
-            &mvp,
-            &Transform::IDENTITY.get_local(),
+        match spot.transformed(&matrix) {
+            Light::Spot {
+                position,
+                direction,
+                ..
+            } => {
+                assert!(approx_eq(position, Vec3::new(1.0, 2.0, 3.0), 1e-5));
+                assert!(approx_eq(direction, -Vec3::Y, 1e-5));
+            }
+            light => panic!("expected a spot light, got {:?}", light),
+        }
+    }
+
+    #[test]
+    fn khr_lights_punctual_are_imported() {
+        let json = r#"{
+            "asset": {"version": "2.0"},
+            "extensionsUsed": ["KHR_lights_punctual"],
+            "extensions": {"KHR_lights_punctual": {"lights": [
+                {"type": "spot", "color": [1, 0, 0], "intensity": 5, "range": 10,
+                 "spot": {"innerConeAngle": 0.1, "outerConeAngle": 0.3}},
+                {"type": "point", "intensity": 2},
+                {"type": "directional"}
+            ]}},
+            "scenes": [{"nodes": [0, 2]}],
+            "nodes": [
+                {"translation": [0, 2, 0], "children": [1]},
+                {"rotation": [-0.70710677, 0, 0, 0.70710677],
+                 "extensions": {"KHR_lights_punctual": {"light": 0}}},
+                {"translation": [3, 0, 0], "extensions": {"KHR_lights_punctual": {"light": 1}}}
+            ]
+        }"#;
+        let path = temp_path("lights.gltf");
+        std::fs::write(&path, json).unwrap();
+        let scene = Scene::load(&path).unwrap();
+        assert_eq!(scene.lights.len(), 3);
+
+        let lights = scene.world_lights();
+        assert_eq!(lights.len(), 2);
+        match lights[0] {
+            Light::Spot {
+                position,
+                direction,
+                color,
+                intensity,
+                range,
+                inner_cone_angle,
+                outer_cone_angle,
+            } => {
+                assert!(approx_eq(position, Vec3::new(0.0, 2.0, 0.0), 1e-5));
+                assert!(approx_eq(direction, -Vec3::Y, 1e-5));
+                assert_eq!((color, intensity, range), (Vec3::X, 5.0, Some(10.0)));
+                assert_eq!((inner_cone_angle, outer_cone_angle), (0.1, 0.3));
+            }
+            light => panic!("expected a spot light, got {:?}", light),
+        }
+        assert_eq!(
+            lights[1],
+            Light::Point {
+                position: Vec3::new(3.0, 0.0, 0.0),
+                color: Vec3::ONE,
+                intensity: 2.0,
+                range: None,
+            }
         );
     }
+}
+
+mod shadows {
+    use crate::light::Light;
+    use crate::material::{AlphaMode, Material, TextureBinding};
+    use crate::shadow::{ShadowMap, ShadowSettings};
+    use crate::texture::{Sampler, Texels, Texture};
+    use crate::utils::geometry::Mesh;
+    use crate::Screen;
+    use glam::{Mat4, Vec3};
+
+    // horizontal square facing up, u grows along x
+    fn quad(y: f32, half_size: f32) -> Mesh {
+        Mesh::quad(Vec3::Y * y, Vec3::X * half_size, -Vec3::Z * half_size)
+    }
 
-    pub fn _test_camera(screen: &mut Screen, rot: &mut f32) {
-        // Quad
-        let v0 = Vertex {
-            position: glam::vec4(-1.0, -1.0, 0.0, 1.0),
-            color: glam::vec3(0.0, 1.0, 1.0),
-            normal: glam::vec3(0.0, 0.0, 1.0),
-            uv: glam::vec2(0.0, 1.0),
+    fn sun() -> Light {
+        Light::Directional {
+            direction: -Vec3::Y,
+            color: Vec3::ONE,
+            intensity: 1.0,
+        }
+    }
+
+    // into a new depth-only target
+    fn render(
+        light: &Light,
+        casters: &[(&Mesh, Mat4)],
+        materials: &[Material],
+        textures: &[Texture],
+        settings: ShadowSettings,
+    ) -> Option<ShadowMap> {
+        let mut target = Screen::create_depth_only(settings.size, settings.size);
+        ShadowMap::render(
+            &mut target,
+            0,
+            light,
+            casters,
+            materials,
+            textures,
+            settings,
+        )
+    }
+
+    fn settings() -> ShadowSettings {
+        ShadowSettings {
+            size: 128,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn occluders_shadow_the_floor_below() {
+        let (floor, occluder) = (quad(0.0, 4.0), quad(1.0, 1.0));
+        let casters = [(&floor, Mat4::IDENTITY), (&occluder, Mat4::IDENTITY)];
+        let map = render(&sun(), &casters, &[], &[], settings()).unwrap();
+        assert_eq!(map.visibility(Vec3::ZERO, 1.0), 0.0);
+        assert_eq!(map.visibility(glam::vec3(3.0, 0.0, 3.0), 1.0), 1.0);
+        // the occluder doesn't shadow itself
+        assert_eq!(map.visibility(glam::vec3(0.5, 1.0, 0.5), 1.0), 1.0);
+    }
+
+    #[test]
+    fn cutouts_cast_shadows_with_holes() {
+        let floor = quad(0.0, 4.0);
+        let mut occluder = quad(1.0, 1.0);
+        occluder.submeshes[0].material = Some(0);
+        let casters = [(&floor, Mat4::IDENTITY), (&occluder, Mat4::IDENTITY)];
+        // the right half of the texture is cut out
+        let textures = [Texture::new(
+            2,
+            1,
+            Texels::from_u8(&[255, 255, 255, 255, 255, 255, 255, 0], 4),
+        )];
+        let mut material = Material {
+            alpha_mode: AlphaMode::Mask,
+            base_color_texture: Some(TextureBinding {
+                texture: 0,
+                sampler: Sampler::NEAREST,
+                tex_coord: 0,
+            }),
+            ..Default::default()
         };
-        let v1 = Vertex {
-            position: glam::vec4(-1.0, 1.0, 0.0, 1.0),
-            color: glam::vec3(1.0, 0.0, 0.0),
-            normal: glam::vec3(0.0, 0.0, 1.0),
-            uv: glam::vec2(0.0, 0.0),
+
+        let materials = [material.clone()];
+        let map = render(&sun(), &casters, &materials, &textures, settings());
+        let map = map.unwrap();
+        assert_eq!(map.visibility(glam::vec3(-0.5, 0.0, 0.0), 1.0), 0.0);
+        assert_eq!(map.visibility(glam::vec3(0.5, 0.0, 0.0), 1.0), 1.0);
+
+        // the same material without the cutoff shadows both halves
+        material.alpha_mode = AlphaMode::Opaque;
+        let materials = [material];
+        let map = render(&sun(), &casters, &materials, &textures, settings());
+        assert_eq!(map.unwrap().visibility(glam::vec3(0.5, 0.0, 0.0), 1.0), 0.0);
+    }
+
+    #[test]
+    fn pcf_softens_the_shadow_edges() {
+        let (floor, occluder) = (quad(0.0, 4.0), quad(1.0, 1.0));
+        let casters = [(&floor, Mat4::IDENTITY), (&occluder, Mat4::IDENTITY)];
+        let map = render(&sun(), &casters, &[], &[], settings()).unwrap();
+        let edge = (-8..=8)
+            .map(|i| map.visibility(glam::vec3(1.0 + i as f32 * 0.01, 0.0, 0.0), 1.0))
+            .collect::<Vec<_>>();
+        assert!(edge.iter().any(|&v| v > 0.0 && v < 1.0));
+
+        let hard = ShadowSettings {
+            pcf_radius: 0,
+            ..settings()
         };
-        let v2 = Vertex {
-            position: glam::vec4(1.0, 1.0, 0.0, 1.0),
-            color: glam::vec3(0.0, 1.0, 0.0),
-            normal: glam::vec3(0.0, 0.0, 1.0),
-            uv: glam::vec2(1.0, 0.0),
+        let map = render(&sun(), &casters, &[], &[], hard).unwrap();
+        let edge =
+            (-8..=8).map(|i| map.visibility(glam::vec3(1.0 + i as f32 * 0.01, 0.0, 0.0), 1.0));
+        assert!(edge.into_iter().all(|v| v == 0.0 || v == 1.0));
+    }
+
+    #[test]
+    fn the_bias_avoids_acne_on_tilted_receivers() {
+        let floor = quad(0.0, 4.0);
+        let light = Light::Directional {
+            direction: glam::vec3(1.0, -1.0, 0.3).normalize(),
+            color: Vec3::ONE,
+            intensity: 1.0,
         };
-        let v3 = Vertex {
-            position: glam::vec4(1.0, -1.0, 0.0, 1.0),
-            color: glam::vec3(0.0, 1.0, 1.0),
-            normal: glam::vec3(0.0, 0.0, 1.0),
-            uv: glam::vec2(1.0, 1.0),
+        let n_dot_l = light.incident(Vec3::ZERO).unwrap().0.y;
+        let map = render(&light, &[(&floor, Mat4::IDENTITY)], &[], &[], settings()).unwrap();
+        for i in 0..40 {
+            for j in 0..40 {
+                let p = glam::vec3(i as f32 * 0.19 - 3.7, 0.0, j as f32 * 0.19 - 3.7);
+                assert_eq!(map.visibility(p, n_dot_l), 1.0, "acne at {}", p);
+            }
+        }
+
+        // without any bias the floor shadows itself
+        let unbiased = ShadowSettings {
+            depth_bias: 0.0,
+            slope_bias: 0.0,
+            ..settings()
         };
+        let map = render(&light, &[(&floor, Mat4::IDENTITY)], &[], &[], unbiased).unwrap();
+        let acne = (0..40).filter(|i| {
+            let p = glam::vec3(*i as f32 * 0.19 - 3.7, 0.0, 0.3);
+            map.visibility(p, n_dot_l) < 1.0
+        });
+        assert!(acne.count() > 0);
+    }
 
-        // Texture
-        let texture = Texture::load(Path::new("assets/bojan.jpg"));
+    #[test]
+    fn spot_lights_cast_shadows_inside_their_cone() {
+        let (floor, occluder) = (quad(0.0, 4.0), quad(1.0, 0.5));
+        let casters = [(&floor, Mat4::IDENTITY), (&occluder, Mat4::IDENTITY)];
+        let light = Light::Spot {
+            position: glam::vec3(0.0, 3.0, 0.0),
+            direction: -Vec3::Y,
+            color: Vec3::ONE,
+            intensity: 1.0,
+            range: None,
+            inner_cone_angle: 0.4,
+            outer_cone_angle: 0.8,
+        };
+        let map = render(&light, &casters, &[], &[], settings()).unwrap();
+        assert_eq!(map.visibility(Vec3::ZERO, 1.0), 0.0);
+        // the shadow of the occluder grows with the distance to the light
+        let n_dot_l = light.incident(glam::vec3(0.6, 0.0, 0.0)).unwrap().0.y;
+        assert_eq!(map.visibility(glam::vec3(0.6, 0.0, 0.0), n_dot_l), 0.0);
+        let n_dot_l = light.incident(glam::vec3(1.5, 0.0, 0.0)).unwrap().0.y;
+        assert_eq!(map.visibility(glam::vec3(1.5, 0.0, 0.0), n_dot_l), 1.0);
+    }
 
-        let aspect_ratio: f32 = 1.0;
+    #[test]
+    fn point_lights_and_empty_scenes_have_no_map() {
+        let floor = quad(0.0, 1.0);
+        let point = Light::Point {
+            position: Vec3::Y,
+            color: Vec3::ONE,
+            intensity: 1.0,
+            range: None,
+        };
+        assert!(render(&point, &[(&floor, Mat4::IDENTITY)], &[], &[], settings()).is_none());
+        assert!(render(&sun(), &[], &[], &[], settings()).is_none());
+    }
 
-        let camera = Camera {
-            far_plane: 100.0,
-            aspect_ratio,
-            transform: Transform::from_translation(glam::vec3(0.0, 0.0, 5.0)),
-            ..Default::default()
+    #[test]
+    fn targets_are_reused_between_lights_and_sizes() {
+        let (floor, occluder) = (quad(0.0, 4.0), quad(1.0, 1.0));
+        let casters = [(&floor, Mat4::IDENTITY), (&occluder, Mat4::IDENTITY)];
+        let spot = Light::Spot {
+            position: glam::vec3(0.0, 3.0, 0.0),
+            direction: -Vec3::Y,
+            color: Vec3::ONE,
+            intensity: 1.0,
+            range: None,
+            inner_cone_angle: 0.4,
+            outer_cone_angle: 0.8,
+        };
+        let small = ShadowSettings {
+            size: 64,
+            ..settings()
         };
 
-        *rot += 0.05;
+        let mut target = Screen::create(32, 32).depth_target(64, 64);
+        for (light, settings) in [(&spot, small), (&sun(), settings()), (&sun(), small)] {
+            let reused = ShadowMap::render(&mut target, 0, light, &casters, &[], &[], settings);
+            let new = render(light, &casters, &[], &[], settings);
+            assert_eq!(reused.unwrap().depth, new.unwrap().depth);
+            assert!(target.is_depth_only());
+            assert_eq!(target.width, settings.size);
+        }
+    }
 
-        // MVP
-        let transform =
-            Transform::from_rotation(glam::Quat::from_euler(glam::EulerRot::XYZ, *rot, 0.0, 0.0));
-        let proj = camera.projection();
-        let view = camera.view();
+    #[test]
+    fn spot_directions_are_normalized_and_wide_cones_clamped() {
+        let (floor, occluder) = (quad(0.0, 4.0), quad(1.0, 0.5));
+        let casters = [(&floor, Mat4::IDENTITY), (&occluder, Mat4::IDENTITY)];
+        let spot = |direction: Vec3, outer_cone_angle: f32| Light::Spot {
+            position: glam::vec3(0.0, 3.0, 0.0),
+            direction,
+            color: Vec3::ONE,
+            intensity: 1.0,
+            range: None,
+            inner_cone_angle: 0.0,
+            outer_cone_angle,
+        };
 
-        let mvp = proj * view * transform.get_local();
+        let unit = render(&spot(-Vec3::Y, 0.8), &casters, &[], &[], settings()).unwrap();
+        let long = render(&spot(-Vec3::Y * 3.0, 0.8), &casters, &[], &[], settings()).unwrap();
+        assert_eq!(unit.view_projection, long.view_projection);
+        assert_eq!(unit.depth, long.depth);
+
+        // the texels of the widest map are large, so the occluder is too
+        let occluder = quad(1.0, 1.5);
+        let casters = [(&floor, Mat4::IDENTITY), (&occluder, Mat4::IDENTITY)];
+        let half_sphere = std::f32::consts::FRAC_PI_2;
+        let wide = render(&spot(-Vec3::Y, half_sphere), &casters, &[], &[], settings()).unwrap();
+        assert!(wide.view_projection.is_finite());
+        assert_eq!(wide.visibility(Vec3::ZERO, 1.0), 0.0);
+        let n_dot_l = std::f32::consts::FRAC_1_SQRT_2;
+        assert_eq!(wide.visibility(glam::vec3(3.0, 0.0, 0.0), n_dot_l), 1.0);
+    }
+}
+
+mod blending {
+    use super::helpers::*;
+    use crate::blend::{BlendMode, OitTexel};
+    use crate::material::{AlphaMode, Material};
+    use crate::raster::DrawState;
+    use crate::scene::Scene;
+    use crate::Screen;
+    use glam::{Vec3, Vec4, Vec4Swizzles};
+    use std::path::Path;
 
-        let triangle0 = [&v0, &v2, &v1];
-        let triangle1 = [&v0, &v3, &v2];
+    #[test]
+    fn blend_modes_combine_source_and_destination() {
+        let source = Vec4::new(1.0, 0.5, 0.0, 0.25);
+        let destination = Vec4::new(0.0, 0.5, 1.0, 0.5);
+        let blend = |mode: BlendMode| mode.blend(source, destination);
+        assert_eq!(blend(BlendMode::Opaque), source);
+        // a quarter of the source over half of the destination covers 5/8
+        assert_eq!(blend(BlendMode::Alpha), Vec4::new(0.25, 0.5, 0.75, 0.625));
+        assert_eq!(
+            blend(BlendMode::Premultiplied),
+            Vec4::new(1.0, 0.875, 0.75, 0.625)
+        );
+        assert_eq!(blend(BlendMode::Additive), Vec4::new(0.25, 0.625, 1.0, 0.5));
+        assert_eq!(
+            blend(BlendMode::Multiply),
+            Vec4::new(0.0, 0.4375, 0.75, 0.5)
+        );
+        assert_eq!(blend(BlendMode::WeightedBlended), destination);
+    }
 
-        screen.raster_triangle(&triangle0, Some(&texture), &mvp, &transform.get_local());
-        screen.raster_triangle(&triangle1, Some(&texture), &mvp, &transform.get_local());
+    #[test]
+    fn transparent_draws_blend_without_writing_depth() {
+        let mut screen = Screen::create(8, 8);
+        draw_fullscreen(&mut screen, 0.5, Vec4::new(1.0, 0.0, 0.0, 1.0));
+        screen.state = DrawState::transparent(BlendMode::Alpha);
+        draw_fullscreen(&mut screen, 0.2, Vec4::new(0.0, 1.0, 0.0, 0.5));
+        let color = screen.hdr_buffer[0];
+        assert_eq!(color, Vec4::new(0.5, 0.5, 0.0, 1.0));
+        assert!(screen.z_buffer.iter().all(|&z| z == 0.5));
+
+        // behind the opaque surface
+        draw_fullscreen(&mut screen, 0.8, Vec4::new(0.0, 0.0, 1.0, 1.0));
+        assert_eq!(screen.hdr_buffer[0], color);
     }
 
-    pub fn _test_raster_mesh(screen: &mut Screen) {
-        // Quad
-        let v0 = Vertex {
-            position: glam::vec4(-2.0, -2.0, 0.0, 1.0),
-            color: glam::vec3(0.0, 1.0, 1.0),
-            normal: glam::vec3(0.0, 0.0, 1.0),
-            uv: glam::vec2(0.0, 1.0),
+    #[test]
+    fn weighted_blended_transparency_doesnt_depend_on_the_order() {
+        let layers = [
+            (0.3, Vec4::new(1.0, 0.0, 0.0, 0.5)),
+            (0.4, Vec4::new(0.0, 0.0, 1.0, 0.75)),
+        ];
+        let render = |order: [usize; 2]| {
+            let mut screen = Screen::create(4, 4);
+            draw_fullscreen(&mut screen, 0.9, Vec4::ONE);
+            screen.state = DrawState::transparent(BlendMode::WeightedBlended);
+            for index in order {
+                let (depth, color) = layers[index];
+                draw_fullscreen(&mut screen, depth, color);
+            }
+            screen.resolve_transparency();
+            assert!(screen.oit_buffer.is_empty());
+            screen.hdr_buffer
         };
-        let v1 = Vertex {
-            position: glam::vec4(-2.0, 2.0, 0.0, 1.0),
-            color: glam::vec3(1.0, 0.0, 0.0),
-            normal: glam::vec3(0.0, 0.0, 1.0),
-            uv: glam::vec2(0.0, 0.0),
+        let pixels = render([0, 1]);
+        let reversed = render([1, 0]);
+        for (a, b) in pixels.iter().zip(&reversed) {
+            assert!(approx_eq(a.xyz(), b.xyz(), 1e-6));
+        }
+
+        // an eighth of the white background is revealed, the rest is a mix of red and blue
+        let color = pixels[0].xyz();
+        assert!(color.z > color.x && color.x > color.y);
+        assert!((color.y - 0.125).abs() < 1e-6, "{}", color);
+    }
+
+    #[test]
+    fn opaque_texels_hide_the_background() {
+        let mut texel = OitTexel::default();
+        let background = Vec4::new(1.0, 1.0, 1.0, 0.0);
+        assert_eq!(texel.resolve(background), background);
+        texel.add(Vec4::new(0.2, 0.4, 0.6, 1.0), 0.5);
+        let resolved = texel.resolve(background);
+        assert!(approx_eq(resolved.xyz(), Vec3::new(0.2, 0.4, 0.6), 1e-6));
+        assert_eq!(resolved.w, 1.0);
+    }
+
+    #[test]
+    fn blended_submeshes_are_drawn_last_from_back_to_front() {
+        let mut scene = Scene::load(Path::new("assets/tests/scene.gltf")).unwrap();
+        let camera_position = Vec3::new(0.0, 0.0, 10.0);
+        let opaque: Vec<_> = scene
+            .draws(camera_position)
+            .iter()
+            .map(|d| d.world)
+            .collect();
+        let instances: Vec<_> = scene.instances().map(|(_, world)| world).collect();
+        assert_eq!(opaque, instances);
+        // the draws point back to their instance, whose vertices they share
+        let draws = scene.draws(camera_position);
+        assert!(draws.iter().all(|d| instances[d.instance] == d.world));
+
+        scene.materials.push(Material {
+            alpha_mode: AlphaMode::Blend,
+            ..Default::default()
+        });
+        scene.meshes[0].submeshes[0].material = Some(0);
+        let draws = scene.draws(camera_position);
+        assert!(draws.iter().all(|draw| draw.transparent));
+        let distances: Vec<f32> = draws
+            .iter()
+            .map(|draw| {
+                let center = draw.mesh.submesh_center(draw.submesh);
+                draw.world
+                    .transform_point3(center)
+                    .distance(camera_position)
+            })
+            .collect();
+        assert!(distances.windows(2).all(|pair| pair[0] >= pair[1]));
+    }
+}
+
+mod cutouts {
+    use super::helpers::*;
+    use crate::material::AlphaMode;
+    use crate::raster::DrawState;
+    use crate::scene::Scene;
+    use crate::shader::{Fragment, FragmentShader, StandardVertexShader};
+    use crate::Screen;
+    use glam::{Mat4, Vec3, Vec4};
+
+    // opaque on the right half of the screen, transparent on the left
+    struct HalfShader {
+        width: f32,
+    }
+
+    impl FragmentShader for HalfShader {
+        fn fragment(&self, fragment: &Fragment) -> Vec4 {
+            Vec3::ONE.extend(fragment.position.x / self.width)
+        }
+    }
+
+    #[test]
+    fn fragments_below_the_cutoff_are_discarded_before_the_depth_write() {
+        let mut screen = Screen::create(8, 2);
+        let vertex_shader = StandardVertexShader::new(&Mat4::IDENTITY, &Mat4::IDENTITY);
+        let shader = HalfShader { width: 8.0 };
+        screen.state = DrawState::cutout(0.5);
+        screen.raster_mesh(&fullscreen(0.25), &vertex_shader, &shader);
+        screen.present();
+        for x in 0..8 {
+            let kept = (x as f32 + 0.5) / 8.0 >= 0.5;
+            assert_eq!(screen.z_buffer[x] == 0.25, kept, "pixel {}", x);
+            assert_eq!(screen.data[x] == 0xffffff, kept, "pixel {}", x);
+        }
+
+        // drawn behind the cutout without alpha test, only visible where it was discarded
+        screen.state = DrawState::default();
+        screen.raster_mesh(&fullscreen(0.5), &vertex_shader, &shader);
+        assert_eq!(
+            screen.z_buffer[..8],
+            [0.5, 0.5, 0.5, 0.5, 0.25, 0.25, 0.25, 0.25]
+        );
+    }
+
+    #[test]
+    fn alpha_mode_and_cutoff_are_loaded_from_gltf() {
+        let path = temp_path("materials.gltf");
+        let json = r#"{
+            "asset": {"version": "2.0"},
+            "scenes": [{"nodes": []}],
+            "materials": [
+                {"alphaMode": "MASK", "alphaCutoff": 0.25},
+                {"alphaMode": "MASK"},
+                {"alphaMode": "BLEND"},
+                {}
+            ]
+        }"#;
+        std::fs::write(&path, json).unwrap();
+        let scene = Scene::load(&path).unwrap();
+        let modes: Vec<_> = scene.materials.iter().map(|m| m.alpha_mode).collect();
+        assert_eq!(
+            modes,
+            [
+                AlphaMode::Mask,
+                AlphaMode::Mask,
+                AlphaMode::Blend,
+                AlphaMode::Opaque
+            ]
+        );
+        assert_eq!(scene.materials[0].alpha_cutoff, 0.25);
+        // the glTF default
+        assert_eq!(scene.materials[1].alpha_cutoff, 0.5);
+    }
+}
+
+mod depth_state {
+    use super::helpers::*;
+    use crate::blend::BlendMode;
+    use crate::camera::Camera;
+    use crate::raster::{CompareFunction, DrawState};
+    use crate::shader::StandardVertexShader;
+    use crate::transform::Transform;
+    use crate::utils::geometry::Mesh;
+    use crate::Screen;
+    use glam::{Mat4, Vec2, Vec3};
+
+    // quad in the xy plane covering the given clip space rectangle at a depth
+    fn quad(min: Vec2, max: Vec2, z: f32) -> Mesh {
+        let (center, half_size) = ((min + max) * 0.5, (max - min) * 0.5);
+        Mesh::quad(
+            center.extend(z),
+            Vec3::X * half_size.x,
+            Vec3::Y * half_size.y,
+        )
+    }
+
+    fn draw(screen: &mut Screen, mesh: &Mesh, mvp: &Mat4, color: Vec3) {
+        let vertex_shader = StandardVertexShader::new(mvp, &Mat4::IDENTITY);
+        screen.raster_mesh(mesh, &vertex_shader, &ColorShader(color.extend(1.0)));
+    }
+
+    #[test]
+    fn compare_functions() {
+        use CompareFunction::*;
+        let results = |compare: CompareFunction| {
+            [(1, 2), (2, 2), (3, 2)].map(|(new, stored)| compare.passes(new, stored))
         };
-        let v2 = Vertex {
-            position: glam::vec4(2.0, 2.0, 0.0, 1.0),
-            color: glam::vec3(0.0, 1.0, 0.0),
-            normal: glam::vec3(0.0, 0.0, 1.0),
-            uv: glam::vec2(1.0, 0.0),
+        assert_eq!(results(Never), [false, false, false]);
+        assert_eq!(results(Less), [true, false, false]);
+        assert_eq!(results(LessEqual), [true, true, false]);
+        assert_eq!(results(Equal), [false, true, false]);
+        assert_eq!(results(GreaterEqual), [false, true, true]);
+        assert_eq!(results(Greater), [false, false, true]);
+        assert_eq!(results(NotEqual), [true, false, true]);
+        assert_eq!(results(Always), [true, true, true]);
+    }
+
+    #[test]
+    fn less_equal_fills_the_far_plane_around_closer_surfaces() {
+        let mut screen = Screen::create_with_depth_clear_value(8, 8, 1.0);
+        let left = quad(Vec2::splat(-1.0), Vec2::new(0.0, 1.0), 0.5);
+        draw(&mut screen, &left, &Mat4::IDENTITY, Vec3::X);
+
+        // a skybox at the far plane with the default depth state doesn't pass at all
+        draw(&mut screen, &fullscreen(1.0), &Mat4::IDENTITY, Vec3::Y);
+        screen.present();
+        assert!(!screen.data.contains(&0x00ff00));
+
+        screen.state = DrawState {
+            depth_compare: CompareFunction::LessEqual,
+            depth_write: false,
+            ..Default::default()
         };
-        let v3 = Vertex {
-            position: glam::vec4(2.0, -2.0, 0.0, 1.0),
-            color: glam::vec3(0.0, 1.0, 1.0),
-            normal: glam::vec3(0.0, 0.0, 1.0),
-            uv: glam::vec2(1.0, 1.0),
+        draw(&mut screen, &fullscreen(1.0), &Mat4::IDENTITY, Vec3::Y);
+        screen.present();
+        for (i, pixel) in screen.data.iter().enumerate() {
+            let expected = if i % 8 < 4 { 0xff0000 } else { 0x00ff00 };
+            assert_eq!(*pixel, expected, "pixel {}", i);
+        }
+        assert!(screen.z_buffer.iter().all(|&z| z == 0.5 || z == 1.0));
+    }
+
+    #[test]
+    fn overlays_ignore_and_keep_the_z_buffer() {
+        let mut screen = Screen::create(4, 4);
+        draw(&mut screen, &fullscreen(0.25), &Mat4::IDENTITY, Vec3::X);
+        screen.state = DrawState {
+            depth_compare: CompareFunction::Always,
+            depth_write: false,
+            ..Default::default()
         };
+        draw(&mut screen, &fullscreen(0.75), &Mat4::IDENTITY, Vec3::Z);
+        screen.present();
+        assert!(screen.data.iter().all(|&pixel| pixel == 0x0000ff));
+        assert!(screen.z_buffer.iter().all(|&z| z == 0.25));
+
+        screen.state.depth_compare = CompareFunction::Never;
+        draw(&mut screen, &fullscreen(0.0), &Mat4::IDENTITY, Vec3::X);
+        screen.present();
+        assert!(screen.data.iter().all(|&pixel| pixel == 0x0000ff));
+    }
 
-        // Texture
-        let texture = Texture::load(Path::new("assets/bojan.jpg"));
+    #[test]
+    fn reversed_z_maps_the_near_plane_to_one() {
+        let camera = Camera {
+            near_plane: 0.5,
+            far_plane: 50.0,
+            reversed_z: true,
+            ..Default::default()
+        };
+        let depth = |distance: f32| {
+            let clip = camera.projection() * glam::vec4(0.0, 0.0, -distance, 1.0);
+            clip.z / clip.w
+        };
+        assert!((depth(0.5) - 1.0).abs() < 1e-6);
+        assert!(depth(50.0).abs() < 1e-6);
+        assert!(depth(2.0) > depth(3.0));
+    }
 
-        // Camera
-        let aspect_ratio: f32 = 1.0;
+    #[test]
+    fn reversed_z_keeps_the_closest_surface() {
         let camera = Camera {
-            far_plane: 100.0,
-            aspect_ratio,
-            transform: Transform::from_translation(glam::vec3(0.0, 0.0, 5.0)),
+            near_plane: 0.5,
+            far_plane: 50.0,
+            transform: Transform::from_translation(Vec3::new(0.0, 0.0, 5.0)),
+            reversed_z: true,
             ..Default::default()
         };
+        let view_projection = camera.projection() * camera.view();
+        let (near, far) = (
+            quad(-Vec2::ONE, Vec2::ONE, 1.0),
+            quad(-Vec2::ONE, Vec2::ONE, -1.0),
+        );
+        for order in [[0, 1], [1, 0]] {
+            let mut screen = Screen::create_with_depth_clear_value(8, 8, 0.0);
+            screen.state = DrawState::default().reversed_z();
+            assert_eq!(screen.state.depth_compare, CompareFunction::Greater);
+            for index in order {
+                let (mesh, color) = [(&near, Vec3::X), (&far, Vec3::Z)][index];
+                draw(&mut screen, mesh, &view_projection, color);
+            }
+            screen.present();
+            let center = 4 * 8 + 4;
+            assert_eq!(screen.data[center], 0xff0000);
+            assert!(screen.z_buffer[center] > 0.0);
+        }
+    }
 
-        let transform =
-            Transform::from_rotation(glam::Quat::from_euler(glam::EulerRot::XYZ, 0.0, 0.0, 0.0));
+    #[test]
+    fn weighted_blending_weights_dont_depend_on_the_depth_direction() {
+        let render = |reversed_z: bool| {
+            let camera = Camera {
+                near_plane: 0.5,
+                far_plane: 50.0,
+                transform: Transform::from_translation(Vec3::new(0.0, 0.0, 5.0)),
+                reversed_z,
+                ..Default::default()
+            };
+            let view_projection = camera.projection() * camera.view();
+            let (clear_value, state) = match reversed_z {
+                true => (
+                    0.0,
+                    DrawState::transparent(BlendMode::WeightedBlended).reversed_z(),
+                ),
+                false => (
+                    f32::INFINITY,
+                    DrawState::transparent(BlendMode::WeightedBlended),
+                ),
+            };
+            let mut screen = Screen::create_with_depth_clear_value(8, 8, clear_value);
+            screen.state = state;
+            let near = quad(-Vec2::ONE, Vec2::ONE, 1.0);
+            let far = quad(-Vec2::ONE, Vec2::ONE, -1.0);
+            draw(&mut screen, &far, &view_projection, Vec3::Z);
+            draw(&mut screen, &near, &view_projection, Vec3::X);
+            screen.resolve_transparency();
+            screen.hdr_buffer[4 * 8 + 4]
+        };
+        let (color, reversed) = (render(false), render(true));
+        // the closer red surface weighs more
+        assert!(color.x > color.z, "{}", color);
+        assert!(
+            (color - reversed).abs().max_element() < 1e-5,
+            "{} {}",
+            color,
+            reversed
+        );
+    }
 
-        // Mesh
-        let triangles = vec![glam::uvec3(2, 1, 0), glam::uvec3(3, 2, 0)];
-        let vertices = vec![v0, v1, v2, v3];
-        let mesh = Mesh::from_vertices(&triangles, &vertices);
+    #[test]
+    fn screens_start_out_cleared_to_the_depth_clear_value() {
+        let screen = Screen::create_with_depth_clear_value(4, 4, 0.0);
+        assert!(screen.z_buffer.iter().all(|&depth| depth == 0.0));
+        assert!(Screen::create(4, 4)
+            .z_buffer
+            .iter()
+            .all(|depth| depth.is_infinite()));
+    }
+}
 
-        // MVP
-        let proj = camera.projection();
-        let view = camera.view();
+mod stencil {
+    use super::helpers::*;
+    use crate::raster::{CompareFunction, DrawState};
+    use crate::shader::StandardVertexShader;
+    use crate::stencil::{StencilOp, StencilState};
+    use crate::utils::geometry::Mesh;
+    use crate::Screen;
+    use glam::{Mat4, Vec3, Vec4};
 
-        screen.raster_mesh(
-            &mesh,
-            &(proj * view * transform.get_local()),
-            &transform.get_local(),
-            Some(&texture),
-        );
+    // the left half of the screen at a depth
+    fn left_half(z: f32) -> Mesh {
+        Mesh::quad(Vec3::new(-0.5, 0.0, z), Vec3::X * 0.5, Vec3::Y)
     }
 
-    pub fn _test_textured_cube(screen: &mut Screen, rot: &mut f32) {
-        // Quad
-        let v0 = Vertex {
-            position: glam::vec4(-1.0, -1.0, 1.0, 1.0),
-            color: glam::vec3(0.0, 1.0, 1.0),
-            normal: glam::vec3(0.0, 0.0, 1.0),
-            uv: glam::vec2(0.0, 1.0),
-        };
-        let v1 = Vertex {
-            position: glam::vec4(-1.0, 1.0, 1.0, 1.0),
-            color: glam::vec3(1.0, 0.0, 0.0),
-            normal: glam::vec3(0.0, 0.0, 1.0),
-            uv: glam::vec2(0.0, 0.0),
+    fn draw(screen: &mut Screen, mesh: &Mesh, state: DrawState) {
+        screen.state = state;
+        let vertex_shader = StandardVertexShader::new(&Mat4::IDENTITY, &Mat4::IDENTITY);
+        screen.raster_mesh(mesh, &vertex_shader, &ColorShader(Vec4::ONE));
+    }
+
+    fn with_stencil(stencil: StencilState) -> DrawState {
+        DrawState {
+            stencil: Some(stencil),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn stencil_ops() {
+        use StencilOp::*;
+        let results = |stored: u8| {
+            [
+                Keep,
+                Zero,
+                Replace,
+                IncrementClamp,
+                DecrementClamp,
+                Invert,
+                IncrementWrap,
+                DecrementWrap,
+            ]
+            .map(|op| op.apply(stored, 7))
         };
-        let v2 = Vertex {
-            position: glam::vec4(1.0, 1.0, 1.0, 1.0),
-            color: glam::vec3(0.0, 1.0, 0.0),
-            normal: glam::vec3(0.0, 0.0, 1.0),
-            uv: glam::vec2(1.0, 0.0),
+        assert_eq!(results(0), [0, 0, 7, 1, 0, 255, 1, 255]);
+        assert_eq!(results(255), [255, 0, 7, 255, 254, 0, 0, 254]);
+    }
+
+    #[test]
+    fn masks_limit_the_compared_and_written_bits() {
+        let state = StencilState {
+            compare: CompareFunction::Equal,
+            reference: 0b0101,
+            read_mask: 0b0011,
+            write_mask: 0b1100,
+            ..Default::default()
         };
-        let v3 = Vertex {
-            position: glam::vec4(1.0, -1.0, 1.0, 1.0),
-            color: glam::vec3(0.0, 1.0, 1.0),
-            normal: glam::vec3(0.0, 0.0, 1.0),
-            uv: glam::vec2(1.0, 1.0),
+        assert!(state.passes(0b1001));
+        assert!(!state.passes(0b0110));
+        assert_eq!(state.update(StencilOp::Replace, 0b1010), 0b0110);
+        assert_eq!(state.update(StencilOp::Zero, 0b1111), 0b0011);
+    }
+
+    #[test]
+    fn draws_are_limited_to_the_marked_pixels() {
+        let mut screen = Screen::create(8, 4);
+        // marks the left half without drawing anything visible
+        let mark = DrawState {
+            depth_compare: CompareFunction::Never,
+            stencil: Some(StencilState {
+                depth_fail: StencilOp::Replace,
+                ..StencilState::write(3)
+            }),
+            ..Default::default()
         };
+        draw(&mut screen, &left_half(0.5), mark);
+        screen.present();
+        assert!(screen.data.iter().all(|&pixel| pixel == 0));
+        for (i, value) in screen.stencil_buffer.iter().enumerate() {
+            assert_eq!(*value, if i % 8 < 4 { 3 } else { 0 }, "pixel {}", i);
+        }
+
+        let test = StencilState::test(CompareFunction::Equal, 3);
+        draw(&mut screen, &fullscreen(0.5), with_stencil(test));
+        screen.present();
+        for (i, pixel) in screen.data.iter().enumerate() {
+            assert_eq!(*pixel, if i % 8 < 4 { 0xffffff } else { 0 }, "pixel {}", i);
+        }
+    }
 
-        // Camera
-        let aspect_ratio: f32 = 1.0;
-        let camera = Camera {
-            far_plane: 100.0,
-            aspect_ratio,
-            transform: Transform::from_translation(glam::vec3(0.0, 0.0, 5.0)),
+    #[test]
+    fn each_outcome_updates_the_buffer_with_its_op() {
+        let mut screen = Screen::create(8, 4);
+        draw(&mut screen, &left_half(0.25), DrawState::default());
+        screen.stencil_buffer[..4].fill(9);
+
+        // the first row fails the stencil test on the left, the rest of the left half
+        // fails the depth test and the right half passes both
+        let state = StencilState {
+            compare: CompareFunction::NotEqual,
+            reference: 9,
+            fail: StencilOp::Zero,
+            depth_fail: StencilOp::IncrementClamp,
+            pass: StencilOp::Replace,
             ..Default::default()
         };
+        draw(&mut screen, &fullscreen(0.5), with_stencil(state));
+        assert_eq!(screen.stencil_buffer[..8], [0, 0, 0, 0, 9, 9, 9, 9]);
+        assert_eq!(screen.stencil_buffer[8..16], [1, 1, 1, 1, 9, 9, 9, 9]);
+    }
 
-        // Texture
-        let texture = Texture::load(Path::new("assets/bojan.jpg"));
+    #[test]
+    fn clear_resets_the_stencil_buffer() {
+        let mut screen = Screen::create(4, 4);
+        draw(
+            &mut screen,
+            &fullscreen(0.5),
+            with_stencil(StencilState::write(1)),
+        );
+        assert!(screen.stencil_buffer.iter().all(|&value| value == 1));
+        screen.stencil_clear_value = 4;
+        screen.clear();
+        assert!(screen.stencil_buffer.iter().all(|&value| value == 4));
+    }
+}
 
-        // Mesh
-        let triangles = vec![glam::uvec3(2, 1, 0), glam::uvec3(3, 2, 0)];
-        let vertices = vec![v0, v1, v2, v3];
-        let mesh = Mesh::from_vertices(&triangles, &vertices);
+mod color {
+    use super::helpers::*;
+    use crate::blend::BlendMode;
+    use crate::color::*;
+    use crate::raster::DrawState;
+    use crate::texture::{Sampler, Texels, Texture};
+    use crate::utils::{from_u32_rgb, from_u8_rgb};
+    use crate::Screen;
+    use glam::{Vec2, Vec3, Vec4, Vec4Swizzles};
+
+    #[test]
+    fn srgb_encoding_round_trips() {
+        assert!((linear_to_srgb(Vec3::splat(0.5)).x - 0.7354).abs() < 1e-3);
+        assert!((srgb_to_linear(Vec3::splat(0.5)).x - 0.2140).abs() < 1e-3);
+        assert_eq!(linear_to_srgb(Vec3::ZERO), Vec3::ZERO);
+        assert!((linear_to_srgb(Vec3::ONE) - Vec3::ONE).abs().max_element() < 1e-5);
+        for i in 0..=100 {
+            let value = Vec3::splat(i as f32 / 100.0);
+            let round_trip = srgb_to_linear(linear_to_srgb(value));
+            assert!((round_trip - value).abs().max_element() < 1e-5, "{}", value);
+        }
+    }
 
-        // Transforms
-        let transform0 = Transform::IDENTITY;
+    #[test]
+    fn tone_mappers_are_monotonic_and_bounded() {
+        let tone_mappers = [
+            ToneMapper::Clamp,
+            ToneMapper::Reinhard,
+            ToneMapper::Aces,
+            ToneMapper::Exposure,
+        ];
+        for tone_mapper in tone_mappers {
+            let mut previous = 0.0;
+            for i in 0..=200 {
+                let mapped = tone_mapper.apply(Vec3::splat(i as f32 * 0.1)).x;
+                assert!((0.0..=1.0).contains(&mapped), "{:?}", tone_mapper);
+                assert!(mapped >= previous, "{:?} at {}", tone_mapper, i);
+                previous = mapped;
+            }
+            assert_eq!(tone_mapper.apply(Vec3::splat(-1.0)), Vec3::ZERO);
+        }
+        // only clamping cuts everything above one off, the curves keep some contrast
+        assert_eq!(ToneMapper::Clamp.apply(Vec3::splat(2.0)), Vec3::ONE);
+        assert_eq!(ToneMapper::Reinhard.apply(Vec3::ONE), Vec3::splat(0.5));
+        for tone_mapper in [ToneMapper::Reinhard, ToneMapper::Exposure] {
+            let (bright, brighter) = (Vec3::splat(2.0), Vec3::splat(4.0));
+            assert!(tone_mapper.apply(bright).x < tone_mapper.apply(brighter).x);
+        }
+    }
 
-        // Rotate
-        *rot += 0.05;
+    #[test]
+    fn output_settings_expose_and_encode() {
+        let output = OutputSettings {
+            srgb: false,
+            ..Default::default()
+        };
+        assert_eq!(
+            output.present(Vec3::new(2.0, 0.5, 0.0)),
+            from_u8_rgb(255, 128, 0)
+        );
+        let output = OutputSettings {
+            exposure: 0.5,
+            ..Default::default()
+        };
+        // half of 0.5 linear is 0.537 in sRGB
+        assert_eq!(output.present(Vec3::splat(0.5)), from_u8_rgb(137, 137, 137));
+    }
 
-        //-z
-        let transform1 = Transform::from_rotation(glam::Quat::from_euler(
-            glam::EulerRot::XYZ,
-            -std::f32::consts::PI,
-            0.0,
-            0.0,
-        ));
-        //+y
-        let transform2 = Transform::from_rotation(glam::Quat::from_euler(
-            glam::EulerRot::XYZ,
-            std::f32::consts::FRAC_PI_2,
-            0.0,
-            0.0,
-        ));
-        //-y
-        let transform3 = Transform::from_rotation(glam::Quat::from_euler(
-            glam::EulerRot::XYZ,
-            -std::f32::consts::FRAC_PI_2,
-            0.0,
-            0.0,
-        ));
-        //+x
-        let transform4 = Transform::from_rotation(glam::Quat::from_euler(
-            glam::EulerRot::XYZ,
-            0.0,
-            -std::f32::consts::FRAC_PI_2,
-            0.0,
-        ));
-        //-x
-        let transform5 = Transform::from_rotation(glam::Quat::from_euler(
-            glam::EulerRot::XYZ,
-            0.0,
-            std::f32::consts::FRAC_PI_2,
-            0.0,
-        ));
+    #[test]
+    fn hdr_buffer_keeps_values_above_one() {
+        let mut screen = Screen::create(4, 4);
+        // linear output, so that the presented values are the clamped ones
+        screen.output.srgb = false;
+        screen.state = DrawState::transparent(BlendMode::Additive);
+        for _ in 0..3 {
+            draw_fullscreen(&mut screen, 0.5, Vec4::new(0.8, 0.4, 0.2, 1.0));
+        }
+        let expected = Vec3::new(2.4, 1.2, 0.6);
+        assert!(screen
+            .hdr_buffer
+            .iter()
+            .all(|color| (color.xyz() - expected).abs().max_element() < 1e-5));
+        screen.present();
+        assert_eq!(screen.data[0], from_u8_rgb(255, 255, 153));
+
+        // the same frame presented again with other settings
+        screen.output.exposure = 0.25;
+        screen.present();
+        assert_eq!(screen.data[0], from_u8_rgb(153, 77, 38));
+        screen.output.tone_mapper = ToneMapper::Reinhard;
+        screen.present();
+        let color = from_u32_rgb(screen.data[0]);
+        assert!(
+            (color - expected * 0.25 / (expected * 0.25 + 1.0))
+                .abs()
+                .max_element()
+                < 0.5 / 255.0
+        );
+    }
 
-        let parent_local =
-            Transform::from_rotation(glam::Quat::from_euler(glam::EulerRot::XYZ, *rot, *rot, 0.0))
-                .get_local();
-
-        // MVP
-        let proj = camera.projection();
-        let view = camera.view();
+    #[test]
+    fn srgb_textures_are_sampled_linearly() {
+        // black and white stay the same in both encodings
+        let texels = vec![0, 255, 255, 0];
+        let mut texture = Texture::new(2, 2, Texels::R8(texels));
+        let sampler = Sampler::NEAREST;
+        let sample =
+            |texture: &Texture, uv: Vec2| texture.sample(&sampler, uv, Vec2::ZERO, Vec2::ZERO);
+        texture.set_srgb(true);
+        assert!(texture.is_srgb());
+        assert_eq!(sample(&texture, Vec2::new(0.75, 0.25)), Vec4::ONE);
+
+        let gray = Texture::new(1, 1, Texels::from_u8(&[188, 188, 188, 128], 4));
+        let mut srgb_gray = Texture::new(1, 1, Texels::from_u8(&[188, 188, 188, 128], 4));
+        srgb_gray.set_srgb(true);
+        let (linear, decoded) = (
+            sample(&gray, Vec2::splat(0.5)),
+            sample(&srgb_gray, Vec2::splat(0.5)),
+        );
+        assert!((linear.x - 188.0 / 255.0).abs() < 1e-5);
+        assert!((decoded.x - 0.5).abs() < 3e-3, "{}", decoded);
+        // alpha isn't encoded
+        assert_eq!(linear.w, decoded.w);
+    }
 
-        screen.raster_mesh(
-            &mesh,
-            &(proj * view * parent_local * transform0.get_local()),
-            &transform0.get_local(),
-            Some(&texture),
-        );
-        screen.raster_mesh(
-            &mesh,
-            &(proj * view * parent_local * transform1.get_local()),
-            &transform1.get_local(),
-            Some(&texture),
-        );
-        screen.raster_mesh(
-            &mesh,
-            &(proj * view * parent_local * transform2.get_local()),
-            &transform2.get_local(),
-            Some(&texture),
-        );
-        screen.raster_mesh(
-            &mesh,
-            &(proj * view * parent_local * transform3.get_local()),
-            &transform3.get_local(),
-            Some(&texture),
-        );
-
-        screen.raster_mesh(
-            &mesh,
-            &(proj * view * parent_local * transform4.get_local()),
-            &transform4.get_local(),
-            Some(&texture),
-        );
-        screen.raster_mesh(
-            &mesh,
-            &(proj * view * parent_local * transform5.get_local()),
-            &transform5.get_local(),
-            Some(&texture),
-        );
-    }
-
-    pub fn _test_camera_inputs(screen: &mut Screen, rot: &mut f32, camera: &Camera) {
-        // Quad
-        let v0 = Vertex {
-            position: glam::vec4(-2.0, -2.0, 0.0, 1.0),
-            color: glam::vec3(0.0, 1.0, 1.0),
-            normal: glam::vec3(0.0, 0.0, 1.0),
-            uv: glam::vec2(0.0, 1.0),
-        };
-        let v1 = Vertex {
-            position: glam::vec4(-2.0, 2.0, 0.0, 1.0),
-            color: glam::vec3(1.0, 0.0, 0.0),
-            normal: glam::vec3(0.0, 0.0, 1.0),
-            uv: glam::vec2(0.0, 0.0),
-        };
-        let v2 = Vertex {
-            position: glam::vec4(2.0, 2.0, 0.0, 1.0),
-            color: glam::vec3(0.0, 1.0, 0.0),
-            normal: glam::vec3(0.0, 0.0, 1.0),
-            uv: glam::vec2(1.0, 0.0),
-        };
-        let v3 = Vertex {
-            position: glam::vec4(2.0, -2.0, 0.0, 1.0),
-            color: glam::vec3(0.0, 1.0, 1.0),
-            normal: glam::vec3(0.0, 0.0, 1.0),
-            uv: glam::vec2(1.0, 1.0),
-        };
-
-        // Texture
-        let texture = Texture::load(Path::new("assets/bojan.jpg"));
-
-        *rot += 0.05;
-
-        let transform =
-            Transform::from_rotation(glam::Quat::from_euler(glam::EulerRot::XYZ, *rot, 0.0, 0.0));
-
-        // Mesh
-        let triangles = vec![glam::uvec3(2, 1, 0), glam::uvec3(3, 2, 0)];
-        let vertices = vec![v0, v1, v2, v3];
-        let mesh = Mesh::from_vertices(&triangles, &vertices);
-
-        // MVP
-        let projection = camera.projection();
-        let view = camera.view();
-        let mvp = projection * view * transform.get_local();
-
-        screen.raster_mesh(&mesh, &mvp, &transform.get_local(), Some(&texture));
-    }
-
-    pub fn _test_gltf(screen: &mut Screen, rot: &mut f32, camera: &Camera, mesh: &Mesh) {
-        *rot += 0.05;
-        let transform =
-            Transform::from_rotation(glam::Quat::from_euler(glam::EulerRot::XYZ, 0.0, *rot, 0.0));
-        let projection = camera.projection();
-        let view = camera.view();
-        let mvp = projection * view * transform.get_local();
-
-        screen.raster_mesh(&mesh, &mvp, &transform.get_local(), None);
-    }
-
-    pub fn _test_gltf_textured(
-        screen: &mut Screen,
-        rot: &mut f32,
-        camera: &Camera,
-        mesh: &Mesh,
-        texture: &Texture,
-    ) {
-        *rot += 0.05;
-        let transform =
-            Transform::from_rotation(glam::Quat::from_euler(glam::EulerRot::XYZ, *rot, *rot, 0.0));
-        let projection = camera.projection();
-        let view = camera.view();
-        let mvp = projection * view * transform.get_local();
-
-        screen.raster_mesh(&mesh, &mvp, &transform.get_local(), Some(texture));
+    #[test]
+    fn srgb_mips_average_in_linear_space() {
+        let mut texture = Texture::new(2, 1, Texels::from_u8(&[0, 255], 1));
+        assert_eq!(texture.levels[1].texels.get(0).x, 128.0 / 255.0);
+        texture.set_srgb(true);
+        // half of the light is 188 once encoded, not the darker 128
+        assert_eq!(texture.levels[1].texels.get(0).x, 188.0 / 255.0);
+        assert!((texture.levels[1].get(0).x - 0.5).abs() < 3e-3);
+        texture.set_srgb(false);
+        assert_eq!(texture.levels[1].texels.get(0).x, 128.0 / 255.0);
     }
 }
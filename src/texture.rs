@@ -1,64 +1,619 @@
-use stb_image;
 use std::path::Path;
 
-use crate::{from_coords_index, from_u32_u8, from_u8_rgb};
-use glam::Vec3;
+use crate::color::{linear_to_srgb, srgb_to_linear};
+use crate::error::{Error, Result};
+use crate::{from_coords_index, from_rgb_u32, from_u8_rgb};
+use glam::{Vec2, Vec3, Vec4, Vec4Swizzles};
+
+// Texel storage of a mip level. The integer formats hold values normalized to [0, 1],
+// single channel formats are read as gray with an opaque alpha.
+pub enum Texels {
+    R8(Vec<u8>),
+    Rgba8(Vec<[u8; 4]>),
+    R16(Vec<u16>),
+    Rgba16(Vec<[u16; 4]>),
+    Rgba32F(Vec<Vec4>),
+}
+
+impl Texels {
+    // interleaved 8 bit channels as decoded by stb_image
+    pub fn from_u8(data: &[u8], channels: usize) -> Self {
+        match channels {
+            1 => Self::R8(data.to_vec()),
+            _ => Self::Rgba8(
+                data.chunks_exact(channels)
+                    .map(|c| expand_channels(c, u8::MAX))
+                    .collect(),
+            ),
+        }
+    }
+
+    pub fn from_u16(data: &[u16], channels: usize) -> Self {
+        match channels {
+            1 => Self::R16(data.to_vec()),
+            _ => Self::Rgba16(
+                data.chunks_exact(channels)
+                    .map(|c| expand_channels(c, u16::MAX))
+                    .collect(),
+            ),
+        }
+    }
+
+    pub fn from_f32(data: &[f32], channels: usize) -> Self {
+        Self::Rgba32F(
+            data.chunks_exact(channels)
+                .map(|c| Vec4::from_array(expand_channels(c, 1.0)))
+                .collect(),
+        )
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Self::R8(data) => data.len(),
+            Self::Rgba8(data) => data.len(),
+            Self::R16(data) => data.len(),
+            Self::Rgba16(data) => data.len(),
+            Self::Rgba32F(data) => data.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, index: usize) -> Vec4 {
+        const MAX_U8: f32 = u8::MAX as f32;
+        const MAX_U16: f32 = u16::MAX as f32;
+        match self {
+            Self::R8(data) => Vec3::splat(data[index] as f32 / MAX_U8).extend(1.0),
+            Self::Rgba8(data) => Vec4::from_array(data[index].map(|c| c as f32)) / MAX_U8,
+            Self::R16(data) => Vec3::splat(data[index] as f32 / MAX_U16).extend(1.0),
+            Self::Rgba16(data) => Vec4::from_array(data[index].map(|c| c as f32)) / MAX_U16,
+            Self::Rgba32F(data) => data[index],
+        }
+    }
+
+    // stores the values in the same format as self
+    fn quantize(&self, values: Vec<Vec4>) -> Self {
+        let to_u8 = |v: f32| (v * u8::MAX as f32).round().clamp(0.0, u8::MAX as f32) as u8;
+        let to_u16 = |v: f32| (v * u16::MAX as f32).round().clamp(0.0, u16::MAX as f32) as u16;
+        match self {
+            Self::R8(_) => Self::R8(values.iter().map(|v| to_u8(v.x)).collect()),
+            Self::Rgba8(_) => Self::Rgba8(values.iter().map(|v| v.to_array().map(to_u8)).collect()),
+            Self::R16(_) => Self::R16(values.iter().map(|v| to_u16(v.x)).collect()),
+            Self::Rgba16(_) => {
+                Self::Rgba16(values.iter().map(|v| v.to_array().map(to_u16)).collect())
+            }
+            Self::Rgba32F(_) => Self::Rgba32F(values),
+        }
+    }
+}
+
+// gray, gray alpha, rgb or rgba to rgba
+fn expand_channels<T: Copy>(channels: &[T], opaque: T) -> [T; 4] {
+    match *channels {
+        [l] => [l, l, l, opaque],
+        [l, a] => [l, l, l, a],
+        [r, g, b] => [r, g, b, opaque],
+        [r, g, b, a, ..] => [r, g, b, a],
+        [] => panic!("texels without channels"),
+    }
+}
+
+// Texture::new needs exactly one texel per pixel, which decoders only promise for valid
+// files. `size` is the width, height and channels the decoder reported for `samples`.
+pub(crate) fn check_decoded(name: &str, size: (usize, usize, usize), samples: usize) -> Result<()> {
+    let (width, height, channels) = size;
+    if width == 0 || height == 0 {
+        return Err(Error::decode(name, "the image is empty"));
+    }
+    if !(1..=4).contains(&channels) {
+        return Err(Error::unsupported(name, format!("{} channels", channels)));
+    }
+    if width
+        .checked_mul(height)
+        .and_then(|n| n.checked_mul(channels))
+        != Some(samples)
+    {
+        return Err(Error::decode(
+            name,
+            format!(
+                "{} samples for a {}x{} image with {} channels",
+                samples, width, height, channels
+            ),
+        ));
+    }
+    Ok(())
+}
+
+// magic numbers of the formats stb_image can decode, tga doesn't have one
+fn has_image_signature(bytes: &[u8]) -> bool {
+    const SIGNATURES: [&[u8]; 10] = [
+        b"\x89PNG",
+        b"\xff\xd8",
+        b"GIF8",
+        b"BM",
+        b"8BPS",
+        b"#?RADIANCE",
+        b"#?RGBE",
+        b"P5",
+        b"P6",
+        b"\x53\x80\xf6\x34",
+    ];
+    SIGNATURES
+        .iter()
+        .any(|signature| bytes.starts_with(signature))
+}
+
+// One level of the mip chain, texels are stored in rows from the top
+pub struct MipLevel {
+    pub width: usize,
+    pub height: usize,
+    pub texels: Texels,
+    // the color channels are sRGB encoded and decoded to linear on every fetch, alpha
+    // is always linear
+    pub srgb: bool,
+}
+
+impl MipLevel {
+    // linear texel at an index of the texels
+    pub fn get(&self, index: usize) -> Vec4 {
+        let texel = self.texels.get(index);
+        if self.srgb {
+            srgb_to_linear(texel.xyz()).extend(texel.w)
+        } else {
+            texel
+        }
+    }
+
+    // texel at integer coordinates, out of range coordinates follow the wrap modes
+    pub fn fetch(&self, x: i32, y: i32, sampler: &Sampler) -> Vec4 {
+        match (
+            sampler.wrap_u.apply(x, self.width),
+            sampler.wrap_v.apply(y, self.height),
+        ) {
+            (Some(x), Some(y)) => self.get(x + y * self.width),
+            _ => sampler.border_color,
+        }
+    }
+
+    // Box filter over the texels covered by each texel of the half sized level. Odd sizes
+    // are rounded down, so a texel covers 2.5 texels of a 5 texel row for example and the
+    // one in the middle is split between its two neighbours. sRGB levels are averaged in
+    // linear space so that the smaller levels don't get darker.
+    fn downsample(&self) -> Self {
+        let (width, height) = ((self.width / 2).max(1), (self.height / 2).max(1));
+        let columns: Vec<_> = (0..width)
+            .map(|x| footprint(x, width, self.width))
+            .collect();
+        let rows: Vec<_> = (0..height)
+            .map(|y| footprint(y, height, self.height))
+            .collect();
+        let mut values = Vec::with_capacity(width * height);
+        for row in &rows {
+            for column in &columns {
+                let mut sum = Vec4::ZERO;
+                for &(y, weight_y) in row {
+                    for &(x, weight_x) in column {
+                        sum += self.get(x + y * self.width) * (weight_x * weight_y);
+                    }
+                }
+                values.push(if self.srgb {
+                    linear_to_srgb(sum.xyz()).extend(sum.w)
+                } else {
+                    sum
+                });
+            }
+        }
+        Self {
+            width,
+            height,
+            texels: self.texels.quantize(values),
+            srgb: self.srgb,
+        }
+    }
+
+    fn sample_nearest(&self, uv: Vec2, sampler: &Sampler) -> Vec4 {
+        let x = (uv.x * self.width as f32).floor() as i32;
+        let y = (uv.y * self.height as f32).floor() as i32;
+        self.fetch(x, y, sampler)
+    }
+
+    fn sample_bilinear(&self, uv: Vec2, sampler: &Sampler) -> Vec4 {
+        // texel centers are at half coordinates
+        let position = uv * glam::vec2(self.width as f32, self.height as f32) - 0.5;
+        let (x, y) = (position.x.floor() as i32, position.y.floor() as i32);
+        let t = position - position.floor();
+        let fetch = |x, y| self.fetch(x, y, sampler);
+        let top = fetch(x, y).lerp(fetch(x + 1, y), t.x);
+        let bottom = fetch(x, y + 1).lerp(fetch(x + 1, y + 1), t.x);
+        top.lerp(bottom, t.y)
+    }
+
+    fn sample(&self, uv: Vec2, filter: Filter, sampler: &Sampler) -> Vec4 {
+        match filter {
+            Filter::Nearest => self.sample_nearest(uv, sampler),
+            Filter::Linear => self.sample_bilinear(uv, sampler),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Filter {
+    Nearest,
+    Linear,
+}
+
+impl Filter {
+    pub fn from_gltf_mag(filter: gltf::texture::MagFilter) -> Self {
+        match filter {
+            gltf::texture::MagFilter::Nearest => Self::Nearest,
+            gltf::texture::MagFilter::Linear => Self::Linear,
+        }
+    }
+
+    // filter inside of a level and filter between levels
+    pub fn from_gltf_min(filter: gltf::texture::MinFilter) -> (Self, Option<Self>) {
+        use gltf::texture::MinFilter;
+        match filter {
+            MinFilter::Nearest => (Self::Nearest, None),
+            MinFilter::Linear => (Self::Linear, None),
+            MinFilter::NearestMipmapNearest => (Self::Nearest, Some(Self::Nearest)),
+            MinFilter::LinearMipmapNearest => (Self::Linear, Some(Self::Nearest)),
+            MinFilter::NearestMipmapLinear => (Self::Nearest, Some(Self::Linear)),
+            MinFilter::LinearMipmapLinear => (Self::Linear, Some(Self::Linear)),
+        }
+    }
+}
+
+// What happens to texture coordinates outside of [0, 1]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Wrap {
+    Repeat,
+    // repeats every other copy flipped, so the edges always line up
+    MirroredRepeat,
+    ClampToEdge,
+    // outside texels are the border color of the sampler
+    ClampToBorder,
+}
+
+impl Wrap {
+    pub fn from_gltf(mode: gltf::texture::WrappingMode) -> Self {
+        match mode {
+            gltf::texture::WrappingMode::Repeat => Self::Repeat,
+            gltf::texture::WrappingMode::MirroredRepeat => Self::MirroredRepeat,
+            gltf::texture::WrappingMode::ClampToEdge => Self::ClampToEdge,
+        }
+    }
+
+    // maps a texel coordinate into [0, size), None for the border
+    pub fn apply(self, coord: i32, size: usize) -> Option<usize> {
+        let size = size as i32;
+        let coord = match self {
+            Self::Repeat => coord.rem_euclid(size),
+            Self::MirroredRepeat => {
+                let coord = coord.rem_euclid(2 * size);
+                if coord < size {
+                    coord
+                } else {
+                    2 * size - 1 - coord
+                }
+            }
+            Self::ClampToEdge => coord.clamp(0, size - 1),
+            Self::ClampToBorder => {
+                if coord < 0 || coord >= size {
+                    return None;
+                }
+                coord
+            }
+        };
+        Some(coord as usize)
+    }
+}
+
+// Describes how a texture is read, shaders hold one per texture so it can change per draw
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Sampler {
+    // filter used when a texel covers more than a pixel
+    pub mag_filter: Filter,
+    // filter used inside of a mip level when a pixel covers more than a texel
+    pub min_filter: Filter,
+    // filter between mip levels, None always samples the full resolution
+    pub mipmap_filter: Option<Filter>,
+    // maximum number of samples along the stretched axis of the pixel footprint,
+    // 1 disables anisotropic filtering
+    pub max_anisotropy: u32,
+    pub wrap_u: Wrap,
+    pub wrap_v: Wrap,
+    // color outside of the texture with Wrap::ClampToBorder
+    pub border_color: Vec4,
+}
+
+impl Sampler {
+    pub const NEAREST: Self = Self {
+        mag_filter: Filter::Nearest,
+        min_filter: Filter::Nearest,
+        mipmap_filter: None,
+        max_anisotropy: 1,
+        wrap_u: Wrap::Repeat,
+        wrap_v: Wrap::Repeat,
+        border_color: Vec4::ZERO,
+    };
+
+    pub const BILINEAR: Self = Self {
+        mag_filter: Filter::Linear,
+        min_filter: Filter::Linear,
+        mipmap_filter: Some(Filter::Nearest),
+        max_anisotropy: 1,
+        wrap_u: Wrap::Repeat,
+        wrap_v: Wrap::Repeat,
+        border_color: Vec4::ZERO,
+    };
+
+    pub const TRILINEAR: Self = Self {
+        mag_filter: Filter::Linear,
+        min_filter: Filter::Linear,
+        mipmap_filter: Some(Filter::Linear),
+        max_anisotropy: 1,
+        wrap_u: Wrap::Repeat,
+        wrap_v: Wrap::Repeat,
+        border_color: Vec4::ZERO,
+    };
+
+    pub fn anisotropic(max_anisotropy: u32) -> Self {
+        Self {
+            max_anisotropy: max_anisotropy.max(1),
+            ..Self::TRILINEAR
+        }
+    }
+
+    // glTF leaves the filters up to the application when they are missing
+    pub fn from_gltf(sampler: &gltf::texture::Sampler) -> Self {
+        let mut result = Self {
+            wrap_u: Wrap::from_gltf(sampler.wrap_s()),
+            wrap_v: Wrap::from_gltf(sampler.wrap_t()),
+            ..Self::default()
+        };
+        if let Some(filter) = sampler.mag_filter() {
+            result.mag_filter = Filter::from_gltf_mag(filter);
+        }
+        if let Some(filter) = sampler.min_filter() {
+            (result.min_filter, result.mipmap_filter) = Filter::from_gltf_min(filter);
+        }
+        result
+    }
+
+    pub fn with_wrap(self, wrap: Wrap) -> Self {
+        Self {
+            wrap_u: wrap,
+            wrap_v: wrap,
+            ..self
+        }
+    }
+}
+
+impl Default for Sampler {
+    fn default() -> Self {
+        Self::TRILINEAR
+    }
+}
 
 pub struct Texture {
     pub width: usize,
     pub height: usize,
-    pub data: Vec<u32>,
-    pub depth: usize,
+    // full resolution first, down to 1x1
+    pub levels: Vec<MipLevel>,
 }
 
 impl Texture {
-    pub fn load(path: &Path) -> Self {
-        let decoded_data = stb_image::image::load(path);
-        if let stb_image::image::LoadResult::ImageU8(image) = decoded_data {
-            let data = (0..image.data.len() / 3)
-                .map(|id| {
-                    from_u8_rgb(
-                        image.data[id * 3],
-                        image.data[id * 3 + 1],
-                        image.data[id * 3 + 2],
-                    )
-                })
-                .collect();
-            Self {
-                width: image.width,
-                height: image.height,
-                data,
-                depth: image.depth,
+    // 8 bit images with one to four channels, 16 bit PNGs and HDR images
+    pub fn load(path: &Path) -> Result<Self> {
+        let bytes = std::fs::read(path).map_err(|e| Error::from_io(path, e))?;
+        Self::from_memory(&bytes, &path.display().to_string())
+    }
+
+    // decodes an image file that is already in memory, the name is only used for errors
+    pub fn from_memory(bytes: &[u8], name: &str) -> Result<Self> {
+        if let Some(texture) = Self::decode_png16(bytes, name)? {
+            return Ok(texture);
+        }
+        match stb_image::image::load_from_memory(bytes) {
+            stb_image::image::LoadResult::ImageU8(image) => {
+                let size = (image.width, image.height, image.depth);
+                check_decoded(name, size, image.data.len())?;
+                Ok(Self::new(
+                    image.width,
+                    image.height,
+                    Texels::from_u8(&image.data, image.depth),
+                ))
             }
-        } else {
-            panic!("Can't load the image");
+            stb_image::image::LoadResult::ImageF32(image) => {
+                let size = (image.width, image.height, image.depth);
+                check_decoded(name, size, image.data.len())?;
+                Ok(Self::new(
+                    image.width,
+                    image.height,
+                    Texels::from_f32(&image.data, image.depth),
+                ))
+            }
+            // stb_image doesn't tell us why it failed
+            stb_image::image::LoadResult::Error(_) if has_image_signature(bytes) => {
+                Err(Error::decode(name, "corrupt or truncated image"))
+            }
+            stb_image::image::LoadResult::Error(_) => Err(Error::unsupported(
+                name,
+                "expected a png, jpeg, bmp, gif, tga, psd, hdr or pnm image",
+            )),
         }
     }
 
+    // stb_image reduces 16 bit images to 8 bit, so those are decoded with the png crate.
+    // None for anything that isn't a 16 bit png.
+    fn decode_png16(bytes: &[u8], name: &str) -> Result<Option<Self>> {
+        let mut decoder = png::Decoder::new(bytes);
+        decoder.set_transformations(png::Transformations::EXPAND);
+        let Ok(mut reader) = decoder.read_info() else {
+            return Ok(None);
+        };
+        if reader.info().bit_depth != png::BitDepth::Sixteen {
+            return Ok(None);
+        }
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let frame = reader
+            .next_frame(&mut buffer)
+            .map_err(|e| Error::decode(name, e))?;
+        let data: Vec<u16> = buffer[..frame.buffer_size()]
+            .chunks_exact(2)
+            .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+            .collect();
+        let (width, height) = (frame.width as usize, frame.height as usize);
+        let channels = frame.color_type.samples();
+        check_decoded(name, (width, height, channels), data.len())?;
+        Ok(Some(Self::new(
+            width,
+            height,
+            Texels::from_u16(&data, channels),
+        )))
+    }
+
+    // builds the mip chain from the full resolution texels, there must be one per pixel
+    pub fn new(width: usize, height: usize, texels: Texels) -> Self {
+        assert_eq!(texels.len(), width * height);
+        Self {
+            width,
+            height,
+            levels: mip_chain(MipLevel {
+                width,
+                height,
+                texels,
+                srgb: false,
+            }),
+        }
+    }
+
+    // Color textures like base color and emissive are stored sRGB encoded, data like
+    // normals and roughness linearly. Rebuilds the mips, which are filtered in linear space.
+    pub fn set_srgb(&mut self, srgb: bool) {
+        if self.levels[0].srgb == srgb {
+            return;
+        }
+        let mut base = self.levels.swap_remove(0);
+        base.srgb = srgb;
+        self.levels = mip_chain(base);
+    }
+
+    pub fn is_srgb(&self) -> bool {
+        self.levels[0].srgb
+    }
+
+    // index of the texel under the uv coordinates, repeating outside of [0, 1]
     pub fn uv_to_index(&self, u: f32, v: f32) -> usize {
         let (u, v) = (u * self.width as f32, v * self.height as f32);
         let (u, v) = (
-            ((u as usize) % self.width) as f32,
-            ((v as usize) % self.height) as f32,
+            Wrap::Repeat
+                .apply(u.floor() as i32, self.width)
+                .unwrap_or(0) as f32,
+            Wrap::Repeat
+                .apply(v.floor() as i32, self.height)
+                .unwrap_or(0) as f32,
         );
         from_coords_index(glam::vec2(u, v), self.width)
     }
 
     pub fn sample_at_uv(&self, u: f32, v: f32) -> u32 {
         let index = self.uv_to_index(u, v);
-        if index < self.data.len() {
-            self.data[index]
+        if index < self.levels[0].texels.len() {
+            from_rgb_u32(self.levels[0].get(index).xyz())
         } else {
             from_u8_rgb(255, 0, 255)
         }
     }
 
     pub fn sample_at_uv_rgb(&self, u: f32, v: f32) -> Vec3 {
-        let color = from_u32_u8(self.sample_at_uv(u, v));
-        Vec3::new(
-            (color.0 as f32) / 255.0,
-            (color.1 as f32) / 255.0,
-            (color.2 as f32) / 255.0,
-        )
+        let index = self.uv_to_index(u, v);
+        if index < self.levels[0].texels.len() {
+            self.levels[0].get(index).xyz()
+        } else {
+            glam::vec3(1.0, 0.0, 1.0)
+        }
+    }
+
+    // Filtered lookup, ddx and ddy are the screen space derivatives of the uv
+    // coordinates and give the footprint of the pixel on the texture.
+    pub fn sample(&self, sampler: &Sampler, uv: Vec2, ddx: Vec2, ddy: Vec2) -> Vec4 {
+        let size = glam::vec2(self.width as f32, self.height as f32);
+        let (ddx, ddy) = (ddx * size, ddy * size);
+        let (length_x, length_y) = (ddx.length(), ddy.length());
+        let (major, minor, major_axis) = if length_x >= length_y {
+            (length_x, length_y, ddx)
+        } else {
+            (length_y, length_x, ddy)
+        };
+
+        // with anisotropy the level is picked for the short axis of the footprint and
+        // several samples are spread along the long one
+        let max_anisotropy = sampler.max_anisotropy.max(1) as f32;
+        let samples = (major / minor.max(f32::EPSILON))
+            .min(max_anisotropy)
+            .ceil()
+            .max(1.0);
+        let lod = (major / samples).max(f32::EPSILON).log2();
+
+        if samples <= 1.0 {
+            return self.sample_level(sampler, uv, lod);
+        }
+        let step = major_axis / size / samples;
+        let count = samples as u32;
+        let sum = (0..count).fold(Vec4::ZERO, |sum, i| {
+            let offset = (i as f32 + 0.5) - samples * 0.5;
+            sum + self.sample_level(sampler, uv + step * offset, lod)
+        });
+        sum / samples
+    }
+
+    fn sample_level(&self, sampler: &Sampler, uv: Vec2, lod: f32) -> Vec4 {
+        if lod <= 0.0 {
+            return self.levels[0].sample(uv, sampler.mag_filter, sampler);
+        }
+        let last = (self.levels.len() - 1) as f32;
+        match sampler.mipmap_filter {
+            None => self.levels[0].sample(uv, sampler.min_filter, sampler),
+            Some(Filter::Nearest) => {
+                let level = lod.round().min(last) as usize;
+                self.levels[level].sample(uv, sampler.min_filter, sampler)
+            }
+            Some(Filter::Linear) => {
+                let lod = lod.min(last);
+                let level = lod.floor() as usize;
+                let fine = self.levels[level].sample(uv, sampler.min_filter, sampler);
+                if level + 1 >= self.levels.len() {
+                    return fine;
+                }
+                let coarse = self.levels[level + 1].sample(uv, sampler.min_filter, sampler);
+                fine.lerp(coarse, lod - level as f32)
+            }
+        }
+    }
+}
+
+// source texels under texel i of a row that shrinks from source_size to size, with the
+// fraction of the texel they cover. The weights sum up to one.
+fn footprint(i: usize, size: usize, source_size: usize) -> Vec<(usize, f32)> {
+    let scale = source_size as f32 / size as f32;
+    let (start, end) = (i as f32 * scale, (i + 1) as f32 * scale);
+    (start.floor() as usize..(end.ceil() as usize).min(source_size))
+        .map(|texel| {
+            let covered = (end.min(texel as f32 + 1.0) - start.max(texel as f32)).max(0.0);
+            (texel, covered / scale)
+        })
+        .collect()
+}
+
+// the base level followed by its downsampled levels, down to 1x1
+fn mip_chain(base: MipLevel) -> Vec<MipLevel> {
+    let mut levels = vec![base];
+    while let Some(last) = levels.last().filter(|l| l.width > 1 || l.height > 1) {
+        levels.push(last.downsample());
     }
+    levels
 }
@@ -0,0 +1,211 @@
+use crate::blend::OitTexel;
+use crate::utils::geometry::BoundingBox2D;
+use glam::Vec4;
+use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use std::sync::{Arc, Mutex, PoisonError};
+
+// Pixel rectangle of a tile, the upper bounds are exclusive
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TileRect {
+    pub x0: usize,
+    pub y0: usize,
+    pub x1: usize,
+    pub y1: usize,
+}
+
+impl TileRect {
+    pub fn width(&self) -> usize {
+        self.x1 - self.x0
+    }
+
+    pub fn height(&self) -> usize {
+        self.y1 - self.y0
+    }
+
+    // inclusive pixel range of the bounding box inside of this tile
+    pub fn intersect(&self, bb: &BoundingBox2D) -> Option<(usize, usize, usize, usize)> {
+        let left = (bb.left as usize).max(self.x0);
+        let right = (bb.right as usize).min(self.x1 - 1);
+        let top = (bb.top as usize).max(self.y0);
+        let bottom = (bb.bottom as usize).min(self.y1 - 1);
+        if left > right || top > bottom {
+            None
+        } else {
+            Some((left, right, top, bottom))
+        }
+    }
+}
+
+// Mutable view into the screen buffers covered by one tile. Tiles never overlap,
+// so they can be rasterized on different threads at the same time.
+pub struct Tile<'a> {
+    // position in the tile grid
+    pub index: usize,
+    pub rect: TileRect,
+    // empty for depth-only screens, like the stencil rows
+    pub hdr_rows: Vec<&'a mut [Vec4]>,
+    pub depth_rows: Vec<&'a mut [f32]>,
+    pub stencil_rows: Vec<&'a mut [u8]>,
+    // empty when the screen has no transparency buffer
+    pub oit_rows: Vec<&'a mut [OitTexel]>,
+}
+
+// Regular grid of tiles covering the screen, the last row and column may be smaller
+#[derive(Debug, Copy, Clone)]
+pub struct TileGrid {
+    pub width: usize,
+    pub height: usize,
+    pub tile_size: usize,
+    pub columns: usize,
+    pub rows: usize,
+}
+
+impl TileGrid {
+    pub fn new(width: usize, height: usize, tile_size: usize) -> Self {
+        let tile_size = tile_size.max(1);
+        Self {
+            width,
+            height,
+            tile_size,
+            columns: width.div_ceil(tile_size),
+            rows: height.div_ceil(tile_size),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.columns * self.rows
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn rect(&self, index: usize) -> TileRect {
+        let (column, row) = (index % self.columns, index / self.columns);
+        let (x0, y0) = (column * self.tile_size, row * self.tile_size);
+        TileRect {
+            x0,
+            y0,
+            x1: (x0 + self.tile_size).min(self.width),
+            y1: (y0 + self.tile_size).min(self.height),
+        }
+    }
+
+    // indices of all tiles touched by the bounding box
+    pub fn tiles_overlapping(&self, bb: &BoundingBox2D) -> impl Iterator<Item = usize> + '_ {
+        let (left, right) = (bb.left as usize, bb.right as usize);
+        let (top, bottom) = (bb.top as usize, bb.bottom as usize);
+        let columns =
+            left / self.tile_size..=(right / self.tile_size).min(self.columns.saturating_sub(1));
+        let rows =
+            top / self.tile_size..=(bottom / self.tile_size).min(self.rows.saturating_sub(1));
+        // an empty grid has no last tile to clamp to, nothing overlaps it
+        rows.filter(|_| !self.is_empty()).flat_map(move |row| {
+            columns
+                .clone()
+                .map(move |column| row * self.columns + column)
+        })
+    }
+
+    // Splits the screen buffers into views of the tiles marked in `used`, without copying.
+    // Rows of tiles that aren't used are skipped. All buffers but the depth may be empty,
+    // the tiles get no rows of them then.
+    pub fn split<'a>(
+        &self,
+        used: &[bool],
+        hdr: &'a mut [Vec4],
+        depth: &'a mut [f32],
+        stencil: &'a mut [u8],
+        oit: &'a mut [OitTexel],
+    ) -> Vec<Tile<'a>> {
+        let hdr_rows = self.split_rows(hdr, used);
+        let depth_rows = self.split_rows(depth, used);
+        let stencil_rows = self.split_rows(stencil, used);
+        let oit_rows = self.split_rows(oit, used);
+        hdr_rows
+            .into_iter()
+            .zip(depth_rows)
+            .zip(stencil_rows)
+            .zip(oit_rows)
+            .enumerate()
+            .filter(|(index, _)| used[*index])
+            .map(
+                |(index, (((hdr_rows, depth_rows), stencil_rows), oit_rows))| Tile {
+                    index,
+                    rect: self.rect(index),
+                    hdr_rows,
+                    depth_rows,
+                    stencil_rows,
+                    oit_rows,
+                },
+            )
+            .collect()
+    }
+
+    // the rows of the used tiles in one buffer of the screen, empty for the others
+    fn split_rows<'a, T>(&self, buffer: &'a mut [T], used: &[bool]) -> Vec<Vec<&'a mut [T]>> {
+        let mut tiles: Vec<Vec<&'a mut [T]>> = (0..self.len()).map(|_| Vec::new()).collect();
+        let tile_rows = buffer.chunks_mut(self.width * self.tile_size);
+        for (tile_row, rows) in tile_rows.enumerate() {
+            let first_tile = tile_row * self.columns;
+            if !used[first_tile..first_tile + self.columns].contains(&true) {
+                continue;
+            }
+            for mut row in rows.chunks_mut(self.width) {
+                for column in 0..self.columns {
+                    let width = self.rect(first_tile + column).width();
+                    let (left, right) = row.split_at_mut(width);
+                    if used[first_tile + column] {
+                        tiles[first_tile + column].push(left);
+                    }
+                    row = right;
+                }
+            }
+        }
+        tiles
+    }
+}
+
+// Threads rasterizing the tiles of a screen. They are started by the first draw that
+// runs in parallel and kept for the following ones, until the thread count changes.
+// Clones share the threads, e.g. the depth targets of a screen.
+#[derive(Default, Clone)]
+pub struct Workers {
+    pool: Arc<Mutex<Option<Arc<ThreadPool>>>>,
+}
+
+impl Workers {
+    // Runs `work` for every tile on up to `threads` threads, on the calling thread when
+    // it is 1. Idle threads steal tiles from busy ones, so expensive tiles don't stall
+    // the others.
+    pub fn for_each_tile<'a, F>(&mut self, tiles: Vec<Tile<'a>>, threads: usize, work: F)
+    where
+        F: Fn(&mut Tile<'a>) + Sync,
+    {
+        if threads > 1 && tiles.len() > 1 {
+            // the lock only guards fetching the threads, other clones may draw meanwhile
+            if let Some(pool) = self.pool(threads) {
+                pool.install(|| tiles.into_par_iter().for_each(|mut tile| work(&mut tile)));
+                return;
+            }
+        }
+        for mut tile in tiles {
+            work(&mut tile);
+        }
+    }
+
+    // None when the threads can't be started, the tiles are rasterized serially then
+    fn pool(&self, threads: usize) -> Option<Arc<ThreadPool>> {
+        let mut pool = self.pool.lock().unwrap_or_else(PoisonError::into_inner);
+        if pool.as_ref().map(|pool| pool.current_num_threads()) != Some(threads) {
+            *pool = ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .thread_name(|index| format!("rusterizer-tile-{}", index))
+                .build()
+                .ok()
+                .map(Arc::new);
+        }
+        pool.clone()
+    }
+}
@@ -27,7 +27,7 @@ impl Transform {
 
     pub fn create(translation: Vec3, rotation: Quat, scale: Vec3) -> Self {
         Self {
-            translation: translation,
+            translation,
             rotation: rotation.normalize(),
             scale,
         }
@@ -63,6 +63,16 @@ impl Transform {
         }
     }
 
+    // glTF matrices are decomposed, which is exact for anything but shear
+    pub fn from_gltf(transform: gltf::scene::Transform) -> Self {
+        let (translation, rotation, scale) = transform.decomposed();
+        Self::create(
+            Vec3::from(translation),
+            Quat::from_array(rotation),
+            Vec3::from(scale),
+        )
+    }
+
     pub fn right(&self) -> Vec3 {
         self.rotation * Vec3::X
     }
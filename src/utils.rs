@@ -1,6 +1,52 @@
+// Implements the arithmetic needed by `Interpolate` field by field for a struct
+// whose fields are all interpolatable, e.g. `impl_interpolate!(MyVaryings { position, uv })`
+#[macro_export]
+macro_rules! impl_interpolate {
+    ($type:ident { $($field:ident),+ $(,)? }) => {
+        impl std::ops::Add for $type {
+            type Output = Self;
+
+            fn add(self, rhs: Self) -> Self {
+                Self {
+                    $($field: self.$field + rhs.$field),+
+                }
+            }
+        }
+
+        impl std::ops::Sub for $type {
+            type Output = Self;
+
+            fn sub(self, rhs: Self) -> Self {
+                Self {
+                    $($field: self.$field - rhs.$field),+
+                }
+            }
+        }
+
+        impl std::ops::Mul<f32> for $type {
+            type Output = Self;
+
+            fn mul(self, rhs: f32) -> Self {
+                Self {
+                    $($field: self.$field * rhs),+
+                }
+            }
+        }
+
+        impl std::ops::MulAssign<f32> for $type {
+            fn mul_assign(&mut self, rhs: f32) {
+                *self = *self * rhs;
+            }
+        }
+    };
+}
+
 pub mod geometry {
+    use crate::error::{Error, Result};
     use glam::{Mat4, UVec3, Vec2, Vec3, Vec4, Vec4Swizzles};
-    use std::ops::{Add, AddAssign, Mul, MulAssign, Sub};
+    use std::collections::HashMap;
+    use std::f32::consts::PI;
+    use std::ops::{Add, AddAssign, Mul, Range, Sub};
 
     // Vertex
     #[derive(Debug, Copy, Clone)]
@@ -9,6 +55,9 @@ pub mod geometry {
         pub color: Vec3,
         pub normal: Vec3,
         pub uv: Vec2,
+        // direction of increasing u with the handedness of the bitangent in w,
+        // zero when the mesh has no tangents
+        pub tangent: Vec4,
     }
 
     impl Vertex {
@@ -18,92 +67,55 @@ pub mod geometry {
                 color,
                 normal,
                 uv,
+                tangent: Vec4::ZERO,
             }
         }
     }
 
-    impl Add for Vertex {
-        type Output = Self;
-
-        fn add(self, rhs: Self) -> Self {
-            let position = self.position + rhs.position;
-            let color = self.color + rhs.color;
-            let normal = self.normal + rhs.normal;
-            let uv = self.uv + rhs.uv;
-            Self {
-                position,
-                color,
-                normal,
-                uv,
-            }
+    crate::impl_interpolate!(Vertex {
+        position,
+        color,
+        normal,
+        uv,
+        tangent
+    });
+
+    impl Varyings for Vertex {
+        fn position(&self) -> Vec4 {
+            self.position
         }
     }
 
-    impl Sub for Vertex {
-        type Output = Self;
-
-        fn sub(self, rhs: Self) -> Self {
-            let position = self.position - rhs.position;
-            let color = self.color - rhs.color;
-            let normal = self.normal - rhs.normal;
-            let uv = self.uv - rhs.uv;
-            Self {
-                position,
-                color,
-                normal,
-                uv,
-            }
-        }
+    // Anything that can be linearly interpolated, i.e. blended as a * (1 - t) + b * t.
+    // Implemented for every type with the arithmetic operators, like f32 and the glam vectors.
+    pub trait Interpolate:
+        Copy + Add<Output = Self> + Sub<Output = Self> + Mul<f32, Output = Self>
+    {
     }
 
-    impl Mul<f32> for Vertex {
-        type Output = Self;
+    impl<T> Interpolate for T where T: Copy + Add<Output = T> + Sub<Output = T> + Mul<f32, Output = T> {}
 
-        fn mul(self, rhs: f32) -> Self {
-            let position = self.position * rhs;
-            let color = self.color * rhs;
-            let normal = self.normal * rhs;
-            let uv = self.uv * rhs;
-            Self {
-                position,
-                color,
-                normal,
-                uv,
-            }
-        }
-    }
-
-    impl MulAssign<f32> for Vertex {
-        fn mul_assign(&mut self, rhs: f32) {
-            self.position *= rhs;
-            self.color *= rhs;
-            self.normal *= rhs;
-            self.uv *= rhs;
-        }
+    // Per-vertex payload carried from the vertex shader through clipping to the fragment shader.
+    // Every attribute is interpolated, the position has to be in clip space.
+    pub trait Varyings: Interpolate + Send + Sync {
+        fn position(&self) -> Vec4;
     }
 
     // Triangle
     #[derive(Debug, Copy, Clone)]
-    pub struct Triangle {
-        pub v0: Vertex,
-        pub v1: Vertex,
-        pub v2: Vertex,
+    pub struct Triangle<V = Vertex> {
+        pub v0: V,
+        pub v1: V,
+        pub v2: V,
     }
 
-    pub enum VerticesOrder {
-        ABC,
-        ACB,
-        BAC,
-        BCA,
-        CAB,
-        CBA,
-    }
-
-    impl Triangle {
-        pub fn create(v0: Vertex, v1: Vertex, v2: Vertex) -> Self {
+    impl<V> Triangle<V> {
+        pub fn create(v0: V, v1: V, v2: V) -> Self {
             Self { v0, v1, v2 }
         }
+    }
 
+    impl Triangle {
         pub fn transform(&self, matrix: &Mat4) -> Self {
             let mut result = *self;
             result.v0.position = *matrix * self.v0.position.xyz().extend(1.0);
@@ -111,148 +123,456 @@ pub mod geometry {
             result.v2.position = *matrix * self.v2.position.xyz().extend(1.0);
             result
         }
+    }
 
-        pub fn reorder(&self, order: VerticesOrder) -> Self {
-            match order {
-                VerticesOrder::ABC => *self,
-                VerticesOrder::ACB => Self::create(self.v0, self.v2, self.v1),
-                VerticesOrder::BAC => Self::create(self.v1, self.v0, self.v2),
-                VerticesOrder::BCA => Self::create(self.v1, self.v2, self.v0),
-                VerticesOrder::CAB => Self::create(self.v2, self.v0, self.v1),
-                VerticesOrder::CBA => Self::create(self.v2, self.v1, self.v0),
-            }
-        }
+    // Range of triangles of a mesh drawn with the same material, e.g. a glTF primitive
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct Submesh {
+        pub triangles: Range<usize>,
+        // index into the materials of the scene, None for the default material
+        pub material: Option<usize>,
     }
 
-    // Mesh
-    pub struct Mesh {
+    // One slice per vertex attribute, empty slices fall back to the defaults of the attribute
+    #[derive(Debug, Default, Copy, Clone)]
+    pub struct VertexBuffers<'a> {
+        pub positions: &'a [Vec3],
+        pub colors: &'a [Vec3],
+        pub normals: &'a [Vec3],
+        pub uvs: &'a [Vec2],
+        pub tangents: &'a [Vec4],
+    }
+
+    // Mesh, the vertex type is the input of the vertex shader
+    pub struct Mesh<V = Vertex> {
         pub triangles: Vec<UVec3>,
-        pub vertices: Vec<Vertex>,
+        pub vertices: Vec<V>,
+        // every triangle belongs to exactly one submesh
+        pub submeshes: Vec<Submesh>,
     }
 
-    impl Mesh {
+    impl<V> Mesh<V> {
         pub fn create() -> Self {
             Self {
                 triangles: Vec::new(),
                 vertices: Vec::new(),
+                submeshes: Vec::new(),
             }
         }
 
+        // adds the next triangles as a submesh, the indices are already offset
+        fn push_submesh(
+            &mut self,
+            triangles: impl IntoIterator<Item = UVec3>,
+            material: Option<usize>,
+        ) {
+            let start = self.triangles.len();
+            self.triangles.extend(triangles);
+            self.submeshes.push(Submesh {
+                triangles: start..self.triangles.len(),
+                material,
+            });
+        }
+
         pub fn get_triangles(&self) -> &Vec<UVec3> {
             &self.triangles
         }
 
-        pub fn get_vertices(&self) -> &Vec<Vertex> {
+        pub fn get_vertices(&self) -> &Vec<V> {
             &self.vertices
         }
 
-        pub fn get_vertices_from_triangle(&self, triangle: UVec3) -> [&Vertex; 3] {
+        pub fn get_vertices_from_triangle(&self, triangle: UVec3) -> [&V; 3] {
             [
                 &self.vertices[triangle.x as usize],
                 &self.vertices[triangle.y as usize],
                 &self.vertices[triangle.z as usize],
             ]
         }
+    }
 
-        pub fn from_vertices(triangles: &[UVec3], vertices: &[Vertex]) -> Self {
+    impl<V: Copy> Mesh<V> {
+        pub fn from_vertices(triangles: &[UVec3], vertices: &[V]) -> Self {
             let mut mesh = Mesh::create();
             mesh.add_section_from_vertices(triangles, vertices);
             mesh
         }
 
-        pub fn add_section_from_vertices(&mut self, triangles: &[UVec3], vertices: &[Vertex]) {
+        // the indices of a section are relative to its own vertices
+        pub fn add_section_from_vertices(&mut self, triangles: &[UVec3], vertices: &[V]) {
             let offset = self.vertices.len() as u32;
-            let triangles: Vec<UVec3> = triangles.iter().map(|index| *index + offset).collect();
-            self.triangles.extend_from_slice(&triangles);
+            self.push_submesh(triangles.iter().map(|index| *index + offset), None);
             self.vertices.extend_from_slice(vertices);
         }
+    }
+
+    impl Mesh {
+        // Square around the center spanned by `right` and `up`, which are half its sides.
+        // Counter clockwise when seen from cross(right, up), which is also the normal. The
+        // uvs cover the texture with v growing downwards.
+        pub fn quad(center: Vec3, right: Vec3, up: Vec3) -> Self {
+            let normal = right.cross(up).normalize();
+            Self::from_vertices(
+                &[UVec3::new(0, 1, 2), UVec3::new(0, 2, 3)],
+                &[(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)].map(|(x, y)| {
+                    Vertex::create(
+                        (center + right * x + up * y).extend(1.0),
+                        Vec3::ONE,
+                        normal,
+                        glam::vec2(x * 0.5 + 0.5, 0.5 - y * 0.5),
+                    )
+                }),
+            )
+        }
+
+        // sphere around the origin, u goes around the y axis and v from the top to the bottom
+        pub fn uv_sphere(radius: f32, segments: u32, rings: u32) -> Self {
+            let mut vertices = Vec::new();
+            for ring in 0..=rings {
+                let v = ring as f32 / rings as f32;
+                for segment in 0..=segments {
+                    let u = segment as f32 / segments as f32;
+                    let (theta, phi) = (u * 2.0 * PI, v * PI);
+                    let normal =
+                        glam::vec3(phi.sin() * theta.sin(), phi.cos(), phi.sin() * theta.cos());
+                    vertices.push(Vertex::create(
+                        (normal * radius).extend(1.0),
+                        Vec3::ONE,
+                        normal,
+                        glam::vec2(u, v),
+                    ));
+                }
+            }
+
+            let mut triangles = Vec::new();
+            let stride = segments + 1;
+            for ring in 0..rings {
+                for segment in 0..segments {
+                    let a = ring * stride + segment;
+                    let b = a + stride;
+                    triangles.push(UVec3::new(a, b, a + 1));
+                    triangles.push(UVec3::new(a + 1, b, b + 1));
+                }
+            }
+            let mut sphere = Self::from_vertices(&triangles, &vertices);
+            sphere.generate_tangents();
+            sphere
+        }
 
         pub fn add_section_from_buffers(
             &mut self,
             triangles: &[UVec3],
-            positions: &[Vec3],
-            colors: &[Vec3],
-            normals: &[Vec3],
-            uvs: &[Vec2],
+            buffers: &VertexBuffers,
+            material: Option<usize>,
         ) {
-            self.triangles.extend_from_slice(triangles);
-
-            let has_uvs = !uvs.is_empty();
-            let has_colors = !colors.is_empty();
-            let has_normals = !normals.is_empty();
-
-            for i in 0..positions.len() {
-                let vertex = Vertex::create(
-                    positions[i].extend(1.0),
-                    if has_colors { colors[i] } else { Vec3::ONE },
-                    if has_normals { normals[i] } else { Vec3::ONE },
-                    if has_uvs { uvs[i] } else { Vec2::ZERO },
+            let offset = self.vertices.len() as u32;
+            self.push_submesh(triangles.iter().map(|index| *index + offset), material);
+
+            let has_uvs = !buffers.uvs.is_empty();
+            let has_colors = !buffers.colors.is_empty();
+            let has_normals = !buffers.normals.is_empty();
+            let has_tangents = !buffers.tangents.is_empty();
+
+            for i in 0..buffers.positions.len() {
+                let mut vertex = Vertex::create(
+                    buffers.positions[i].extend(1.0),
+                    if has_colors {
+                        buffers.colors[i]
+                    } else {
+                        Vec3::ONE
+                    },
+                    if has_normals {
+                        buffers.normals[i]
+                    } else {
+                        Vec3::ONE
+                    },
+                    if has_uvs { buffers.uvs[i] } else { Vec2::ZERO },
                 );
+                if has_tangents {
+                    vertex.tangent = buffers.tangents[i];
+                }
                 self.vertices.push(vertex)
             }
         }
 
-        pub fn from_gltf_mesh(mesh: &gltf::Mesh, buffers: &[gltf::buffer::Data]) -> Mesh {
-            let mut positions: Vec<Vec3> = Vec::new();
-            let mut tex_coords: Vec<Vec2> = Vec::new();
-            let mut normals: Vec<Vec3> = Vec::new();
-            let mut indices = vec![];
+        // center of the bounding box of the vertices used by the submesh, in model space
+        pub fn submesh_center(&self, submesh: &Submesh) -> Vec3 {
+            let triangles = &self.triangles[submesh.triangles.clone()];
+            let mut positions = triangles
+                .iter()
+                .flat_map(|indices| indices.to_array())
+                .map(|index| self.vertices[index as usize].position.xyz());
+            let Some(first) = positions.next() else {
+                return Vec3::ZERO;
+            };
+            let (min, max) =
+                positions.fold((first, first), |(min, max), p| (min.min(p), max.max(p)));
+            (min + max) * 0.5
+        }
 
+        // replaces the tangents of all vertices with MikkTSpace tangents, false when
+        // they couldn't be generated. Vertices are split where MikkTSpace gives their
+        // triangles different tangents, the submeshes keep their triangles.
+        pub fn generate_tangents(&mut self) -> bool {
+            let positions: Vec<Vec3> = self.vertices.iter().map(|v| v.position.xyz()).collect();
+            let normals: Vec<Vec3> = self.vertices.iter().map(|v| v.normal).collect();
+            let uvs: Vec<Vec2> = self.vertices.iter().map(|v| v.uv).collect();
+            let Some(generated) = generate_tangents(&self.triangles, &positions, &normals, &uvs)
+            else {
+                return false;
+            };
+            self.vertices = generated
+                .sources
+                .iter()
+                .zip(generated.tangents)
+                .map(|(source, tangent)| Vertex {
+                    tangent,
+                    ..self.vertices[*source as usize]
+                })
+                .collect();
+            self.triangles = generated.triangles;
+            true
+        }
+
+        // every primitive becomes a submesh with the material of the primitive
+        pub fn from_gltf_mesh(mesh: &gltf::Mesh, buffers: &[gltf::buffer::Data]) -> Result<Mesh> {
             let mut result = Mesh::create();
             for primitive in mesh.primitives() {
-                let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
-                if let Some(indices_reader) = reader.read_indices() {
-                    indices_reader.into_u32().for_each(|i| indices.push(i));
+                let malformed = |message: String| Error::MalformedAccessor {
+                    mesh: mesh.index(),
+                    primitive: primitive.index(),
+                    message,
+                };
+                if primitive.mode() != gltf::mesh::Mode::Triangles {
+                    return Err(Error::unsupported(
+                        format!("primitive {} of mesh {}", primitive.index(), mesh.index()),
+                        format!(
+                            "{:?} primitives, only triangles are supported",
+                            primitive.mode()
+                        ),
+                    ));
                 }
-                if let Some(positions_reader) = reader.read_positions() {
-                    positions_reader.for_each(|p| positions.push(Vec3::new(p[0], p[1], p[2])));
+
+                let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+                let positions: Vec<Vec3> = reader
+                    .read_positions()
+                    .ok_or_else(|| {
+                        malformed("POSITION is missing or outside of its buffer".into())
+                    })?
+                    .map(Vec3::from)
+                    .collect();
+                let count = positions.len();
+
+                let normals: Vec<Vec3> = reader
+                    .read_normals()
+                    .map(|normals| normals.map(Vec3::from).collect())
+                    .unwrap_or_default();
+                if !normals.is_empty() && normals.len() != count {
+                    let message = format!("{} normals for {} positions", normals.len(), count);
+                    return Err(malformed(message));
                 }
-                if let Some(tex_coord_reader) = reader.read_tex_coords(0) {
-                    tex_coord_reader
-                        .into_f32()
-                        .for_each(|tc| tex_coords.push(Vec2::new(tc[0], tc[1])));
+                let tex_coords: Vec<Vec2> = reader
+                    .read_tex_coords(0)
+                    .map(|uvs| uvs.into_f32().map(Vec2::from).collect())
+                    .unwrap_or_default();
+                if !tex_coords.is_empty() && tex_coords.len() != count {
+                    let message = format!("{} uvs for {} positions", tex_coords.len(), count);
+                    return Err(malformed(message));
                 }
-                if let Some(normals_reader) = reader.read_normals() {
-                    normals_reader.for_each(|p| normals.push(Vec3::new(p[0], p[1], p[2])));
+                let tangents: Option<Vec<Vec4>> = reader
+                    .read_tangents()
+                    .map(|tangents| tangents.map(Vec4::from).collect());
+                if let Some(tangents) = tangents.as_ref().filter(|t| t.len() != count) {
+                    let message = format!("{} tangents for {} positions", tangents.len(), count);
+                    return Err(malformed(message));
                 }
-            }
 
-            let colors: Vec<Vec3> = positions.iter().map(|_| Vec3::ONE).collect();
-            println!("Num indices: {:?}", indices.len());
-            println!("tex_coords: {:?}", tex_coords.len());
-            println!("positions: {:?}", positions.len());
-            println!("normals: {:?}", normals.len());
+                // primitives without indices draw their vertices in order
+                let indices: Vec<u32> = match reader.read_indices() {
+                    Some(indices_reader) => indices_reader.into_u32().collect(),
+                    None => (0..count as u32).collect(),
+                };
+                if indices.len() % 3 != 0 {
+                    let message = format!("{} indices don't form triangles", indices.len());
+                    return Err(malformed(message));
+                }
+                if let Some(index) = indices.iter().find(|i| **i as usize >= count) {
+                    let message = format!("index {} is out of range for {} vertices", index, count);
+                    return Err(malformed(message));
+                }
 
-            let triangles: Vec<UVec3> = indices
-                .chunks_exact(3)
-                .map(|tri| UVec3::new(tri[0], tri[1], tri[2]))
-                .collect();
-            result.add_section_from_buffers(&triangles, &positions, &colors, &normals, &tex_coords);
+                let triangles: Vec<UVec3> = indices
+                    .chunks_exact(3)
+                    .map(|tri| UVec3::new(tri[0], tri[1], tri[2]))
+                    .collect();
+                // glTF asks for MikkTSpace tangents when the file has none
+                let generated = match tangents {
+                    Some(_) => None,
+                    None if normals.is_empty() || tex_coords.is_empty() => None,
+                    None => generate_tangents(&triangles, &positions, &normals, &tex_coords),
+                };
+                let material = primitive.material().index();
+                match generated {
+                    Some(generated) => {
+                        let buffers = VertexBuffers {
+                            positions: &generated.split(&positions),
+                            normals: &generated.split(&normals),
+                            uvs: &generated.split(&tex_coords),
+                            tangents: &generated.tangents,
+                            ..Default::default()
+                        };
+                        result.add_section_from_buffers(&generated.triangles, &buffers, material);
+                    }
+                    None => {
+                        let buffers = VertexBuffers {
+                            positions: &positions,
+                            normals: &normals,
+                            uvs: &tex_coords,
+                            tangents: tangents.as_deref().unwrap_or_default(),
+                            ..Default::default()
+                        };
+                        result.add_section_from_buffers(&triangles, &buffers, material);
+                    }
+                }
+            }
 
-            result
+            Ok(result)
         }
     }
 
-    impl Default for Mesh {
+    impl<V> Default for Mesh<V> {
         fn default() -> Self {
             Self::create()
         }
     }
 
-    impl Add for Mesh {
+    impl<V> Add for Mesh<V> {
         type Output = Self;
 
-        fn add(self, rhs: Self) -> Self {
-            let mut result = Self::from_vertices(self.get_triangles(), self.get_vertices());
-            result.add_section_from_vertices(rhs.get_triangles(), rhs.get_vertices());
-            result
+        fn add(mut self, rhs: Self) -> Self {
+            self += rhs;
+            self
         }
     }
 
-    impl AddAssign for Mesh {
+    // keeps the submeshes of both meshes
+    impl<V> AddAssign for Mesh<V> {
         fn add_assign(&mut self, rhs: Self) {
-            self.add_section_from_vertices(rhs.get_triangles(), rhs.get_vertices());
+            let offset = self.vertices.len() as u32;
+            let first = self.triangles.len();
+            self.triangles
+                .extend(rhs.triangles.iter().map(|index| *index + offset));
+            self.vertices.extend(rhs.vertices);
+            self.submeshes
+                .extend(rhs.submeshes.into_iter().map(|submesh| Submesh {
+                    triangles: submesh.triangles.start + first..submesh.triangles.end + first,
+                    ..submesh
+                }));
+        }
+    }
+
+    // The triangles de-indexed for MikkTSpace, every corner of a triangle is its own vertex
+    // and gets its own tangent. MikkTSpace welds the corners again where that doesn't
+    // change the tangent space, so reading through the indices gives it the same input.
+    struct TangentGeometry<'a> {
+        triangles: &'a [UVec3],
+        positions: &'a [Vec3],
+        normals: &'a [Vec3],
+        uvs: &'a [Vec2],
+        // one per corner, three per triangle
+        tangents: Vec<Vec4>,
+    }
+
+    impl TangentGeometry<'_> {
+        fn index(&self, face: usize, vert: usize) -> usize {
+            self.triangles[face][vert] as usize
+        }
+    }
+
+    impl bevy_mikktspace::Geometry for TangentGeometry<'_> {
+        fn num_faces(&self) -> usize {
+            self.triangles.len()
+        }
+
+        fn num_vertices_of_face(&self, _face: usize) -> usize {
+            3
+        }
+
+        fn position(&self, face: usize, vert: usize) -> [f32; 3] {
+            self.positions[self.index(face, vert)].to_array()
+        }
+
+        fn normal(&self, face: usize, vert: usize) -> [f32; 3] {
+            self.normals[self.index(face, vert)].to_array()
+        }
+
+        fn tex_coord(&self, face: usize, vert: usize) -> [f32; 2] {
+            self.uvs[self.index(face, vert)].to_array()
+        }
+
+        fn set_tangent_encoded(&mut self, tangent: [f32; 4], face: usize, vert: usize) {
+            self.tangents[face * 3 + vert] = Vec4::from(tangent);
+        }
+    }
+
+    // Indexed triangles with MikkTSpace tangents, a vertex whose triangles got different
+    // tangents, e.g. on a mirrored uv seam, is split into one vertex per tangent
+    pub struct GeneratedTangents {
+        pub triangles: Vec<UVec3>,
+        // the vertex of the input that each new vertex is a copy of
+        pub sources: Vec<u32>,
+        pub tangents: Vec<Vec4>,
+    }
+
+    impl GeneratedTangents {
+        // the values of an attribute of the input for the new vertices
+        pub fn split<T: Copy>(&self, values: &[T]) -> Vec<T> {
+            self.sources.iter().map(|i| values[*i as usize]).collect()
+        }
+    }
+
+    pub fn generate_tangents(
+        triangles: &[UVec3],
+        positions: &[Vec3],
+        normals: &[Vec3],
+        uvs: &[Vec2],
+    ) -> Option<GeneratedTangents> {
+        let mut geometry = TangentGeometry {
+            triangles,
+            positions,
+            normals,
+            uvs,
+            tangents: vec![Vec4::ZERO; triangles.len() * 3],
+        };
+        if !bevy_mikktspace::generate_tangents(&mut geometry) {
+            return None;
         }
+
+        // welds the corners again, the other attributes are those of the input vertex
+        let mut generated = GeneratedTangents {
+            triangles: Vec::with_capacity(triangles.len()),
+            sources: Vec::new(),
+            tangents: Vec::new(),
+        };
+        let mut welded = HashMap::new();
+        let mut corners = triangles
+            .iter()
+            .flat_map(|triangle| triangle.to_array())
+            .zip(geometry.tangents);
+        for _ in triangles {
+            let mut triangle = [0; 3];
+            for index in &mut triangle {
+                let (source, tangent) = corners.next().unwrap();
+                let key = (source, tangent.to_array().map(f32::to_bits));
+                *index = *welded.entry(key).or_insert_with(|| {
+                    generated.sources.push(source);
+                    generated.tangents.push(tangent);
+                    generated.sources.len() as u32 - 1
+                });
+            }
+            generated.triangles.push(UVec3::from_array(triangle));
+        }
+        Some(generated)
     }
 
     pub struct BoundingBox2D {
@@ -277,156 +597,165 @@ pub mod geometry {
     }
 }
 
-pub mod utils {
-    use crate::utils::geometry::Mesh;
-    use glam::{Mat4, Vec2, Vec3};
-    use std::path::Path;
+use crate::error::{Error, Result};
+use crate::utils::geometry::{Interpolate, Mesh};
+use glam::{Mat4, Vec2, Vec3};
+use std::path::Path;
 
-    pub fn from_u8_rgb(r: u8, g: u8, b: u8) -> u32 {
-        let (r, g, b) = (r as u32, g as u32, b as u32);
-        (r << 16) | (g << 8) | b
-    }
+pub fn from_u8_rgb(r: u8, g: u8, b: u8) -> u32 {
+    let (r, g, b) = (r as u32, g as u32, b as u32);
+    (r << 16) | (g << 8) | b
+}
 
-    pub fn from_u32_u8(color: u32) -> (u8, u8, u8) {
-        let r: u8 = (color >> 16) as u8;
-        let g: u8 = (color >> 8) as u8;
-        let b: u8 = color as u8;
-        (r, g, b)
-    }
+pub fn from_u32_u8(color: u32) -> (u8, u8, u8) {
+    let r: u8 = (color >> 16) as u8;
+    let g: u8 = (color >> 8) as u8;
+    let b: u8 = color as u8;
+    (r, g, b)
+}
 
-    pub fn from_rgb_u32(color: Vec3) -> u32 {
-        from_u8_rgb(
-            (color.x * 255.0) as u8,
-            (color.y * 255.0) as u8,
-            (color.z * 255.0) as u8,
-        )
-    }
+// quantizes a color in [0, 1], anything outside is clamped
+pub fn from_rgb_u32(color: Vec3) -> u32 {
+    let quantize = |c: f32| (c.clamp(0.0, 1.0) * 255.0).round() as u8;
+    from_u8_rgb(quantize(color.x), quantize(color.y), quantize(color.z))
+}
 
-    // conversion from indives to coordinate system
-    pub fn from_index_coords(index: usize, width: usize) -> (usize, usize) {
-        (index % width, index / width)
-    }
+// inverse of from_rgb_u32, up to the quantization
+pub fn from_u32_rgb(color: u32) -> Vec3 {
+    let (r, g, b) = from_u32_u8(color);
+    glam::vec3(r as f32, g as f32, b as f32) / 255.0
+}
 
-    // conversion from a coordinate system to indices
-    pub fn from_coords_index(pos: Vec2, width: usize) -> usize {
-        pos.x as usize + pos.y as usize * width
-    }
+// conversion from indives to coordinate system
+pub fn from_index_coords(index: usize, width: usize) -> (usize, usize) {
+    (index % width, index / width)
+}
+
+// conversion from a coordinate system to indices
+pub fn from_coords_index(pos: Vec2, width: usize) -> usize {
+    pos.x as usize + pos.y as usize * width
+}
 
-    pub fn edge_function(pos: Vec2, v0: Vec2, v1: Vec2) -> f32 {
-        let seg_a = v1 - v0;
-        let seg_b = pos - v0;
+pub fn edge_function(pos: Vec2, v0: Vec2, v1: Vec2) -> f32 {
+    let seg_a = v1 - v0;
+    let seg_b = pos - v0;
 
-        seg_a.x * seg_b.y - seg_a.y * seg_b.x
-    }
+    seg_a.x * seg_b.y - seg_a.y * seg_b.x
+}
 
-    pub fn barycentric_coordinates(
-        pos: Vec2,
-        v0: Vec2,
-        v1: Vec2,
-        v2: Vec2,
-        area: f32,
-    ) -> Option<Vec3> {
-        let area_res = 1.0 / area;
+pub fn barycentric_coordinates(pos: Vec2, v0: Vec2, v1: Vec2, v2: Vec2, area: f32) -> Option<Vec3> {
+    let area_res = 1.0 / area;
 
-        let ef0 = edge_function(pos, v1, v2) * area_res;
-        let ef1 = edge_function(pos, v2, v0) * area_res;
-        let ef2 = 1.0 - ef0 - ef1;
+    let ef0 = edge_function(pos, v1, v2) * area_res;
+    let ef1 = edge_function(pos, v2, v0) * area_res;
+    let ef2 = 1.0 - ef0 - ef1;
 
-        if ef0 >= 0.0 && ef1 >= 0.0 && ef2 >= 0.0 {
-            Some(glam::vec3(ef0, ef1, ef2))
-        } else {
-            None
-        }
+    if ef0 >= 0.0 && ef1 >= 0.0 && ef2 >= 0.0 {
+        Some(glam::vec3(ef0, ef1, ef2))
+    } else {
+        None
     }
+}
 
-    pub fn map_to_range<T>(v: T, a1: T, a2: T, b1: T, b2: T) -> T
-    where
-        T: std::ops::Sub<Output = T>
-            + std::ops::Div<Output = T>
-            + std::ops::Mul<Output = T>
-            + std::ops::Add<Output = T>
-            + Copy,
-    {
-        b1 + (v - a1) * (b2 - b1) / (a2 - a1)
+pub fn map_to_range<T>(v: T, a1: T, a2: T, b1: T, b2: T) -> T
+where
+    T: std::ops::Sub<Output = T>
+        + std::ops::Div<Output = T>
+        + std::ops::Mul<Output = T>
+        + std::ops::Add<Output = T>
+        + Copy,
+{
+    b1 + (v - a1) * (b2 - b1) / (a2 - a1)
+}
+
+// first mesh found in the scenes of the file, use `Scene::load` for all nodes
+pub fn load_gltf(path: &Path) -> Result<Mesh> {
+    let (document, buffers) = import_gltf(path)?;
+    for scene in document.scenes() {
+        for node in scene.nodes() {
+            if let Some(mesh) = node.mesh() {
+                return Mesh::from_gltf_mesh(&mesh, &buffers);
+            }
+        }
     }
 
-    pub fn load_gltf(path: &Path) -> Mesh {
-        // handle loading textures, cameras, meshes here
-        let (document, buffers, _images) = gltf::import(path).unwrap();
-
-        for scene in document.scenes() {
-            for node in scene.nodes() {
-                println!(
-                    "Node #{} has {} children, camera: {:?}, mesh: {:?}, transform: {:?}",
-                    node.index(),
-                    node.children().count(),
-                    node.camera(),
-                    node.mesh().is_some(),
-                    node.transform(),
-                );
-                println!(
-                    "Node #{} has transform: trans {:?}, rot {:?}, scale {:?},",
-                    node.index(),
-                    node.transform().decomposed().0,
-                    node.transform().decomposed().1,
-                    node.transform().decomposed().2,
-                );
-                if let Some(mesh) = node.mesh() {
-                    return Mesh::from_gltf_mesh(&mesh, &buffers);
+    Ok(Mesh::create())
+}
+
+// reads a .gltf or .glb file and its buffers, the images are left to the caller
+pub fn import_gltf(path: &Path) -> Result<(gltf::Document, Vec<gltf::buffer::Data>)> {
+    let name = path.display().to_string();
+    let bytes = std::fs::read(path).map_err(|e| Error::from_io(path, e))?;
+    let gltf::Gltf { document, blob } =
+        gltf::Gltf::from_slice(&bytes).map_err(|e| gltf_error(&name, e))?;
+
+    let base = path.parent().unwrap_or(Path::new("."));
+    let buffers = gltf::import_buffers(&document, Some(base), blob).map_err(|e| match e {
+        gltf::Error::Io(error) => {
+            // report the buffer that is missing rather than the document
+            let missing = document.buffers().find_map(|buffer| match buffer.source() {
+                gltf::buffer::Source::Uri(uri) if !uri.starts_with("data:") => {
+                    Some(base.join(uri)).filter(|path| !path.exists())
                 }
-            }
+                _ => None,
+            });
+            Error::from_io(missing.unwrap_or(path.to_path_buf()), error)
         }
+        e => gltf_error(&name, e),
+    })?;
 
-        Mesh::create()
-    }
+    Ok((document, buffers))
+}
 
-    pub fn lerp<T>(start: T, end: T, alpha: f32) -> T
-    where
-        T: std::ops::Sub<Output = T>
-            + std::ops::Mul<f32, Output = T>
-            + std::ops::Add<Output = T>
-            + Copy,
-    {
-        start + (end - start) * alpha
+fn gltf_error(name: &str, error: gltf::Error) -> Error {
+    match error {
+        gltf::Error::UnsupportedScheme
+        | gltf::Error::UnsupportedImageEncoding
+        | gltf::Error::UnsupportedImageFormat(_)
+        | gltf::Error::ExternalReferenceInSliceImport => Error::unsupported(name, error),
+        _ => Error::decode(name, error),
     }
+}
 
-    //https://github.com/graphitemaster/normals_revisited
-    pub fn minor(
-        src: &[f32; 16],
-        r0: usize,
-        r1: usize,
-        r2: usize,
-        c0: usize,
-        c1: usize,
-        c2: usize,
-    ) -> f32 {
-        src[4 * r0 + c0]
-            * (src[4 * r1 + c1] * src[4 * r2 + c2] - src[4 * r2 + c1] * src[4 * r1 + c2])
-            - src[4 * r0 + c1]
-                * (src[4 * r1 + c0] * src[4 * r2 + c2] - src[4 * r2 + c0] * src[4 * r1 + c2])
-            + src[4 * r0 + c2]
-                * (src[4 * r1 + c0] * src[4 * r2 + c1] - src[4 * r2 + c0] * src[4 * r1 + c1])
-    }
+pub fn lerp<T: Interpolate>(start: T, end: T, alpha: f32) -> T {
+    start + (end - start) * alpha
+}
 
-    pub fn cofactor(matrix: &Mat4) -> Mat4 {
-        let src: [f32; 16] = matrix.to_cols_array();
-        let mut dst: [f32; 16] = [0.0; 16];
-        dst[0] = minor(&src, 1, 2, 3, 1, 2, 3);
-        dst[1] = -minor(&src, 1, 2, 3, 0, 2, 3);
-        dst[2] = minor(&src, 1, 2, 3, 0, 1, 3);
-        dst[3] = -minor(&src, 1, 2, 3, 0, 1, 2);
-        dst[4] = -minor(&src, 0, 2, 3, 1, 2, 3);
-        dst[5] = minor(&src, 0, 2, 3, 0, 2, 3);
-        dst[6] = -minor(&src, 0, 2, 3, 0, 1, 3);
-        dst[7] = minor(&src, 0, 2, 3, 0, 1, 2);
-        dst[8] = minor(&src, 0, 1, 3, 1, 2, 3);
-        dst[9] = -minor(&src, 0, 1, 3, 0, 2, 3);
-        dst[10] = minor(&src, 0, 1, 3, 0, 1, 3);
-        dst[11] = -minor(&src, 0, 1, 3, 0, 1, 2);
-        dst[12] = -minor(&src, 0, 1, 2, 1, 2, 3);
-        dst[13] = minor(&src, 0, 1, 2, 0, 2, 3);
-        dst[14] = -minor(&src, 0, 1, 2, 0, 1, 3);
-        dst[15] = minor(&src, 0, 1, 2, 0, 1, 2);
-        Mat4::from_cols_array(&dst)
-    }
+//https://github.com/graphitemaster/normals_revisited
+pub fn minor(
+    src: &[f32; 16],
+    r0: usize,
+    r1: usize,
+    r2: usize,
+    c0: usize,
+    c1: usize,
+    c2: usize,
+) -> f32 {
+    src[4 * r0 + c0] * (src[4 * r1 + c1] * src[4 * r2 + c2] - src[4 * r2 + c1] * src[4 * r1 + c2])
+        - src[4 * r0 + c1]
+            * (src[4 * r1 + c0] * src[4 * r2 + c2] - src[4 * r2 + c0] * src[4 * r1 + c2])
+        + src[4 * r0 + c2]
+            * (src[4 * r1 + c0] * src[4 * r2 + c1] - src[4 * r2 + c0] * src[4 * r1 + c1])
+}
+
+pub fn cofactor(matrix: &Mat4) -> Mat4 {
+    let src: [f32; 16] = matrix.to_cols_array();
+    let mut dst: [f32; 16] = [0.0; 16];
+    dst[0] = minor(&src, 1, 2, 3, 1, 2, 3);
+    dst[1] = -minor(&src, 1, 2, 3, 0, 2, 3);
+    dst[2] = minor(&src, 1, 2, 3, 0, 1, 3);
+    dst[3] = -minor(&src, 1, 2, 3, 0, 1, 2);
+    dst[4] = -minor(&src, 0, 2, 3, 1, 2, 3);
+    dst[5] = minor(&src, 0, 2, 3, 0, 2, 3);
+    dst[6] = -minor(&src, 0, 2, 3, 0, 1, 3);
+    dst[7] = minor(&src, 0, 2, 3, 0, 1, 2);
+    dst[8] = minor(&src, 0, 1, 3, 1, 2, 3);
+    dst[9] = -minor(&src, 0, 1, 3, 0, 2, 3);
+    dst[10] = minor(&src, 0, 1, 3, 0, 1, 3);
+    dst[11] = -minor(&src, 0, 1, 3, 0, 1, 2);
+    dst[12] = -minor(&src, 0, 1, 2, 1, 2, 3);
+    dst[13] = minor(&src, 0, 1, 2, 0, 2, 3);
+    dst[14] = -minor(&src, 0, 1, 2, 0, 1, 3);
+    dst[15] = minor(&src, 0, 1, 2, 0, 1, 2);
+    Mat4::from_cols_array(&dst)
 }